@@ -0,0 +1,138 @@
+use std::str::from_utf8;
+use serde::{Deserialize, Serialize};
+use crate::animation::track::Track;
+use crate::input::recording::CameraStart;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+
+/// # Cutscene
+/// A camera path, such as a level's intro. The camera follows the `position` track and faces the
+/// points on the `look_at` track, or takes its angles from the `rotation` track if there's no
+/// `look_at`. With neither it keeps facing the way it was.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Cutscene {
+    pub position: Track,
+    #[serde(default)]
+    pub look_at: Option<Track>,
+    /// (pitch, yaw, roll) in radians as x, y and z.
+    #[serde(default)]
+    pub rotation: Option<Track>,
+}
+
+impl Cutscene {
+    /// Loads a cutscene from a JSON file under `assets/`.
+    pub fn load_asset(file: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let asset = Asset::get(file).ok_or(format!("Missing cutscene {}", file))?;
+
+        Ok(serde_json::from_str(from_utf8(&asset.data)?)?)
+    }
+
+    /// Seconds until every track has finished.
+    pub fn duration(&self) -> f32 {
+        [Some(&self.position), self.look_at.as_ref(), self.rotation.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(Track::duration)
+            .fold(0.0, f32::max)
+    }
+
+    /// Puts the camera where it is `time` seconds in.
+    pub fn apply(&self, camera: &mut Camera, time: f32) {
+        let position = self.position.sample(time);
+        camera.position = Point3D { x: position.x, y: position.y, z: position.z };
+
+        if let Some(look_at) = &self.look_at {
+            let target = look_at.sample(time);
+            camera.look_at(&Point3D { x: target.x, y: target.y, z: target.z });
+        } else if let Some(rotation) = &self.rotation {
+            let angles = rotation.sample(time);
+            camera.set_rotation(EulerAngles { pitch: angles.x, yaw: angles.y, roll: angles.z });
+        }
+    }
+}
+
+/// # CutscenePlayer
+/// Plays a cutscene on the camera, then puts the camera back how it found it so whatever comes
+/// next starts from where it expects.
+#[derive(Debug, Clone)]
+pub struct CutscenePlayer {
+    cutscene: Cutscene,
+    time: f32,
+    /// Where the camera was before the cutscene took it over.
+    camera_before: Option<CameraStart>,
+}
+
+impl CutscenePlayer {
+    pub fn new(cutscene: Cutscene) -> Self {
+        Self { cutscene, time: 0.0, camera_before: None }
+    }
+
+    /// # update
+    /// Moves the camera along by a frame. Returns false once the cutscene is over or has been
+    /// skipped, by which time the camera is back where it started.
+    pub fn update(&mut self, camera: &mut Camera, delta_time: f32, skip: bool) -> bool {
+        let camera_before = self.camera_before.get_or_insert_with(|| CameraStart::from_camera(camera));
+
+        if skip || self.time >= self.cutscene.duration() {
+            camera_before.apply(camera);
+            return false;
+        }
+
+        self.cutscene.apply(camera, self.time);
+        self.time += delta_time;
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_camera() -> Camera {
+        let mut camera = Camera::for_tests(Point3D { x: 25.0, y: 1.5, z: 0.0 }, 1000.0);
+        camera.rotation = EulerAngles { pitch: 0.0, yaw: 90.0_f32.to_radians(), roll: 0.0 };
+        camera.rotation_vector = Vector3D { x: -1.0, y: 0.0, z: 0.0 };
+
+        camera
+    }
+
+    #[test]
+    /// # test_intro_flies_then_hands_back
+    /// The level 1 intro moves the camera about and finishes with it back at the start.
+    fn test_intro_flies_then_hands_back() {
+        let cutscene = Cutscene::load_asset("cutscenes/level1_intro.json").unwrap();
+        assert!(cutscene.duration() > 1.0);
+
+        let mut camera = start_camera();
+        let mut player = CutscenePlayer::new(cutscene);
+        let mut frames = 0;
+
+        while player.update(&mut camera, 1.0 / 30.0, false) {
+            frames += 1;
+
+            if frames == 30 {
+                assert!(camera.position.y > 10.0, "flying over the level: {:?}", camera.position);
+            }
+        }
+
+        assert!(frames > 30);
+        assert_eq!(camera.position, start_camera().position);
+        assert_eq!(camera.rotation_vector, start_camera().rotation_vector);
+    }
+
+    #[test]
+    /// # test_skip_intro
+    fn test_skip_intro() {
+        let mut camera = start_camera();
+        let mut player = CutscenePlayer::new(Cutscene::load_asset("cutscenes/level1_intro.json").unwrap());
+
+        assert!(player.update(&mut camera, 1.0 / 30.0, false));
+        assert_ne!(camera.position, start_camera().position);
+
+        assert!(!player.update(&mut camera, 1.0 / 30.0, true));
+        assert_eq!(camera.position, start_camera().position);
+    }
+}
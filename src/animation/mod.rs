@@ -0,0 +1,2 @@
+pub mod cutscene;
+pub mod track;
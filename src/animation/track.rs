@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use crate::types::geometry::*;
+
+/// How a track gets from one keyframe to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// A straight line at a steady speed.
+    #[default]
+    Linear,
+    /// Holds each value until the next keyframe, then jumps.
+    Step,
+    /// A smooth curve through every keyframe, shaped by the keyframes either side.
+    CatmullRom,
+}
+
+/// A value a track passes through at a time in seconds.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Keyframe {
+    pub time: f32,
+    pub value: Vector3D,
+}
+
+/// # Track
+/// Keyframes for one `Vector3D` over time, such as a position or a rotation. Keyframes are in time
+/// order and there's at least one, which the JSON is checked for when it's loaded.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(try_from = "JsonTrack", into = "JsonTrack")]
+pub struct Track {
+    interpolation: Interpolation,
+    keyframes: Vec<Keyframe>,
+    /// Starts again from the first keyframe once it reaches the last, rather than stopping.
+    looping: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct JsonTrack {
+    #[serde(default)]
+    interpolation: Interpolation,
+    keyframes: Vec<Keyframe>,
+    #[serde(default)]
+    looping: bool,
+}
+
+impl TryFrom<JsonTrack> for Track {
+    type Error = String;
+
+    fn try_from(track: JsonTrack) -> Result<Self, Self::Error> {
+        Self::new(track.interpolation, track.keyframes, track.looping)
+    }
+}
+
+impl From<Track> for JsonTrack {
+    fn from(track: Track) -> Self {
+        Self { interpolation: track.interpolation, keyframes: track.keyframes, looping: track.looping }
+    }
+}
+
+impl Track {
+    pub fn new(interpolation: Interpolation, keyframes: Vec<Keyframe>, looping: bool) -> Result<Self, String> {
+        if keyframes.is_empty() {
+            return Err("A track needs at least one keyframe".to_string());
+        }
+
+        if keyframes.windows(2).any(|pair| pair[1].time < pair[0].time) {
+            return Err("A track's keyframes have to be in time order".to_string());
+        }
+
+        Ok(Self { interpolation, keyframes, looping })
+    }
+
+    /// Seconds from the first keyframe to the last.
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().unwrap().time - self.keyframes[0].time
+    }
+
+    /// # sample
+    /// The value at `time` seconds. Before the first keyframe it's the first value and after the
+    /// last it's the last value, unless the track loops.
+    pub fn sample(&self, time: f32) -> Vector3D {
+        let keys = &self.keyframes;
+        let (first, last) = (&keys[0], &keys[keys.len() - 1]);
+
+        let time = if self.looping && self.duration() > 0.0 {
+            first.time + (time - first.time).rem_euclid(self.duration())
+        } else {
+            time
+        };
+
+        if time <= first.time {
+            return first.value.clone();
+        }
+
+        if time >= last.time {
+            return last.value.clone();
+        }
+
+        // The keyframes either side of `time`, the first of which is at or before it
+        let index = keys.partition_point(|key| key.time <= time) - 1;
+        let (from, to) = (&keys[index], &keys[index + 1]);
+        let t = (time - from.time) / (to.time - from.time);
+
+        match self.interpolation {
+            Interpolation::Step => from.value.clone(),
+            Interpolation::Linear => lerp(&from.value, &to.value, t),
+            Interpolation::CatmullRom => {
+                // The ends have no neighbour beyond them, so they stand in for it
+                let before = &keys[index.saturating_sub(1)].value;
+                let after = &keys[(index + 2).min(keys.len() - 1)].value;
+
+                catmull_rom(before, &from.value, &to.value, after, t)
+            }
+        }
+    }
+}
+
+/// # TransformAnimation
+/// Position and rotation tracks for something that moves. Positions are offsets from where it
+/// would otherwise be and rotations are (pitch, yaw, roll) in radians as x, y and z, added to its
+/// own. Either can be left out.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TransformAnimation {
+    #[serde(default)]
+    pub position: Option<Track>,
+    #[serde(default)]
+    pub rotation: Option<Track>,
+}
+
+impl TransformAnimation {
+    /// The offset and rotation `time` seconds in.
+    pub fn sample(&self, time: f32) -> (Vector3D, EulerAngles) {
+        let zero = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+
+        let offset = self.position.as_ref().map_or(zero.clone(), |track| track.sample(time));
+        let angles = self.rotation.as_ref().map_or(zero, |track| track.sample(time));
+
+        (offset, EulerAngles { pitch: angles.x, yaw: angles.y, roll: angles.z })
+    }
+}
+
+fn lerp(a: &Vector3D, b: &Vector3D, t: f32) -> Vector3D {
+    Vector3D {
+        x: a.x + (b.x - a.x) * t,
+        y: a.y + (b.y - a.y) * t,
+        z: a.z + (b.z - a.z) * t,
+    }
+}
+
+/// The point `t` of the way from `p1` to `p2` on a Catmull-Rom spline, which passes through every
+/// keyframe with its direction at each set by the keyframes either side.
+fn catmull_rom(p0: &Vector3D, p1: &Vector3D, p2: &Vector3D, p3: &Vector3D, t: f32) -> Vector3D {
+    let (t2, t3) = (t * t, t * t * t);
+
+    let curve = |p0: f32, p1: f32, p2: f32, p3: f32| {
+        0.5 * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+    };
+
+    Vector3D {
+        x: curve(p0.x, p1.x, p2.x, p3.x),
+        y: curve(p0.y, p1.y, p2.y, p3.y),
+        z: curve(p0.z, p1.z, p2.z, p3.z),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32) -> Keyframe {
+        Keyframe { time, value: Vector3D { x, y: -x, z: 0.0 } }
+    }
+
+    fn track(interpolation: Interpolation, looping: bool) -> Track {
+        Track::new(interpolation, vec![key(0.0, 0.0), key(1.0, 2.0), key(3.0, 4.0), key(4.0, 0.0)], looping).unwrap()
+    }
+
+    #[test]
+    /// # test_linear_and_step
+    fn test_linear_and_step() {
+        let linear = track(Interpolation::Linear, false);
+        assert_eq!(linear.sample(0.5), Vector3D { x: 1.0, y: -1.0, z: 0.0 });
+        assert_eq!(linear.sample(2.0).x, 3.0);
+
+        let step = track(Interpolation::Step, false);
+        assert_eq!(step.sample(0.5).x, 0.0);
+        assert_eq!(step.sample(2.9).x, 2.0);
+        assert_eq!(step.sample(3.0).x, 4.0);
+    }
+
+    #[test]
+    /// # test_catmull_rom_passes_through_keyframes
+    /// The curve hits every keyframe and doesn't jump anywhere in between.
+    fn test_catmull_rom_passes_through_keyframes() {
+        let curve = track(Interpolation::CatmullRom, false);
+
+        for time in [0.0, 1.0, 3.0, 4.0] {
+            assert!((curve.sample(time).x - track(Interpolation::Step, false).sample(time).x).abs() < 1e-5, "at {}", time);
+        }
+
+        for step in 0..400 {
+            let time = step as f32 * 0.01;
+            assert!((curve.sample(time + 0.01).x - curve.sample(time).x).abs() < 0.1, "jump at {}", time);
+        }
+
+        // It curves rather than following the straight lines between keyframes
+        assert_ne!(curve.sample(2.0).x, 3.0);
+    }
+
+    #[test]
+    /// # test_ends_clamp_or_loop
+    fn test_ends_clamp_or_loop() {
+        let clamped = track(Interpolation::Linear, false);
+        assert_eq!(clamped.sample(-1.0).x, 0.0);
+        assert_eq!(clamped.sample(10.0).x, 0.0);
+        assert_eq!(clamped.sample(3.5).x, 2.0);
+
+        let looping = track(Interpolation::Linear, true);
+        assert_eq!(looping.duration(), 4.0);
+        assert!((looping.sample(4.5).x - 1.0).abs() < 1e-5);
+        assert!((looping.sample(-0.5).x - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    /// # test_track_from_json
+    fn test_track_from_json() {
+        let json = r#"{
+            "interpolation": "catmull_rom",
+            "looping": true,
+            "keyframes": [{ "time": 0.0, "value": { "x": 0.0, "y": 0.0, "z": 0.0 } }]
+        }"#;
+
+        let track: Track = serde_json::from_str(json).unwrap();
+        assert_eq!(track.sample(3.0), Vector3D { x: 0.0, y: 0.0, z: 0.0 });
+
+        assert!(serde_json::from_str::<Track>(r#"{ "keyframes": [] }"#).is_err());
+        assert!(serde_json::from_str::<Track>(r#"{ "keyframes": [
+            { "time": 1.0, "value": { "x": 0.0, "y": 0.0, "z": 0.0 } },
+            { "time": 0.0, "value": { "x": 0.0, "y": 0.0, "z": 0.0 } }
+        ] }"#).is_err());
+
+        let animation: TransformAnimation = serde_json::from_str(&format!(r#"{{ "rotation": {} }}"#, json)).unwrap();
+        assert_eq!(animation.sample(1.0).0, Vector3D { x: 0.0, y: 0.0, z: 0.0 });
+    }
+}
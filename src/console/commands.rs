@@ -0,0 +1,31 @@
+/// # Command
+/// A console command, as listed by `help`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Command {
+    pub name: &'static str,
+    /// The arguments it takes, e.g. `<x> <y> <z>`.
+    pub usage: &'static str,
+    pub help: &'static str,
+}
+
+/// # CommandHandler
+/// Something that adds its own commands to the console, such as the camera or a screen.
+pub trait CommandHandler {
+    fn commands(&self) -> Vec<Command>;
+
+    /// Suggestions for a command's argument, `index` 0 being the first after the name. The
+    /// console narrows them down to what's been typed so far.
+    fn complete(&self, _command: &str, _index: usize) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Runs one of this handler's commands, returning what to print, or what went wrong.
+    fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String>;
+}
+
+/// Parses a command's argument as a number, saying which it was if it isn't one.
+pub fn number(args: &[&str], index: usize, name: &str) -> Result<f32, String> {
+    let arg = args.get(index).ok_or(format!("Missing {}", name))?;
+
+    arg.parse().map_err(|_| format!("{} isn't a number: {}", name, arg))
+}
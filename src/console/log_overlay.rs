@@ -0,0 +1,117 @@
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
+use crate::console::commands::{Command, CommandHandler};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
+use crate::utils::logging::{self, Filters, Level};
+
+/// Records the overlay shows, the latest at the bottom.
+const VISIBLE_RECORDS: usize = 12;
+
+const LINE_HEIGHT: f32 = 16.0;
+
+/// # LogOverlay
+/// The latest log records over the bottom of the screen, and the console commands for what gets
+/// logged.
+#[derive(Debug, Default)]
+pub struct LogOverlay {
+    pub open: bool,
+}
+
+impl LogOverlay {
+    pub fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        if !self.open {
+            return;
+        }
+
+        let (width, bottom) = (camera.viewport.width, camera.viewport.height - 50.0);
+        let top = bottom - LINE_HEIGHT * VISIBLE_RECORDS as f32 - 10.0;
+        backend.polygon(
+            &[Pos2::new(0.0, top), Pos2::new(width, top), Pos2::new(width, bottom), Pos2::new(0.0, bottom)],
+            Color32::from_black_alpha(200),
+            Stroke::new(1.0, Color32::DARK_GREEN),
+        );
+
+        let mut style = TextStyle::new(10.0);
+        style.align = Align2::LEFT_BOTTOM;
+
+        for (index, record) in logging::latest(VISIBLE_RECORDS).iter().rev().enumerate() {
+            let colour = match record.level {
+                Level::Error => Color32::RED,
+                Level::Warn => Color32::YELLOW,
+                Level::Info => Color32::GREEN,
+                _ => Color32::DARK_GREEN,
+            };
+            let text = format!("{} {}: {}", record.level.name().to_uppercase(), record.module, record.message);
+            let position = Pos2::new(10.0, bottom - 5.0 - index as f32 * LINE_HEIGHT);
+
+            backend.text(&text, position, &style, Stroke::new(1.0, colour));
+        }
+    }
+}
+
+impl CommandHandler for LogOverlay {
+    fn commands(&self) -> Vec<Command> {
+        vec![
+            Command { name: "loglevel", usage: "[filters]", help: "Shows or sets what's logged, e.g. info,camera=debug" },
+            Command { name: "logview", usage: "", help: "Shows or hides the latest log records" },
+        ]
+    }
+
+    fn complete(&self, command: &str, index: usize) -> Vec<String> {
+        match (command, index) {
+            ("loglevel", 0) => Level::ALL.iter().map(|level| level.name().to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        match command {
+            "loglevel" => {
+                let mut filters = logging::filters();
+                let reply = loglevel(&mut filters, args)?;
+                logging::set_filters(filters);
+
+                Ok(reply)
+            }
+            "logview" => {
+                self.open = !self.open;
+
+                Ok(format!("Log view {}", if self.open { "on" } else { "off" }))
+            }
+            _ => Err(format!("Unknown command {}", command)),
+        }
+    }
+}
+
+/// # loglevel
+/// The `loglevel` command on `filters`: changes them if there are `args` and says what they are.
+fn loglevel(filters: &mut Filters, args: &[&str]) -> Result<String, String> {
+    if !args.is_empty() {
+        *filters = filters.parse(&args.join(","))?;
+    }
+
+    let modules = filters.modules.iter().map(|(module, level)| format!(",{}={}", module, level.name()));
+
+    Ok(format!("Logging {}{}", filters.default.name(), modules.collect::<String>()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_loglevel_command
+    /// Works on its own filters rather than the game's, which other tests are logging through.
+    fn test_loglevel_command() {
+        let mut filters = Filters::new();
+
+        assert_eq!(loglevel(&mut filters, &[]), Ok("Logging info".to_string()));
+        assert!(loglevel(&mut filters, &["nothing_here=loud"]).is_err());
+        assert_eq!(filters, Filters::new());
+
+        assert_eq!(loglevel(&mut filters, &["warn", "nothing_here=trace"]), Ok("Logging warn,nothing_here=trace".to_string()));
+        assert!(filters.enabled(Level::Trace, "nothing_here"));
+        assert!(!filters.enabled(Level::Info, "camera"));
+    }
+}
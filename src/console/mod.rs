@@ -0,0 +1,583 @@
+pub mod commands;
+pub mod log_overlay;
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use eframe::egui::{Align2, Color32, Event, Key, Pos2, Stroke};
+use crate::console::commands::{Command, CommandHandler};
+use crate::input::bindings::{Action, Binding, InputBindings};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
+use crate::screens::events::{EntityDestroyed, HudMessage, LevelComplete, ScriptOutput, SpawnWave, TriggerFired};
+use crate::utils::message_bus::{MessageBus, Subscription};
+
+/// Lines the console keeps, the oldest going first.
+const MAX_LINES: usize = 200;
+
+/// Lines shown when the console is open.
+const VISIBLE_LINES: usize = 14;
+
+const LINE_HEIGHT: f32 = 18.0;
+
+/// Entries `logs` shows unless it's asked for a different number.
+const DEFAULT_LOGS: usize = 10;
+
+/// The console's own commands, the rest come from its `CommandHandler`s.
+const BUILT_IN: [Command; 3] = [
+    Command { name: "help", usage: "", help: "Lists the commands" },
+    Command { name: "clear", usage: "", help: "Empties the console" },
+    Command { name: "logs", usage: "[count]", help: "Shows what's happened in the level lately" },
+];
+
+/// # ConsoleLine
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsoleLine {
+    pub text: String,
+    pub is_error: bool,
+}
+
+/// # Console
+/// Drops down over the top of the screen to type commands into, showing what they said along
+/// with what the current screen's scripts printed and what went wrong with them. While it's closed
+/// a note at the bottom of the screen says there are errors to look at.
+///
+/// Commands come from `CommandHandler`s passed in as they're typed, so whatever's on screen can
+/// add its own. Tab completes commands and their arguments, and up and down go through the
+/// commands typed before. Escape closes it.
+pub struct Console {
+    pub open: bool,
+    lines: Rc<RefCell<VecDeque<ConsoleLine>>>,
+    /// Errors that have come in since the console was last opened.
+    unseen_errors: Rc<Cell<usize>>,
+    /// What's happened in the level, for `logs`.
+    events: Rc<RefCell<VecDeque<String>>>,
+    subscriptions: Vec<Subscription>,
+    /// The command being typed.
+    pub input: String,
+    history: Vec<String>,
+    /// Where in `history` up and down have got to, if they've been pressed since the last command.
+    history_index: Option<usize>,
+    /// What the keys bound to opening the console type, lower case, so opening it doesn't type them.
+    toggle_characters: Vec<char>,
+    /// Set when the console opens, until the rest of that frame's input has been handled.
+    just_opened: bool,
+}
+
+impl Console {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            lines: Rc::new(RefCell::new(VecDeque::new())),
+            unseen_errors: Rc::new(Cell::new(0)),
+            events: Rc::new(RefCell::new(VecDeque::new())),
+            subscriptions: Vec::new(),
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            toggle_characters: Vec::new(),
+            just_opened: false,
+        }
+    }
+
+    /// Takes note of the keys that open the console, again whenever they're rebound.
+    pub fn bind_toggle(&mut self, bindings: &InputBindings) {
+        self.toggle_characters = bindings
+            .bindings(Action::ToggleConsole)
+            .iter()
+            .filter_map(|binding| match binding.input {
+                Binding::Key(key) => {
+                    // Only keys named by a single character type one
+                    let mut characters = key.symbol_or_name().chars();
+                    characters.next().filter(|_| characters.next().is_none())
+                }
+                _ => None,
+            })
+            .map(|character| character.to_ascii_lowercase())
+            .collect();
+    }
+
+    pub fn print(&self, text: &str, is_error: bool) {
+        push_line(&self.lines, &self.unseen_errors, ConsoleLine { text: text.to_string(), is_error });
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.just_opened = self.open;
+        self.unseen_errors.set(0);
+    }
+
+    /// Subscribes to what the screen's scripts have to say, and what happens for `logs`.
+    pub fn connect(&mut self, bus: &mut MessageBus) {
+        let (lines, unseen_errors) = (self.lines.clone(), self.unseen_errors.clone());
+
+        self.subscriptions.push(bus.subscribe(move |output: &ScriptOutput| {
+            push_line(&lines, &unseen_errors, ConsoleLine { text: output.text.clone(), is_error: output.is_error });
+        }));
+
+        self.log(bus, |event: &EntityDestroyed| format!("Destroyed {} ({})", event.id, event.object_type));
+        self.log(bus, |event: &TriggerFired| format!("Trigger {}", event.id));
+        self.log(bus, |event: &SpawnWave| format!("Wave {}", event.wave));
+        self.log(bus, |event: &HudMessage| format!("Message {}", event.text));
+        self.log(bus, |_: &LevelComplete| "Level complete".to_string());
+    }
+
+    /// Keeps a line in the event log for every event of type `E`.
+    fn log<E: 'static>(&mut self, bus: &mut MessageBus, describe: impl Fn(&E) -> String + 'static) {
+        let events = self.events.clone();
+
+        self.subscriptions.push(bus.subscribe(move |event: &E| {
+            let mut events = events.borrow_mut();
+            events.push_back(describe(event));
+
+            if events.len() > MAX_LINES {
+                events.pop_front();
+            }
+        }));
+    }
+
+    pub fn disconnect(&mut self, bus: &mut MessageBus) {
+        for subscription in self.subscriptions.drain(..) {
+            bus.unsubscribe(subscription);
+        }
+    }
+
+    /// Types and runs commands from the frame's keyboard input.
+    pub fn handle_events(&mut self, events: &[Event], handlers: &mut [&mut dyn CommandHandler]) {
+        for event in events {
+            match event {
+                // The key that opened the console types as it's pressed, on the same frame
+                Event::Text(text) if self.just_opened => {
+                    let toggle = &self.toggle_characters;
+                    let text: String = text.chars().filter(|character| !toggle.contains(&character.to_ascii_lowercase())).collect();
+                    self.type_text(&text);
+                }
+                Event::Text(text) => self.type_text(text),
+                Event::Key { key, pressed: true, .. } => self.key(*key, handlers),
+                _ => {}
+            }
+        }
+
+        self.just_opened = false;
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        self.input.push_str(text);
+    }
+
+    pub fn key(&mut self, key: Key, handlers: &mut [&mut dyn CommandHandler]) {
+        match key {
+            Key::Enter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+
+                if !line.trim().is_empty() {
+                    if self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+
+                    self.run(&line, handlers);
+                }
+            }
+            Key::Tab => self.complete(handlers),
+            Key::ArrowUp if !self.history.is_empty() => {
+                let index = self.history_index.map_or(self.history.len() - 1, |index| index.saturating_sub(1));
+                self.history_index = Some(index);
+                self.input = self.history[index].clone();
+            }
+            Key::ArrowDown if self.history_index.is_some() => {
+                self.history_index = self.history_index.map(|index| index + 1).filter(|index| *index < self.history.len());
+                self.input = self.history_index.map(|index| self.history[index].clone()).unwrap_or_default();
+            }
+            Key::Backspace => {
+                self.input.pop();
+            }
+            Key::Escape => self.toggle(),
+            _ => {}
+        }
+    }
+
+    /// # run
+    /// Runs a command line, printing it and whatever the command has to say.
+    pub fn run(&mut self, line: &str, handlers: &mut [&mut dyn CommandHandler]) {
+        self.print(&format!("> {}", line), false);
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((name, args)) = words.split_first() else {
+            return;
+        };
+
+        let result = match *name {
+            "help" => Ok(Self::help(handlers)),
+            "clear" => {
+                self.lines.borrow_mut().clear();
+                return;
+            }
+            "logs" => self.logs(args),
+            _ => match handlers.iter_mut().find(|handler| handles(&***handler, name)) {
+                Some(handler) => handler.run(name, args),
+                None => Err(format!("Unknown command {}, try help", name)),
+            },
+        };
+
+        match result {
+            Ok(text) => text.lines().for_each(|line| self.print(line, false)),
+            Err(error) => self.print(&error, true),
+        }
+    }
+
+    fn help(handlers: &[&mut dyn CommandHandler]) -> String {
+        all_commands(handlers)
+            .iter()
+            .map(|command| format!("{} - {}", format!("{} {}", command.name, command.usage).trim_end(), command.help))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn logs(&self, args: &[&str]) -> Result<String, String> {
+        let count = match args.first() {
+            Some(count) => count.parse().map_err(|_| format!("Not a count: {}", count))?,
+            None => DEFAULT_LOGS,
+        };
+
+        let events = self.events.borrow();
+        if events.is_empty() {
+            return Ok("Nothing's happened yet".to_string());
+        }
+
+        Ok(events.iter().skip(events.len().saturating_sub(count)).cloned().collect::<Vec<_>>().join("\n"))
+    }
+
+    /// # complete
+    /// Finishes the word being typed if only one command or argument fits, otherwise as much of
+    /// it as they all share, listing them.
+    fn complete(&mut self, handlers: &[&mut dyn CommandHandler]) {
+        let mut words: Vec<&str> = self.input.split_whitespace().collect();
+        if self.input.is_empty() || self.input.ends_with(' ') {
+            words.push("");
+        }
+
+        let Some((typed, before)) = words.split_last() else {
+            return;
+        };
+
+        let candidates: Vec<String> = match before.first() {
+            None => all_commands(handlers).iter().map(|command| command.name.to_string()).collect(),
+            Some(name) => handlers
+                .iter()
+                .find(|handler| handles(&***handler, name))
+                .map(|handler| handler.complete(name, before.len() - 1))
+                .unwrap_or_default(),
+        };
+
+        let matches: Vec<&String> = candidates.iter().filter(|candidate| candidate.starts_with(typed)).collect();
+        let Some(first) = matches.first() else {
+            return;
+        };
+
+        let shared = matches.iter().fold(first.as_str(), |shared, candidate| {
+            // In bytes, so a shared prefix ending in a wider character is cut after it, not inside
+            let length = shared
+                .char_indices()
+                .zip(candidate.chars())
+                .take_while(|((_, a), b)| a == b)
+                .last()
+                .map_or(0, |((index, character), _)| index + character.len_utf8());
+            &shared[..length]
+        });
+
+        let mut input = before.join(" ");
+        if !input.is_empty() {
+            input.push(' ');
+        }
+        input.push_str(shared);
+
+        if matches.len() == 1 {
+            input.push(' ');
+        } else {
+            let options = matches.iter().map(|candidate| candidate.as_str()).collect::<Vec<_>>().join("  ");
+            self.print(&options, false);
+        }
+
+        self.input = input;
+    }
+
+    pub fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let mut style = TextStyle::new(12.0);
+
+        if !self.open {
+            let errors = self.unseen_errors.get();
+
+            if errors > 0 {
+                style.align = Align2::RIGHT_BOTTOM;
+                let position = Pos2::new(camera.viewport.width - 20.0, camera.viewport.height - 20.0);
+
+                backend.text(&format!("{} ERRORS IN CONSOLE", errors), position, &style, Stroke::new(1.0, Color32::RED));
+            }
+
+            return;
+        }
+
+        let height = LINE_HEIGHT * (VISIBLE_LINES as f32 + 2.0);
+        let width = camera.viewport.width;
+        backend.polygon(
+            &[Pos2::new(0.0, 0.0), Pos2::new(width, 0.0), Pos2::new(width, height), Pos2::new(0.0, height)],
+            Color32::from_black_alpha(230),
+            Stroke::new(1.0, Color32::DARK_GREEN),
+        );
+
+        let lines = self.lines.borrow();
+        let shown = lines.iter().skip(lines.len().saturating_sub(VISIBLE_LINES));
+
+        for (index, line) in shown.enumerate() {
+            let colour = if line.is_error { Color32::RED } else { Color32::GREEN };
+            let position = Pos2::new(10.0, 8.0 + index as f32 * LINE_HEIGHT);
+
+            backend.text(&line.text, position, &style, Stroke::new(1.0, colour));
+        }
+
+        let position = Pos2::new(10.0, 8.0 + VISIBLE_LINES as f32 * LINE_HEIGHT);
+        backend.text(&format!("> {}_", self.input), position, &style, Stroke::new(1.5, Color32::GREEN));
+    }
+}
+
+fn handles(handler: &dyn CommandHandler, name: &str) -> bool {
+    handler.commands().iter().any(|command| command.name == name)
+}
+
+fn all_commands(handlers: &[&mut dyn CommandHandler]) -> Vec<Command> {
+    BUILT_IN.into_iter().chain(handlers.iter().flat_map(|handler| handler.commands())).collect()
+}
+
+fn push_line(lines: &RefCell<VecDeque<ConsoleLine>>, unseen_errors: &Cell<usize>, line: ConsoleLine) {
+    if line.is_error {
+        unseen_errors.set(unseen_errors.get() + 1);
+    }
+
+    let mut lines = lines.borrow_mut();
+    lines.push_back(line);
+
+    if lines.len() > MAX_LINES {
+        lines.pop_front();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_console_collects_script_output
+    fn test_console_collects_script_output() {
+        let mut bus = MessageBus::new();
+        let mut console = Console::new();
+        console.connect(&mut bus);
+
+        bus.publish(ScriptOutput { text: "hello".to_string(), is_error: false });
+        bus.publish(ScriptOutput { text: "broken".to_string(), is_error: true });
+        assert_eq!(console.unseen_errors.get(), 1);
+        assert_eq!(console.lines.borrow().back().unwrap(), &ConsoleLine { text: "broken".to_string(), is_error: true });
+
+        console.toggle();
+        assert!(console.open);
+        assert_eq!(console.unseen_errors.get(), 0);
+
+        for line in 0..MAX_LINES {
+            console.print(&line.to_string(), false);
+        }
+        assert_eq!(console.lines.borrow().len(), MAX_LINES);
+        assert_eq!(console.lines.borrow()[0].text, "0");
+
+        console.disconnect(&mut bus);
+        bus.publish(ScriptOutput { text: "gone".to_string(), is_error: true });
+        assert_eq!(console.lines.borrow().len(), MAX_LINES);
+    }
+
+    /// Teleports to named places, to test commands with.
+    struct Places {
+        at: String,
+    }
+
+    impl CommandHandler for Places {
+        fn commands(&self) -> Vec<Command> {
+            vec![
+                Command { name: "goto", usage: "<place>", help: "Goes somewhere" },
+                Command { name: "greet", usage: "", help: "Says hello" },
+            ]
+        }
+
+        fn complete(&self, _command: &str, index: usize) -> Vec<String> {
+            if index == 0 { ["base", "bridge", "hill", "ålen", "åsen"].map(String::from).to_vec() } else { Vec::new() }
+        }
+
+        fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+            match (command, args) {
+                ("goto", [place]) => {
+                    self.at = place.to_string();
+                    Ok(format!("At {}", place))
+                }
+                ("goto", _) => Err("Where to?".to_string()),
+                _ => Ok("Hello".to_string()),
+            }
+        }
+    }
+
+    fn type_line(console: &mut Console, text: &str, handlers: &mut [&mut dyn CommandHandler]) {
+        console.type_text(text);
+        console.key(Key::Enter, handlers);
+    }
+
+    fn last_line(console: &Console) -> ConsoleLine {
+        console.lines.borrow().back().unwrap().clone()
+    }
+
+    #[test]
+    /// # test_commands_run_on_their_handler
+    fn test_commands_run_on_their_handler() {
+        let mut console = Console::new();
+        let mut places = Places { at: String::new() };
+
+        type_line(&mut console, "goto  hill", &mut [&mut places]);
+        assert_eq!(places.at, "hill");
+        assert_eq!(last_line(&console), ConsoleLine { text: "At hill".to_string(), is_error: false });
+
+        type_line(&mut console, "goto", &mut [&mut places]);
+        assert!(last_line(&console).is_error);
+
+        type_line(&mut console, "fly", &mut [&mut places]);
+        assert_eq!(last_line(&console).text, "Unknown command fly, try help");
+
+        type_line(&mut console, "help", &mut [&mut places]);
+        assert_eq!(last_line(&console).text, "greet - Says hello");
+
+        type_line(&mut console, "clear", &mut [&mut places]);
+        assert!(console.lines.borrow().is_empty());
+    }
+
+    #[test]
+    /// # test_tab_completion
+    fn test_tab_completion() {
+        let mut console = Console::new();
+        let mut places = Places { at: String::new() };
+
+        console.type_text("g");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "g");
+        assert_eq!(last_line(&console).text, "goto  greet");
+
+        console.type_text("o");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "goto ");
+
+        console.type_text("b");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "goto b");
+
+        console.type_text("a");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "goto base ");
+    }
+
+    #[test]
+    /// # test_tab_completion_outside_ascii
+    /// What the candidates share is cut on a character boundary, not partway through one.
+    fn test_tab_completion_outside_ascii() {
+        let mut console = Console::new();
+        let mut places = Places { at: String::new() };
+
+        console.type_text("goto å");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "goto å");
+        assert_eq!(last_line(&console).text, "ålen  åsen");
+
+        console.type_text("s");
+        console.key(Key::Tab, &mut [&mut places]);
+        assert_eq!(console.input, "goto åsen ");
+    }
+
+    #[test]
+    /// # test_toggle_key_isnt_typed
+    /// What the key bound to opening the console types on the frame it opens is left out,
+    /// whichever key it is, and that key types as normal after that.
+    fn test_toggle_key_isnt_typed() {
+        let mut bindings = InputBindings::default();
+        let mut console = Console::new();
+        console.bind_toggle(&bindings);
+        let type_frame = |console: &mut Console, text: &str| console.handle_events(&[Event::Text(text.to_string())], &mut []);
+
+        console.toggle();
+        type_frame(&mut console, "a`b");
+        assert_eq!(console.input, "ab");
+        type_frame(&mut console, "`");
+        assert_eq!(console.input, "ab`");
+
+        bindings.clear(Action::ToggleConsole);
+        bindings.bind(Action::ToggleConsole, Binding::Key(Key::Q));
+        console.bind_toggle(&bindings);
+        console.key(Key::Escape, &mut []);
+        console.input.clear();
+
+        console.toggle();
+        type_frame(&mut console, "`qQ");
+        assert_eq!(console.input, "`");
+        type_frame(&mut console, "quit");
+        assert_eq!(console.input, "`quit");
+    }
+
+    #[test]
+    /// # test_history
+    fn test_history() {
+        let mut console = Console::new();
+        let mut places = Places { at: String::new() };
+
+        for line in ["goto base", "greet", "greet", "goto hill"] {
+            type_line(&mut console, line, &mut [&mut places]);
+        }
+
+        let mut press = |key| {
+            console.key(key, &mut [&mut places]);
+            console.input.clone()
+        };
+
+        assert_eq!(press(Key::ArrowUp), "goto hill");
+        assert_eq!(press(Key::ArrowUp), "greet");
+        assert_eq!(press(Key::ArrowUp), "goto base");
+        assert_eq!(press(Key::ArrowUp), "goto base");
+        assert_eq!(press(Key::ArrowDown), "greet");
+        assert_eq!(press(Key::ArrowDown), "goto hill");
+        assert_eq!(press(Key::ArrowDown), "");
+    }
+
+    #[test]
+    /// # test_down_keeps_what_is_typed
+    /// Down only goes through the history once up has started going back through it.
+    fn test_down_keeps_what_is_typed() {
+        let mut console = Console::new();
+        type_line(&mut console, "greet", &mut []);
+
+        console.type_text("go");
+        console.key(Key::ArrowDown, &mut []);
+        assert_eq!(console.input, "go");
+    }
+
+    #[test]
+    /// # test_logs
+    fn test_logs() {
+        let mut bus = MessageBus::new();
+        let mut console = Console::new();
+        console.connect(&mut bus);
+
+        type_line(&mut console, "logs", &mut []);
+        assert_eq!(last_line(&console).text, "Nothing's happened yet");
+
+        bus.publish(TriggerFired { id: "ambush".to_string() });
+        bus.publish(SpawnWave { wave: "ambush".to_string() });
+        bus.publish(LevelComplete);
+
+        type_line(&mut console, "logs 2", &mut []);
+        let lines: Vec<String> = console.lines.borrow().iter().map(|line| line.text.clone()).collect();
+        assert_eq!(lines[lines.len() - 2..], ["Wave ambush", "Level complete"]);
+    }
+}
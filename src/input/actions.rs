@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, BTreeSet};
+use eframe::egui::{Event, InputState, Pos2, Vec2};
+use crate::input::bindings::{Action, ActionBinding, Binding, InputBindings};
+
+/// # ActionState
+/// A snapshot of the actions for one frame, built from the raw egui input and the player's
+/// bindings. Screens query this rather than egui so they don't care which keys are bound.
+///
+/// The pointer fields are only for driving on-screen UI such as menus.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ActionState {
+    values: BTreeMap<Action, f32>,
+    pressed: BTreeSet<Action>,
+    pub pointer: Option<Pos2>,
+    pub pointer_moved: bool,
+    pub clicked: bool,
+    /// The first key or mouse button that went down this frame, used when rebinding.
+    pub captured: Option<Binding>,
+    /// Each player's actions from their own bindings, for local two player games.
+    pub players: Vec<ActionState>,
+}
+
+impl ActionState {
+    pub fn from_input(input: &InputState, bindings: &InputBindings) -> Self {
+        let state = Self {
+            pointer: input.pointer.hover_pos(),
+            pointer_moved: input.pointer.delta() != Vec2::ZERO,
+            clicked: input.pointer.primary_clicked(),
+            captured: Self::first_pressed(input),
+            players: bindings.players.iter().map(|player| Self::default().read(input, player)).collect(),
+            ..Self::default()
+        };
+
+        state.read(input, &bindings.actions)
+    }
+
+    /// Sets every action in `actions` from the input.
+    fn read(mut self, input: &InputState, actions: &BTreeMap<Action, Vec<ActionBinding>>) -> Self {
+        for (action, action_bindings) in actions {
+            let mut value = 0.0;
+            let mut pressed = false;
+
+            for binding in action_bindings {
+                let (binding_value, binding_pressed) = match binding.input {
+                    Binding::Key(key) => (if input.key_down(key) { 1.0 } else { 0.0 }, input.key_pressed(key)),
+                    Binding::MouseButton(button) => (if input.pointer.button_down(button) { 1.0 } else { 0.0 }, input.pointer.button_pressed(button)),
+                    Binding::MouseX => (input.pointer.delta().x, false),
+                    Binding::MouseY => (input.pointer.delta().y, false),
+                };
+
+                value += binding_value * binding.scale;
+                pressed |= binding_pressed;
+            }
+
+            self.set(*action, value, pressed);
+        }
+
+        self
+    }
+
+    fn first_pressed(input: &InputState) -> Option<Binding> {
+        input.events.iter().find_map(|event| match event {
+            Event::Key { key, pressed: true, repeat: false, .. } => Some(Binding::Key(*key)),
+            Event::PointerButton { button, pressed: true, .. } => Some(Binding::MouseButton(*button)),
+            _ => None,
+        })
+    }
+
+    /// Sets the value of an action, and whether it went down this frame.
+    pub fn set(&mut self, action: Action, value: f32, pressed: bool) {
+        if value != 0.0 {
+            self.values.insert(action, value);
+        } else {
+            self.values.remove(&action);
+        }
+
+        if pressed {
+            self.pressed.insert(action);
+        } else {
+            self.pressed.remove(&action);
+        }
+    }
+
+    /// The combined value of every binding for the action, 0.0 if none are active.
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+
+    pub fn is_down(&self, action: Action) -> bool {
+        self.value(action) != 0.0
+    }
+
+    /// Did the action go down this frame?
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// One player's actions, nothing if they've no bindings.
+    pub fn player(&self, index: usize) -> ActionState {
+        self.players.get(index).cloned().unwrap_or_default()
+    }
+
+    /// # axis
+    /// Combines a pair of opposing actions into a single value, e.g. forward and backward.
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+
+    /// Every action with a non zero value.
+    pub fn values(&self) -> impl Iterator<Item = (Action, f32)> + '_ {
+        self.values.iter().map(|(action, value)| (*action, *value))
+    }
+
+    /// Every action that went down this frame.
+    pub fn pressed_actions(&self) -> impl Iterator<Item = Action> + '_ {
+        self.pressed.iter().copied()
+    }
+
+    /// # gameplay_only
+    /// A copy with just the actions, dropping the pointer and UI fields which only matter to menus.
+    pub fn gameplay_only(&self) -> Self {
+        Self {
+            values: self.values.clone(),
+            pressed: self.pressed.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Has the player touched anything this frame?
+    pub fn any_activity(&self) -> bool {
+        !self.values.is_empty() || self.pointer_moved || self.captured.is_some()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::{Key, Modifiers, RawInput};
+
+    fn input_with_events(events: Vec<Event>) -> InputState {
+        let raw = RawInput { events, ..Default::default() };
+
+        InputState::default().begin_pass(raw, false, 1.0, &Default::default())
+    }
+
+    fn key_event(key: Key) -> Event {
+        Event::Key { key, physical_key: None, pressed: true, repeat: false, modifiers: Modifiers::NONE }
+    }
+
+    #[test]
+    /// # test_any_binding_triggers_action
+    /// Both W and the up arrow are bound to forward by default.
+    fn test_any_binding_triggers_action() {
+        let bindings = InputBindings::default();
+
+        for key in [Key::W, Key::ArrowUp] {
+            let state = ActionState::from_input(&input_with_events(vec![key_event(key)]), &bindings);
+
+            assert_eq!(state.value(Action::MoveForward), 1.0);
+            assert!(state.pressed(Action::MoveForward));
+            assert!(!state.is_down(Action::MoveBackward));
+        }
+    }
+
+    #[test]
+    /// # test_axis
+    fn test_axis() {
+        let bindings = InputBindings::default();
+        let state = ActionState::from_input(&input_with_events(vec![key_event(Key::S)]), &bindings);
+
+        assert_eq!(state.axis(Action::MoveForward, Action::MoveBackward), -1.0);
+    }
+
+    #[test]
+    /// # test_mouse_axis_is_scaled
+    /// Mouse movement is the delta between frames scaled by the binding, Y is inverted by default.
+    fn test_mouse_axis_is_scaled() {
+        let bindings = InputBindings::default();
+
+        let input = input_with_events(vec![Event::PointerMoved(Pos2::new(10.0, 10.0))])
+            .begin_pass(RawInput { events: vec![Event::PointerMoved(Pos2::new(20.0, 5.0))], ..Default::default() }, false, 1.0, &Default::default());
+        let state = ActionState::from_input(&input, &bindings);
+
+        assert!((state.value(Action::LookHorizontal) - 2.0).abs() < 1e-5);
+        assert!((state.value(Action::LookVertical) - 1.0).abs() < 1e-5);
+        assert!(state.pointer_moved);
+    }
+
+    #[test]
+    /// # test_players_have_their_own_keys
+    /// Either player's keys drive the game, but each player only gets their own.
+    fn test_players_have_their_own_keys() {
+        let state = ActionState::from_input(&input_with_events(vec![key_event(Key::ArrowUp)]), &InputBindings::default());
+
+        assert!(state.is_down(Action::MoveForward));
+        assert!(state.player(1).is_down(Action::MoveForward));
+        assert!(!state.player(0).is_down(Action::MoveForward));
+        assert_eq!(state.player(2), ActionState::default());
+    }
+
+    #[test]
+    /// # test_captured_binding
+    fn test_captured_binding() {
+        let state = ActionState::from_input(&input_with_events(vec![key_event(Key::Q)]), &InputBindings::default());
+
+        assert_eq!(state.captured, Some(Binding::Key(Key::Q)));
+        assert!(state.any_activity());
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use eframe::egui::{Key, PointerButton};
+use serde::{Deserialize, Serialize};
+
+/// Where the player's bindings are saved, relative to the working directory.
+pub const BINDINGS_FILE: &str = "bindings.json";
+
+/// # Action
+/// The things the player can do. Game code asks about actions and never about the keys or buttons
+/// behind them, so the player is free to rebind them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    /// Straight up and down, for cameras that fly.
+    MoveUp,
+    MoveDown,
+    /// Held to turn mouse movement into looking around.
+    Look,
+    LookHorizontal,
+    LookVertical,
+    Fire,
+    MenuUp,
+    MenuDown,
+    MenuSelect,
+    Back,
+    ToggleRecording,
+    /// Saves the current frame as an SVG.
+    ExportFrame,
+    /// Changes to the next camera mode.
+    SwitchCamera,
+    /// Opens and closes the console.
+    ToggleConsole,
+    /// Shows and hides the FPS and frame timings.
+    ToggleStats,
+}
+
+impl Action {
+    pub const ALL: [Action; 19] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Look,
+        Action::LookHorizontal,
+        Action::LookVertical,
+        Action::Fire,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuSelect,
+        Action::Back,
+        Action::ToggleRecording,
+        Action::ExportFrame,
+        Action::SwitchCamera,
+        Action::ToggleConsole,
+        Action::ToggleStats,
+    ];
+
+    /// Actions the player can rebind from the options screen. The look axes are left out as they
+    /// only make sense on the mouse.
+    pub const REBINDABLE: [Action; 17] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::StrafeLeft,
+        Action::StrafeRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Look,
+        Action::Fire,
+        Action::MenuUp,
+        Action::MenuDown,
+        Action::MenuSelect,
+        Action::Back,
+        Action::ToggleRecording,
+        Action::ExportFrame,
+        Action::SwitchCamera,
+        Action::ToggleConsole,
+        Action::ToggleStats,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "FORWARD",
+            Action::MoveBackward => "BACKWARD",
+            Action::StrafeLeft => "STRAFE LEFT",
+            Action::StrafeRight => "STRAFE RIGHT",
+            Action::MoveUp => "UP",
+            Action::MoveDown => "DOWN",
+            Action::Look => "LOOK",
+            Action::LookHorizontal => "LOOK HORIZONTAL",
+            Action::LookVertical => "LOOK VERTICAL",
+            Action::Fire => "FIRE",
+            Action::MenuUp => "MENU UP",
+            Action::MenuDown => "MENU DOWN",
+            Action::MenuSelect => "MENU SELECT",
+            Action::Back => "BACK",
+            Action::ToggleRecording => "RECORD",
+            Action::ExportFrame => "EXPORT SVG",
+            Action::SwitchCamera => "CAMERA",
+            Action::ToggleConsole => "CONSOLE",
+            Action::ToggleStats => "STATS",
+        }
+    }
+
+    /// The name used in config and recording files, the same as the variant name.
+    pub fn name(&self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|action| action.name() == name)
+    }
+}
+
+/// # Binding
+/// A physical input. Keys and mouse buttons are either on (1.0) or off (0.0), mouse movement gives
+/// the distance moved this frame.
+///
+/// Saved as a short string, e.g. `"Key:W"`, `"Mouse:Primary"` or `"MouseX"`, so the config file is
+/// easy to edit by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(into = "String", try_from = "String")]
+pub enum Binding {
+    Key(Key),
+    MouseButton(PointerButton),
+    MouseX,
+    MouseY,
+}
+
+impl Binding {
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => key.name().to_uppercase(),
+            Binding::MouseButton(button) => format!("MOUSE {}", pointer_button_name(*button).to_uppercase()),
+            Binding::MouseX => "MOUSE X".to_string(),
+            Binding::MouseY => "MOUSE Y".to_string(),
+        }
+    }
+}
+
+fn pointer_button_name(button: PointerButton) -> &'static str {
+    match button {
+        PointerButton::Primary => "Primary",
+        PointerButton::Secondary => "Secondary",
+        PointerButton::Middle => "Middle",
+        PointerButton::Extra1 => "Extra1",
+        PointerButton::Extra2 => "Extra2",
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> Self {
+        match binding {
+            Binding::Key(key) => format!("Key:{}", key.name()),
+            Binding::MouseButton(button) => format!("Mouse:{}", pointer_button_name(button)),
+            Binding::MouseX => "MouseX".to_string(),
+            Binding::MouseY => "MouseY".to_string(),
+        }
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.split_once(':') {
+            Some(("Key", name)) => Key::from_name(name)
+                .map(Binding::Key)
+                .ok_or(format!("Unknown key {}", name)),
+            Some(("Mouse", name)) => [
+                PointerButton::Primary,
+                PointerButton::Secondary,
+                PointerButton::Middle,
+                PointerButton::Extra1,
+                PointerButton::Extra2,
+            ]
+                .into_iter()
+                .find(|button| pointer_button_name(*button) == name)
+                .map(Binding::MouseButton)
+                .ok_or(format!("Unknown mouse button {}", name)),
+            None if value == "MouseX" => Ok(Binding::MouseX),
+            None if value == "MouseY" => Ok(Binding::MouseY),
+            _ => Err(format!("Unknown binding {}", value)),
+        }
+    }
+}
+
+/// A binding for an action along with how much to scale its value by, e.g. mouse sensitivity or
+/// a negative scale to invert an axis.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ActionBinding {
+    pub input: Binding,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl ActionBinding {
+    pub fn new(input: Binding) -> Self {
+        Self { input, scale: 1.0 }
+    }
+}
+
+/// # InputBindings
+/// Maps each action to any number of physical inputs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InputBindings {
+    pub actions: BTreeMap<Action, Vec<ActionBinding>>,
+    /// Each player's own driving and fire keys for local two player games, sharing the keyboard.
+    /// They're separate from `actions` so the players don't drive each other's tanks.
+    #[serde(default = "default_players")]
+    pub players: Vec<BTreeMap<Action, Vec<ActionBinding>>>,
+}
+
+/// WASD and space for the first player, the arrow keys and enter for the second.
+fn default_players() -> Vec<BTreeMap<Action, Vec<ActionBinding>>> {
+    let keys = |keys: [Key; 5]| {
+        let actions = [Action::MoveForward, Action::MoveBackward, Action::StrafeLeft, Action::StrafeRight, Action::Fire];
+
+        actions.into_iter().zip(keys).map(|(action, key)| (action, vec![ActionBinding::new(Binding::Key(key))])).collect()
+    };
+
+    vec![
+        keys([Key::W, Key::S, Key::A, Key::D, Key::Space]),
+        keys([Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight, Key::Enter]),
+    ]
+}
+
+/// Most bindings an action can have from the rebinding screen before the oldest is replaced.
+const MAX_BINDINGS_PER_ACTION: usize = 2;
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let key = |key| ActionBinding::new(Binding::Key(key));
+        let mouse = |button| ActionBinding::new(Binding::MouseButton(button));
+
+        let actions = BTreeMap::from([
+            (Action::MoveForward, vec![key(Key::W), key(Key::ArrowUp)]),
+            (Action::MoveBackward, vec![key(Key::S), key(Key::ArrowDown)]),
+            (Action::StrafeLeft, vec![key(Key::A)]),
+            (Action::StrafeRight, vec![key(Key::D)]),
+            (Action::MoveUp, vec![key(Key::E)]),
+            (Action::MoveDown, vec![key(Key::Q)]),
+            (Action::Look, vec![mouse(PointerButton::Primary)]),
+            (Action::LookHorizontal, vec![ActionBinding { input: Binding::MouseX, scale: 0.2 }]),
+            (Action::LookVertical, vec![ActionBinding { input: Binding::MouseY, scale: -0.2 }]),
+            (Action::Fire, vec![key(Key::Space), mouse(PointerButton::Secondary)]),
+            (Action::MenuUp, vec![key(Key::ArrowUp), key(Key::W)]),
+            (Action::MenuDown, vec![key(Key::ArrowDown), key(Key::S)]),
+            (Action::MenuSelect, vec![key(Key::Enter), key(Key::Space)]),
+            (Action::Back, vec![key(Key::Escape)]),
+            (Action::ToggleRecording, vec![key(Key::F9)]),
+            (Action::ExportFrame, vec![key(Key::F10)]),
+            (Action::SwitchCamera, vec![key(Key::C)]),
+            (Action::ToggleConsole, vec![key(Key::Backtick)]),
+            (Action::ToggleStats, vec![key(Key::F3)]),
+        ]);
+
+        Self { actions, players: default_players() }
+    }
+}
+
+impl InputBindings {
+    /// # load_or_default
+    /// Loads the bindings from the given file. If there isn't one yet, or it can't be read, the
+    /// defaults are used instead. Actions missing from the file (e.g. added since it was saved)
+    /// get their default bindings.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let mut bindings: Self = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+
+        for (action, defaults) in Self::default().actions {
+            bindings.actions.entry(action).or_insert(defaults);
+        }
+
+        bindings
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn bindings(&self, action: Action) -> &[ActionBinding] {
+        self.actions.get(&action).map(|bindings| bindings.as_slice()).unwrap_or(&[])
+    }
+
+    /// # bind
+    /// Adds a binding to the action. If the action already has the maximum number of bindings the
+    /// oldest one is dropped to make room.
+    pub fn bind(&mut self, action: Action, input: Binding) {
+        let bindings = self.actions.entry(action).or_default();
+
+        if bindings.iter().any(|binding| binding.input == input) {
+            return;
+        }
+
+        if bindings.len() >= MAX_BINDINGS_PER_ACTION {
+            bindings.remove(0);
+        }
+
+        bindings.push(ActionBinding::new(input));
+    }
+
+    /// Removes every binding from the action. It's kept as an empty list so loading doesn't put
+    /// the defaults back.
+    pub fn clear(&mut self, action: Action) {
+        self.actions.insert(action, Vec::new());
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_binding_round_trip
+    /// Bindings are saved as strings and should come back the same.
+    fn test_binding_round_trip() {
+        for binding in [Binding::Key(Key::ArrowUp), Binding::MouseButton(PointerButton::Secondary), Binding::MouseY] {
+            let json = serde_json::to_string(&binding).unwrap();
+            assert_eq!(serde_json::from_str::<Binding>(&json).unwrap(), binding);
+        }
+
+        assert_eq!(serde_json::to_string(&Binding::Key(Key::W)).unwrap(), "\"Key:W\"");
+    }
+
+    #[test]
+    /// # test_unknown_binding
+    fn test_unknown_binding() {
+        assert!(serde_json::from_str::<Binding>("\"Key:NotAKey\"").is_err());
+        assert!(serde_json::from_str::<Binding>("\"Joystick\"").is_err());
+    }
+
+    #[test]
+    /// # test_bindings_round_trip
+    fn test_bindings_round_trip() {
+        let bindings = InputBindings::default();
+        let json = serde_json::to_string(&bindings).unwrap();
+
+        assert_eq!(serde_json::from_str::<InputBindings>(&json).unwrap(), bindings);
+    }
+
+    #[test]
+    /// # test_scale_defaults_to_one
+    fn test_scale_defaults_to_one() {
+        let binding: ActionBinding = serde_json::from_str("{\"input\": \"Key:Q\"}").unwrap();
+
+        assert_eq!(binding.scale, 1.0);
+    }
+
+    #[test]
+    /// # test_bind_replaces_oldest
+    fn test_bind_replaces_oldest() {
+        let mut bindings = InputBindings::default();
+        bindings.bind(Action::MoveForward, Binding::Key(Key::I));
+
+        let inputs: Vec<Binding> = bindings.bindings(Action::MoveForward).iter().map(|b| b.input).collect();
+        assert_eq!(inputs, vec![Binding::Key(Key::ArrowUp), Binding::Key(Key::I)]);
+    }
+
+    #[test]
+    /// # test_load_missing_file_gives_defaults
+    fn test_load_missing_file_gives_defaults() {
+        assert_eq!(InputBindings::load_or_default("does/not/exist.json"), InputBindings::default());
+    }
+}
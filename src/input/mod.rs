@@ -0,0 +1,3 @@
+pub mod bindings;
+pub mod actions;
+pub mod recording;
\ No newline at end of file
@@ -0,0 +1,319 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::str::{from_utf8, FromStr};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+
+const MAGIC: &str = "vectar-recording";
+const VERSION: u32 = 2;
+
+/// Where the camera was when recording started, so a replay starts from the same place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CameraStart {
+    pub position: Point3D,
+    pub rotation: EulerAngles,
+    pub rotation_vector: Vector3D,
+}
+
+impl CameraStart {
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self {
+            position: camera.position.clone(),
+            rotation: camera.rotation,
+            rotation_vector: camera.rotation_vector.clone(),
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.position.clone();
+        camera.rotation = self.rotation;
+        camera.rotation_vector = self.rotation_vector.clone();
+    }
+}
+
+/// One frame of input: how long the frame was and the actions that were active.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub delta_time: f32,
+    pub actions: ActionState,
+}
+
+/// # Recording
+/// Per frame actions and delta times captured while playing, which can be replayed to drive the
+/// simulation exactly as it ran. Used to reproduce bugs, for the attract mode demo and for
+/// regression tests. Recording always starts a level over, so a replay only needs the map and
+/// where the camera was to start from the same state.
+///
+/// Saved as text, one line per run of identical frames so long stretches of holding a key cost a
+/// single line:
+///
+/// ```text
+/// vectar-recording 2
+/// map maps/level1.json
+/// camera 25 0 0 0 1.5707964 0 -1 0 0
+/// 120 0.016666668 MoveForward=1
+/// 1 0.016666668 MoveForward=1 !Fire
+/// ```
+///
+/// Each frame line is `<count> <delta time> [Action=value ...] [!Action ...]` where `!` marks an
+/// action that was pressed that frame. Lines starting with `#` are comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// The map file under `assets/` the recording was made in.
+    pub map: String,
+    pub camera_start: CameraStart,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn new(map: &str, camera: &Camera) -> Self {
+        Self {
+            map: map.to_string(),
+            camera_start: CameraStart::from_camera(camera),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Records a frame. Only the gameplay actions are kept, the pointer is for menus.
+    pub fn push(&mut self, delta_time: f32, actions: &ActionState) {
+        self.frames.push(RecordedFrame { delta_time, actions: actions.gameplay_only() });
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+
+    /// Loads a recording baked into the assets, e.g. the attract mode demo.
+    pub fn load_asset(file_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let recording = Asset::get(file_name).ok_or(format!("Failed to load {}", file_name))?;
+
+        Ok(from_utf8(&recording.data)?.parse()?)
+    }
+
+    fn write_frame(output: &mut String, count: usize, frame: &RecordedFrame) {
+        let _ = write!(output, "{} {}", count, frame.delta_time);
+
+        for (action, value) in frame.actions.values() {
+            let _ = write!(output, " {}={}", action.name(), value);
+        }
+
+        for action in frame.actions.pressed_actions() {
+            let _ = write!(output, " !{}", action.name());
+        }
+
+        output.push('\n');
+    }
+
+    fn parse_frame(line: &str) -> Result<(usize, RecordedFrame), String> {
+        let mut parts = line.split_whitespace();
+
+        let count = parts.next().and_then(|part| part.parse().ok()).ok_or(format!("Bad frame count in '{}'", line))?;
+        let delta_time = parts.next().and_then(|part| part.parse().ok()).ok_or(format!("Bad delta time in '{}'", line))?;
+
+        let mut actions = ActionState::default();
+
+        for part in parts {
+            if let Some(name) = part.strip_prefix('!') {
+                let action = Action::from_name(name).ok_or(format!("Unknown action {}", name))?;
+                let value = actions.value(action);
+                actions.set(action, value, true);
+            } else {
+                let (name, value) = part.split_once('=').ok_or(format!("Bad action '{}'", part))?;
+                let action = Action::from_name(name).ok_or(format!("Unknown action {}", name))?;
+                let value = value.parse().map_err(|_| format!("Bad value '{}'", part))?;
+                let pressed = actions.pressed(action);
+                actions.set(action, value, pressed);
+            }
+        }
+
+        Ok((count, RecordedFrame { delta_time, actions }))
+    }
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut output = format!("{} {}\n", MAGIC, VERSION);
+
+        let _ = writeln!(output, "map {}", self.map);
+
+        let start = &self.camera_start;
+        let _ = writeln!(
+            output,
+            "camera {} {} {} {} {} {} {} {} {}",
+            start.position.x, start.position.y, start.position.z,
+            start.rotation.pitch, start.rotation.yaw, start.rotation.roll,
+            start.rotation_vector.x, start.rotation_vector.y, start.rotation_vector.z,
+        );
+
+        // Run length encode identical frames
+        let mut frames = self.frames.iter().peekable();
+        while let Some(frame) = frames.next() {
+            let mut count = 1;
+            while frames.peek() == Some(&frame) {
+                frames.next();
+                count += 1;
+            }
+
+            Self::write_frame(&mut output, count, frame);
+        }
+
+        f.write_str(&output)
+    }
+}
+
+impl FromStr for Recording {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        let header = lines.next().ok_or("Empty recording")?;
+        if header != format!("{} {}", MAGIC, VERSION) {
+            return Err(format!("Not a version {} recording: '{}'", VERSION, header));
+        }
+
+        let map = lines.next().and_then(|line| line.strip_prefix("map ")).ok_or("Missing map line")?.to_string();
+
+        let camera = lines.next().and_then(|line| line.strip_prefix("camera ")).ok_or("Missing camera line")?;
+        let values: Vec<f32> = camera
+            .split_whitespace()
+            .map(|value| value.parse().map_err(|_| format!("Bad camera value '{}'", value)))
+            .collect::<Result<_, _>>()?;
+
+        let [x, y, z, pitch, yaw, roll, vx, vy, vz] = values[..] else {
+            return Err(format!("Expected 9 camera values, got {}", values.len()));
+        };
+
+        let camera_start = CameraStart {
+            position: Point3D { x, y, z },
+            rotation: EulerAngles { pitch, yaw, roll },
+            rotation_vector: Vector3D { x: vx, y: vy, z: vz },
+        };
+
+        let mut frames = Vec::new();
+        for line in lines {
+            let (count, frame) = Recording::parse_frame(line)?;
+            frames.extend(std::iter::repeat_n(frame, count));
+        }
+
+        Ok(Self { map, camera_start, frames })
+    }
+}
+
+/// # Replay
+/// Steps through a recording one frame at a time.
+pub struct Replay {
+    recording: Recording,
+    index: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self { recording, index: 0 }
+    }
+
+    pub fn map(&self) -> &str {
+        &self.recording.map
+    }
+
+    pub fn camera_start(&self) -> &CameraStart {
+        &self.recording.camera_start
+    }
+
+    /// The next recorded frame, or `None` once the recording has run out.
+    pub fn next_frame(&mut self) -> Option<&RecordedFrame> {
+        let frame = self.recording.frames.get(self.index);
+        self.index += 1;
+        frame
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 1.0, y: 2.0, z: 3.0 }, 1000.0)
+    }
+
+    fn actions(forward: f32, fire: bool) -> ActionState {
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveForward, forward, false);
+        actions.set(Action::Fire, if fire { 1.0 } else { 0.0 }, fire);
+        actions
+    }
+
+    #[test]
+    /// # test_round_trip
+    fn test_round_trip() {
+        let mut recording = Recording::new("maps/level1.json", &camera());
+        recording.push(1.0 / 60.0, &actions(1.0, false));
+        recording.push(1.0 / 60.0, &actions(1.0, false));
+        recording.push(1.0 / 30.0, &actions(-0.5, true));
+        recording.push(1.0 / 60.0, &ActionState::default());
+
+        let parsed: Recording = recording.to_string().parse().unwrap();
+
+        assert_eq!(parsed, recording);
+    }
+
+    #[test]
+    /// # test_identical_frames_share_a_line
+    fn test_identical_frames_share_a_line() {
+        let mut recording = Recording::new("maps/level1.json", &camera());
+        for _ in 0..100 {
+            recording.push(0.016, &actions(1.0, false));
+        }
+
+        let text = recording.to_string();
+
+        assert_eq!(text.lines().count(), 4);
+        assert!(text.ends_with("100 0.016 MoveForward=1\n"), "{}", text);
+    }
+
+    #[test]
+    /// # test_pointer_is_not_recorded
+    fn test_pointer_is_not_recorded() {
+        let mut state = actions(1.0, false);
+        state.pointer_moved = true;
+        state.clicked = true;
+
+        let mut recording = Recording::new("maps/level1.json", &camera());
+        recording.push(0.016, &state);
+
+        assert_eq!(recording.frames[0].actions, actions(1.0, false));
+    }
+
+    #[test]
+    /// # test_bad_recordings
+    fn test_bad_recordings() {
+        assert!("".parse::<Recording>().is_err());
+        assert!("vectar-recording 1\ncamera 0 0 0 0 0 0 0 0 1\n".parse::<Recording>().is_err());
+        assert!("vectar-recording 2\ncamera 0 0 0 0 0 0 0 0 1\n".parse::<Recording>().is_err());
+        assert!("vectar-recording 2\nmap maps/level1.json\ncamera 0 0 0\n".parse::<Recording>().is_err());
+        assert!("vectar-recording 2\nmap maps/level1.json\ncamera 0 0 0 0 0 0 0 0 1\n1 0.1 Jump=1\n".parse::<Recording>().is_err());
+    }
+
+    #[test]
+    /// # test_attract_demo_loads
+    fn test_attract_demo_loads() {
+        let recording = Recording::load_asset("demos/attract.rec").unwrap();
+
+        assert_eq!(recording.map, "maps/level1.json");
+        assert_eq!(recording.frames.len(), 1020);
+    }
+}
@@ -3,39 +3,186 @@ mod player;
 mod types;
 mod objects;
 mod utils;
+mod rendering;
+mod input;
+mod animation;
+mod scripting;
+mod console;
 
+use std::time::{SystemTime, UNIX_EPOCH};
 use eframe::{egui};
 use eframe::epaint::StrokeKind;
 use egui::{Color32, Pos2, Rect, Stroke, Vec2};
-use crate::screens::{huds, title_screen, level1_screen};
-use crate::screens::traits::{HudRenderer, ScreenRenderer};
+use crate::console::Console;
+use crate::console::commands::CommandHandler;
+use crate::console::log_overlay::LogOverlay;
+use crate::screens::{huds, title_screen, level_select_screen, options_screen, level1_screen, versus_screen};
+use crate::screens::traits::{HudRenderer, ScreenRenderer, ScreenTransition};
 use crate::player::camera::Camera;
+use crate::input::actions::ActionState;
+use crate::input::bindings::{Action, InputBindings, BINDINGS_FILE};
+use crate::input::recording::{Recording, Replay};
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::crt::Crt;
+use crate::rendering::depth_cue::DepthCue;
+use crate::rendering::debug_overlay::DebugOverlay;
+use crate::rendering::raster::Raster;
+use crate::rendering::svg::SvgFrame;
+use crate::rendering::viewport::{Layout, View, ViewportFrame, Views};
 use crate::types::geometry::*;
+use crate::utils::{logging, profiling};
+use crate::utils::settings::{Settings, SETTINGS_FILE};
 
 const SCREEN_WIDTH: f32 = 1024.0;
 const SCREEN_HEIGHT: f32 = 768.0;
 
+/// Where exported frames are saved, relative to the working directory.
+const EXPORTS_FOLDER: &str = "exports";
+
 struct Game {
     hud: Box<dyn HudRenderer>,
     current_screen:  Box<dyn ScreenRenderer>,
     camera: Camera,
+    bindings: InputBindings,
+    crt: Crt,
+    /// How the levels fade with distance, from the settings.
+    depth_cue: DepthCue,
+    console: Console,
+    log_overlay: LogOverlay,
+    debug_overlay: DebugOverlay,
+    views: Views,
 }
 
 impl Game {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Game {
-        let camera = player::camera::Camera::new(
+    fn new(_cc: &eframe::CreationContext<'_>, replay: Option<Replay>) -> Game {
+        let settings = Settings::load_or_default(SETTINGS_FILE);
+        let mut game = Game {
+            hud: Box::new(huds::TitleHud::new()),
+            current_screen: Box::new(title_screen::TitleScreen::new()),
+            camera: Self::level_start_camera(Rectangle { width: SCREEN_WIDTH, height: SCREEN_HEIGHT }),
+            bindings: InputBindings::load_or_default(BINDINGS_FILE),
+            crt: Crt::new(settings.crt),
+            depth_cue: settings.depth_cue,
+            console: Console::new(),
+            log_overlay: LogOverlay::default(),
+            debug_overlay: DebugOverlay::default(),
+            views: Views::default(),
+        };
+
+        game.console.bind_toggle(&game.bindings);
+
+        if let Some(replay) = replay {
+            game.start_replay(replay, false);
+        }
+
+        game
+    }
+
+    /// Plays a recording back in the level it was made in, starting the camera where the
+    /// recording started. The attract demo stops on any key, other replays only on Back.
+    fn start_replay(&mut self, replay: Replay, attract: bool) {
+        self.camera = Self::level_start_camera(self.camera.viewport.clone());
+        replay.camera_start().apply(&mut self.camera);
+        let screen = if attract {
+            level1_screen::Level1Screen::with_attract(replay, self.depth_cue.clone())
+        } else {
+            level1_screen::Level1Screen::with_replay(replay, self.depth_cue.clone())
+        };
+        self.current_screen = Box::new(screen.expect("Failed to start replay"));
+        self.hud = Box::new(huds::GameHud::new());
+        self.connect_to_screen();
+    }
+
+    /// Lets the HUD and console hear about what happens on the current screen.
+    fn connect_to_screen(&mut self) {
+        if let Some(bus) = self.current_screen.bus() {
+            self.hud.connect(bus);
+            self.console.connect(bus);
+        }
+    }
+
+    fn disconnect_from_screen(&mut self) {
+        if let Some(bus) = self.current_screen.bus() {
+            self.hud.disconnect(bus);
+            self.console.disconnect(bus);
+        }
+    }
+
+    /// Where the player starts when a level is (re)started.
+    fn level_start_camera(viewport: Rectangle) -> Camera {
+        player::camera::Camera::new(
             types::geometry::Point3D { x: 25.0, y: 0.0, z: 0.0 },
             EulerAngles { pitch: 0.0, yaw: 90.0_f32.to_radians(), roll: 0.0 },
             Vector3D{x: -1.0, y: 0.0, z: 0.0},
             90.0,
-            Rectangle { width: SCREEN_WIDTH, height: SCREEN_HEIGHT },
+            viewport,
             1000.0,
-        );
+        )
+    }
 
-        Game {
-            hud: Box::new(huds::TitleHud::new()),
-            current_screen: Box::new(level1_screen::Level1Screen::new()),
-            camera,
+    /// Swap to the screen (and matching HUD) a screen has asked for.
+    fn apply_transition(&mut self, ctx: &egui::Context, transition: ScreenTransition) {
+        // The bindings and settings may have been changed on the options screen
+        if let Some((bindings, settings)) = self.current_screen.edited() {
+            self.bindings = bindings.clone();
+            self.console.bind_toggle(&self.bindings);
+            self.crt.settings = settings.crt;
+            self.depth_cue = settings.depth_cue.clone();
+        }
+        self.disconnect_from_screen();
+
+        match transition {
+            ScreenTransition::Title => {
+                self.current_screen = Box::new(title_screen::TitleScreen::new());
+                self.hud = Box::new(huds::TitleHud::new());
+            }
+            ScreenTransition::LevelSelect => {
+                self.current_screen = Box::new(level_select_screen::LevelSelectScreen::new());
+                self.hud = Box::new(huds::TitleHud::new());
+            }
+            ScreenTransition::Options => {
+                self.current_screen = Box::new(options_screen::OptionsScreen::new());
+                self.hud = Box::new(huds::TitleHud::new());
+            }
+            ScreenTransition::Map(map) | ScreenTransition::Record(map) => {
+                self.camera = Self::level_start_camera(self.camera.viewport.clone());
+                let depth_cue = self.depth_cue.clone();
+                self.current_screen = Box::new(match transition {
+                    ScreenTransition::Record(_) => level1_screen::Level1Screen::with_recording(map, &self.camera, depth_cue),
+                    _ => level1_screen::Level1Screen::with_map(map, depth_cue),
+                });
+                self.hud = Box::new(huds::GameHud::new());
+                self.connect_to_screen();
+            }
+            ScreenTransition::Versus => {
+                self.current_screen = Box::new(versus_screen::VersusScreen::new(&self.camera, self.depth_cue.clone()));
+                self.hud = Box::new(huds::VersusHud::new());
+                self.connect_to_screen();
+            }
+            ScreenTransition::AttractDemo => {
+                let recording = Recording::load_asset("demos/attract.rec").expect("Failed to load attract demo");
+
+                self.start_replay(Replay::new(recording), true);
+            }
+            ScreenTransition::Quit => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+        }
+    }
+
+    /// # export_frame
+    /// Renders the current frame again as SVG and saves it to `exports/<unix time>.svg`.
+    fn export_frame(&self) {
+        let mut frame = SvgFrame::new(self.camera.viewport.width, self.camera.viewport.height, Color32::BLACK);
+        render_game(self.views.layout, &self.camera, self.current_screen.as_ref(), self.hud.as_ref(), &mut frame);
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or_default();
+        let path = format!("{}/{}.svg", EXPORTS_FOLDER, seconds);
+
+        // Failing to export isn't worth stopping the game over
+        match frame.save(&path) {
+            Ok(()) => self.console.print(&format!("Exported {}", path), false),
+            Err(error) => self.console.print(&format!("Failed to export {}: {}", path, error), true),
         }
     }
 }
@@ -50,41 +197,46 @@ impl eframe::App for Game {
 
         self.camera.update_viewport_size(Rectangle{width: current_window_size.x, height: current_window_size.y});
 
+        let delta_time = ctx.input(|i| i.stable_dt);
 
-        ctx.input(|input| {
-            if let Some(pos) = input.pointer.latest_pos() {
-                // Use pos.x, pos.y for absolute mouse position
-            }
-            let mouse_delta = input.pointer.delta();
-            if mouse_delta != egui::Vec2::ZERO && input.pointer.primary_down() {
-                // Use mouse_delta.x, mouse_delta.y to adjust camera (e.g., yaw/pitch)
-                self.camera.rotate(mouse_delta.x / 5.0, -mouse_delta.y / 5.0);
-            }
-            if input.pointer.button_clicked(egui::PointerButton::Primary) {
-                // Handle left-click (e.g., shoot or select)
-                println!("Click");
-            }
+        // Everything measured since the last update is the last frame
+        self.debug_overlay.update(delta_time, profiling::take_frame());
 
-            if input.key_down(egui::Key::W) {
-                // Move camera forward
-                self.camera.move_forward(0.1);
-            }
+        let actions = ctx.input(|input| ActionState::from_input(input, &self.bindings));
 
-            if input.key_down(egui::Key::A) {
-                self.camera.move_strafe(0.1);
-            }
+        // Once it's open the console has the keyboard, Escape closes it
+        if !self.console.open && actions.pressed(Action::ToggleConsole) {
+            self.console.toggle();
+        }
 
-            if input.key_down(egui::Key::S) {
-                self.camera.move_forward(-0.1);
-            }
+        // While the console's open the keyboard types into it rather than playing
+        let actions = if self.console.open {
+            let events = ctx.input(|input| input.events.clone());
+            let mut handlers: Vec<&mut dyn CommandHandler> = vec![&mut self.camera, &mut self.log_overlay, &mut self.views];
+            handlers.extend(self.current_screen.commands());
+            self.console.handle_events(&events, &mut handlers);
 
-            if input.key_down(egui::Key::D) {
-                self.camera.move_strafe(-0.1);
-            }
-            
-            
-        });
+            ActionState::default()
+        } else {
+            actions
+        };
 
+        // The screen handles its own input, including moving the player (camera)
+        let transition = self.current_screen.update(&mut self.camera, &actions, delta_time);
+
+        if let Some(transition) = transition {
+            self.apply_transition(ctx, transition);
+        }
+
+        self.hud.update(&self.camera, self.current_screen.entities(), delta_time);
+
+        if actions.pressed(Action::ExportFrame) {
+            self.export_frame();
+        }
+
+        if actions.pressed(Action::ToggleStats) {
+            self.debug_overlay.open = !self.debug_overlay.open;
+        }
 
         // Create a central panel that fills the window
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -92,22 +244,135 @@ impl eframe::App for Game {
           //  let canvas_size = current_window_size; // Or adjust based on your needs
           //  let canvas_rect = Rect::from_min_size(ui.min_rect().min, canvas_size);
 
-            // Get the painter for custom drawing
-            let painter = ui.painter();
+            // Get the painter for custom drawing, everything goes through the CRT effects on its way
+            let mut painter = ui.painter().clone();
+            let mut frame = self.crt.begin_frame(&mut painter, delta_time);
 
-            //TODO: Move player (camera)
-
-            // Screen is rendered first
-            self.current_screen.render(&self.camera, painter);
-            
-            // HUD is last
-            self.hud.render(&self.camera, painter);
+            render_game(self.views.layout, &self.camera, self.current_screen.as_ref(), self.hud.as_ref(), &mut frame);
+            self.debug_overlay.render(&self.camera, &mut frame);
+            self.log_overlay.render(&self.camera, &mut frame);
+            self.console.render(&self.camera, &mut frame);
         });
     }
 }
 
 
+/// # render_frame
+/// Draws everything for a frame: the screen's background first, then the screen and the HUD last
+/// so it's on top.
+fn render_frame(camera: &Camera, screen: &dyn ScreenRenderer, hud: &dyn HudRenderer, backend: &mut dyn RenderBackend) {
+    render_scene(camera, screen, backend);
+    hud.render(camera, backend);
+}
+
+fn render_scene(camera: &Camera, screen: &dyn ScreenRenderer, backend: &mut dyn RenderBackend) {
+    if let Some(background) = screen.background() {
+        background.render(camera, backend);
+    }
+
+    screen.render(camera, backend);
+}
+
+/// # render_game
+/// Draws the screen through its own views if it has them, otherwise through the game's `layout`
+/// if the screen uses it, or the whole window from `window` if it doesn't.
+fn render_game(layout: Layout, window: &Camera, screen: &dyn ScreenRenderer, hud: &dyn HudRenderer, backend: &mut dyn RenderBackend) {
+    if let Some(views) = screen.views(&window.viewport) {
+        render_screen_views(&views, window, screen, hud, backend);
+        return;
+    }
+
+    let layout = if screen.uses_layout() { layout } else { Layout::Single };
+    render_views(&layout.views(window, &window.viewport), screen, hud, backend);
+}
+
+/// # render_screen_views
+/// Draws each of the screen's own views with the screen's HUD for it, then what the screen and
+/// the game's HUD put over the whole window.
+fn render_screen_views(views: &[View], window: &Camera, screen: &dyn ScreenRenderer, hud: &dyn HudRenderer, backend: &mut dyn RenderBackend) {
+    for (index, view) in views.iter().enumerate() {
+        let mut frame = ViewportFrame::new(backend, view.rect);
+
+        render_scene(&view.camera, screen, &mut frame);
+        screen.render_view_hud(index, &view.camera, &mut frame);
+    }
+
+    screen.render_over_views(window, backend);
+    hud.render(window, backend);
+}
+
+/// # render_views
+/// Draws a frame for each view into its part of the window, in order. The player's own view gets
+/// the HUD, the others their labels. Labelled views are cleared first, as they can sit over the
+/// player's.
+fn render_views(views: &[View], screen: &dyn ScreenRenderer, hud: &dyn HudRenderer, backend: &mut dyn RenderBackend) {
+    for view in views {
+        let mut frame = ViewportFrame::new(backend, view.rect);
+
+        if view.label.is_some() {
+            view.clear(&mut frame);
+            render_scene(&view.camera, screen, &mut frame);
+            view.render_label(&mut frame);
+        } else {
+            render_frame(&view.camera, screen, hud, &mut frame);
+        }
+    }
+}
+
+/// The value following `name` on the command line, e.g. the path in `--replay <path>`.
+fn arg_value<'a>(args: &'a [String], name: &str) -> Option<&'a String> {
+    args.iter().position(|arg| arg == name).and_then(|index| args.get(index + 1))
+}
+
+/// # screenshot
+/// Renders the first frame of level 1 without opening a window and saves it as a PNG, or as a PPM
+/// or SVG depending on the path's extension. PNG and PPM get the player's CRT effects, SVG is the
+/// bare lines.
+fn screenshot(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut camera = Game::level_start_camera(Rectangle { width: SCREEN_WIDTH, height: SCREEN_HEIGHT });
+    let settings = Settings::load_or_default(SETTINGS_FILE);
+    let screen = level1_screen::Level1Screen::with_map(level1_screen::LEVEL1_MAP, settings.depth_cue);
+    screen.ground_camera(&mut camera);
+    let hud = huds::GameHud::new();
+
+    if path.ends_with(".svg") {
+        let mut frame = SvgFrame::new(SCREEN_WIDTH, SCREEN_HEIGHT, Color32::BLACK);
+        render_frame(&camera, &screen, &hud, &mut frame);
+
+        return frame.save(path);
+    }
+
+    let mut raster = Raster::new(SCREEN_WIDTH as usize, SCREEN_HEIGHT as usize, Color32::BLACK);
+    let mut crt = Crt::new(settings.crt);
+    let mut frame = crt.begin_frame(&mut raster, 0.0);
+    render_frame(&camera, &screen, &hud, &mut frame);
+
+    if path.ends_with(".ppm") {
+        raster.save_ppm(path)
+    } else {
+        raster.save_png(path)
+    }
+}
+
 fn main() -> Result<(), eframe::Error> {
+    let args: Vec<String> = std::env::args().collect();
+
+    // `VECTAR_LOG=info,camera=debug vectar` logs more from the camera, see `Filters::parse`
+    if let Err(error) = logging::init_from_env() {
+        eprintln!("Ignoring {}: {}", logging::LOG_ENV, error);
+    }
+
+    // `vectar --screenshot level1.png` renders a frame to a file and exits
+    if let Some(path) = arg_value(&args, "--screenshot") {
+        screenshot(path).expect("Failed to save screenshot");
+        return Ok(());
+    }
+
+    // `vectar --replay recordings/<file>.rec` plays a recording back instead of showing the title
+    let replay = arg_value(&args, "--replay")
+        .map(|path| Recording::load(path).expect("Failed to load recording"))
+        .map(Replay::new);
+
     // Set up the native window options
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
@@ -119,6 +384,64 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Vectar",
         native_options,
-        Box::new(|cc| Ok(Box::new(Game::new(cc)))),
+        Box::new(|cc| Ok(Box::new(Game::new(cc, replay)))),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::recorder::Recorder;
+
+    /// Records what's drawn, and where each opaque fill covered the lines drawn before it.
+    #[derive(Default)]
+    struct Layers {
+        recorder: Recorder,
+        fills: Vec<(Rect, usize)>,
+    }
+
+    impl RenderBackend for Layers {
+        fn line(&mut self, points: [Pos2; 2], stroke: Stroke) {
+            self.recorder.line(points, stroke);
+        }
+
+        fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+            if fill.is_opaque() {
+                self.fills.push((Rect::from_points(points), self.recorder.lines.len()));
+            }
+
+            self.recorder.polygon(points, fill, stroke);
+        }
+    }
+
+    #[test]
+    /// # test_picture_in_picture_hides_the_view_under_it
+    /// The rear view is drawn over the player's, so none of the player's lines can show through
+    /// it.
+    fn test_picture_in_picture_hides_the_view_under_it() {
+        let window = Rectangle { width: SCREEN_WIDTH, height: SCREEN_HEIGHT };
+        let mut camera = Game::level_start_camera(window.clone());
+        let screen = level1_screen::Level1Screen::new();
+        screen.ground_camera(&mut camera);
+        // Looking down, so the ground fills the top of the window too
+        camera.look_at(&Point3D { x: 15.0, y: -10.0, z: 0.0 });
+        let hud = huds::GameHud::new();
+        let views = Layout::PictureInPicture.views(&camera, &window);
+        let pip = views[1].rect.shrink(1.0);
+
+        let mut player = Recorder::default();
+        render_views(&views[..1], &screen, &hud, &mut player);
+
+        let mut output = Layers::default();
+        render_views(&views, &screen, &hud, &mut output);
+
+        let crosses = |[start, end]: [Pos2; 2]| (0..=20).any(|i| pip.contains(start.lerp(end, i as f32 / 20.0)));
+        let under: Vec<usize> = (0..player.lines.len()).filter(|&index| crosses(player.lines[index].0)).collect();
+        assert!(!under.is_empty(), "nothing of the player's view is under the rear view");
+        assert_eq!(output.recorder.lines[..player.lines.len()], player.lines[..]);
+
+        // A line is hidden by a fill over the rear view drawn after it
+        let hidden = |index: usize| output.fills.iter().any(|(rect, drawn)| *drawn > index && rect.expand(0.01).contains_rect(pip));
+        assert!(under.into_iter().all(hidden), "the player's view shows through the rear view");
+    }
+}
@@ -0,0 +1,267 @@
+use std::ops::Range;
+use crate::animation::track::TransformAnimation;
+use crate::objects::lod::Lod;
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+
+/// # Entity
+/// A placed object in a level. Wraps the mesh with the identifying data from the map file so that
+/// systems such as the radar can tell what kind of thing they are looking at.
+#[derive(Debug, Clone)]
+pub struct Entity {
+    pub id: String,
+    pub object_type: String,
+    pub mesh: Mesh,
+    /// The versions of the mesh's model to draw depending on how big it is on screen.
+    pub lod: Lod,
+    /// Moves the whole entity about, such as a moving platform.
+    pub animation: Option<EntityAnimation>,
+    /// Pieces of the model that move on their own, such as a turret.
+    pub parts: Vec<AnimatedPart>,
+    /// Radius of a circle across the ground around the entity's origin that contains all of it,
+    /// which is what bumps into other entities.
+    pub radius: f32,
+    /// A script file under `assets/` that moves the entity about every tick.
+    pub script: Option<String>,
+}
+
+impl Entity {
+    /// An entity that's always drawn at full detail.
+    pub fn new(id: String, object_type: String, mesh: Mesh) -> Self {
+        let lod = Lod::single(&mesh);
+        let radius = mesh.verts.iter().map(|vert| vert.x.hypot(vert.z)).fold(0.0, f32::max);
+
+        Self { id, object_type, mesh, lod, animation: None, parts: Vec::new(), radius, script: None }
+    }
+
+    /// Whether the two entities are close enough across the ground to bump into each other.
+    pub fn touches(&self, other: &Entity) -> bool {
+        let (x, z) = (self.mesh.position.x - other.mesh.position.x, self.mesh.position.z - other.mesh.position.z);
+
+        x.hypot(z) < self.radius + other.radius
+    }
+
+    /// Picks the level of detail for how big the entity is from the camera.
+    pub fn update_lod(&mut self, camera: &Camera) {
+        self.lod.update(camera, &self.mesh.position);
+    }
+
+    /// # animate
+    /// Moves the entity and its parts to where their animations have them `time` seconds into the
+    /// level.
+    pub fn animate(&mut self, time: f32) {
+        if let Some(animation) = &self.animation {
+            let (offset, rotation) = animation.animation.sample(time);
+
+            self.mesh.position = animation.home_position.clone() + offset;
+            self.mesh.rotation = EulerAngles {
+                pitch: animation.home_rotation.pitch + rotation.pitch,
+                yaw: animation.home_rotation.yaw + rotation.yaw,
+                roll: animation.home_rotation.roll + rotation.roll,
+            };
+        }
+
+        for part in &mut self.parts {
+            (part.offset, part.rotation) = part.animation.sample(time);
+        }
+    }
+
+    /// # render_mesh
+    /// The mesh to draw this frame, the current level of detail placed where the entity is with
+    /// its parts moved.
+    pub fn render_mesh(&self) -> Mesh {
+        let level = self.lod.current();
+        let mut verts = level.verts.clone();
+
+        for part in &self.parts {
+            part.apply(self.lod.current_index(), &mut verts);
+        }
+
+        Mesh {
+            verts,
+            faces: level.faces.clone(),
+            position: self.mesh.position.clone(),
+            rotation: self.mesh.rotation,
+        }
+    }
+}
+
+/// # EntityAnimation
+/// An animation for a whole entity, relative to where the level placed it.
+#[derive(Debug, Clone)]
+pub struct EntityAnimation {
+    animation: TransformAnimation,
+    home_position: Vector3D,
+    home_rotation: EulerAngles,
+}
+
+impl EntityAnimation {
+    /// Animates a mesh from where it is now.
+    pub fn new(mesh: &Mesh, animation: TransformAnimation) -> Self {
+        Self { animation, home_position: mesh.position.clone(), home_rotation: mesh.rotation }
+    }
+
+    /// Animates about somewhere else, for a copy of the entity.
+    pub fn move_home(&mut self, position: Vector3D) {
+        self.home_position = position;
+    }
+}
+
+/// # AnimatedPart
+/// Some of an entity's model that moves relative to the rest, turning about a pivot in the model's
+/// own space. Which vertices belong to it is worked out for every level of detail, so it still
+/// moves when the model is simplified.
+#[derive(Debug, Clone)]
+pub struct AnimatedPart {
+    /// For each level of detail, whether each of its vertices is part of this.
+    members: Vec<Vec<bool>>,
+    pivot: Point3D,
+    animation: TransformAnimation,
+    offset: Vector3D,
+    rotation: EulerAngles,
+}
+
+impl AnimatedPart {
+    /// # new
+    /// A part made of the full model's vertices in `verts`, such as an OBJ's objects. Vertices of
+    /// the coarser levels belong to the part if the nearest full model vertex does.
+    pub fn new(lod: &Lod, verts: &[Range<usize>], pivot: Point3D, animation: TransformAnimation) -> Self {
+        let full = &lod.levels()[0].verts;
+        let in_part: Vec<bool> = (0..full.len()).map(|index| verts.iter().any(|range| range.contains(&index))).collect();
+
+        let members = lod.levels()
+            .iter()
+            .enumerate()
+            .map(|(level_index, level)| {
+                if level_index == 0 {
+                    return in_part.clone();
+                }
+
+                level.verts
+                    .iter()
+                    .map(|vert| {
+                        let nearest = full
+                            .iter()
+                            .enumerate()
+                            .min_by(|(_, a), (_, b)| distance_squared(vert, a).total_cmp(&distance_squared(vert, b)))
+                            .map(|(index, _)| index);
+
+                        nearest.is_some_and(|index| in_part[index])
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Self {
+            members,
+            pivot,
+            animation,
+            offset: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        }
+    }
+
+    /// Holds the part at an offset and rotation, for parts driven by code rather than an animation.
+    pub fn set_pose(&mut self, offset: Vector3D, rotation: EulerAngles) {
+        (self.offset, self.rotation) = (offset, rotation);
+    }
+
+    /// Moves the part's vertices in a level of detail's vertices.
+    fn apply(&self, level: usize, verts: &mut [Point3D]) {
+        let Some(members) = self.members.get(level) else {
+            return;
+        };
+
+        let part = Mesh {
+            verts: verts
+                .iter()
+                .zip(members)
+                .filter(|(_, member)| **member)
+                .map(|(vert, _)| Point3D { x: vert.x - self.pivot.x, y: vert.y - self.pivot.y, z: vert.z - self.pivot.z })
+                .collect(),
+            faces: Vec::new(),
+            position: Vector3D::from(&self.pivot) + self.offset.clone(),
+            rotation: self.rotation,
+        };
+
+        let mut moved = part.get_transformed_verts().into_iter();
+
+        for (vert, member) in verts.iter_mut().zip(members) {
+            if *member && let Some(new_vert) = moved.next() {
+                *vert = new_vert;
+            }
+        }
+    }
+}
+
+/// # select_objects
+/// The vertex ranges of the named objects from a model's objects, as from `load_model_parts`.
+pub fn select_objects(objects: &[(String, Range<usize>)], names: &[String], model: &str) -> Result<Vec<Range<usize>>, String> {
+    names
+        .iter()
+        .map(|name| objects
+            .iter()
+            .find(|(object, _)| object == name)
+            .map(|(_, verts)| verts.clone())
+            .ok_or(format!("No object {} in {}", name, model)))
+        .collect()
+}
+
+fn distance_squared(a: &Point3D, b: &Point3D) -> f32 {
+    (a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::track::{Interpolation, Keyframe, Track};
+
+    /// Two unit squares' worth of vertices, one above the other.
+    fn stack() -> Mesh {
+        let square = |y: f32| [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, z)| Point3D { x, y, z });
+
+        Mesh {
+            verts: [square(0.0), square(2.0)].concat(),
+            faces: vec![],
+            position: Vector3D { x: 10.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        }
+    }
+
+    fn turn(time: f32, yaw: f32) -> Keyframe {
+        Keyframe { time, value: Vector3D { x: 0.0, y: yaw, z: 0.0 } }
+    }
+
+    #[test]
+    /// # test_part_turns_about_pivot
+    /// Only the part's vertices move, turning about the pivot, and the whole entity can move too.
+    fn test_part_turns_about_pivot() {
+        let mesh = stack();
+        let mut entity = Entity::new("turret".to_string(), "test".to_string(), mesh.clone());
+
+        let top = 4..8;
+        let spin = Track::new(Interpolation::Linear, vec![turn(0.0, 0.0), turn(1.0, std::f32::consts::PI)], false).unwrap();
+        entity.parts.push(AnimatedPart::new(
+            &entity.lod,
+            &[top],
+            Point3D { x: 1.0, y: 0.0, z: 0.0 },
+            TransformAnimation { position: None, rotation: Some(spin) },
+        ));
+
+        let lift = Track::new(Interpolation::Step, vec![Keyframe { time: 0.0, value: Vector3D { x: 0.0, y: 5.0, z: 0.0 } }], false).unwrap();
+        entity.animation = Some(EntityAnimation::new(&mesh, TransformAnimation { position: Some(lift), rotation: None }));
+
+        entity.animate(1.0);
+        let render = entity.render_mesh();
+
+        assert_eq!(render.position, Vector3D { x: 10.0, y: 5.0, z: 0.0 });
+        assert_eq!(render.verts[..4], mesh.verts[..4]);
+
+        // Half a turn about x = 1 takes x = -1 to x = 3 and flips z
+        let top = &render.verts[4];
+        assert!((top.x - 3.0).abs() < 1e-5 && (top.y - 2.0).abs() < 1e-5 && (top.z - 1.0).abs() < 1e-5, "{:?}", top);
+    }
+
+}
@@ -0,0 +1,393 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+
+/// How far past a switch point, as a fraction of it, the projected size has to go before the level
+/// of detail changes. Stops a model flickering between two levels when it sits right on the edge.
+const HYSTERESIS: f32 = 0.15;
+
+/// Meshes with more vertices than this get levels of detail generated when the level doesn't give
+/// any.
+pub const AUTO_LOD_MIN_VERTS: usize = 200;
+
+/// Generated levels of detail as (fraction of the vertices kept, projected radius in pixels below
+/// which it's used).
+pub const AUTO_LODS: [(f32, f32); 2] = [(0.3, 60.0), (0.08, 20.0)];
+
+thread_local! {
+    /// Meshes `simplify` has made, by the model they came from and their vertex count, so each
+    /// model's levels are only generated once however many entities use it.
+    static SIMPLIFIED: RefCell<HashMap<(String, usize), Mesh>> = RefCell::new(HashMap::new());
+}
+
+/// One version of a model.
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub verts: Vec<Point3D>,
+    pub faces: Vec<Face>,
+    /// This level is used while the model's projected bounding sphere has at least this radius in
+    /// pixels (give or take the hysteresis).
+    pub min_radius: f32,
+}
+
+/// # Lod
+/// Levels of detail for a model, from the full model down to the coarsest. Each frame `update`
+/// picks the level to draw from how big the model's bounding sphere is on screen.
+#[derive(Debug, Clone)]
+pub struct Lod {
+    levels: Vec<LodLevel>,
+    /// Radius of a sphere around the model's origin that contains every vertex of the full model.
+    bounding_radius: f32,
+    current: usize,
+}
+
+impl Lod {
+    /// Just the one level, always drawn at full detail.
+    pub fn single(mesh: &Mesh) -> Self {
+        Self::new(mesh, Vec::new())
+    }
+
+    /// # new
+    /// The full mesh plus coarser versions of it, each paired with the projected radius in pixels
+    /// below which it's used. They should get coarser as the radius gets smaller.
+    pub fn new(mesh: &Mesh, coarser: Vec<(Mesh, f32)>) -> Self {
+        let bounding_radius = mesh.radius();
+
+        let mut levels = vec![LodLevel { verts: mesh.verts.clone(), faces: mesh.faces.clone(), min_radius: 0.0 }];
+
+        for (lod_mesh, below_radius) in coarser {
+            levels.last_mut().unwrap().min_radius = below_radius;
+            levels.push(LodLevel { verts: lod_mesh.verts, faces: lod_mesh.faces, min_radius: 0.0 });
+        }
+
+        Self { levels, bounding_radius, current: 0 }
+    }
+
+    /// # generated
+    /// Builds the coarser levels of `model` with `simplify_cached`. `lods` are (fraction of the
+    /// vertices kept, projected radius below which it's used).
+    pub fn generated(model: &str, mesh: &Mesh, lods: &[(f32, f32)]) -> Self {
+        let coarser = lods
+            .iter()
+            .map(|(ratio, below_radius)| (simplify_cached(model, mesh, (mesh.verts.len() as f32 * ratio).round() as usize), *below_radius))
+            .collect();
+
+        Self::new(mesh, coarser)
+    }
+
+    /// Radius of a sphere around the model's origin that contains all of it.
+    pub fn bounding_radius(&self) -> f32 {
+        self.bounding_radius
+    }
+
+    pub fn current(&self) -> &LodLevel {
+        &self.levels[self.current]
+    }
+
+    /// Which of `levels` is `current`.
+    pub fn current_index(&self) -> usize {
+        self.current
+    }
+
+    /// Every level, from the full model down to the coarsest.
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// # projected_radius
+    /// Radius in pixels of the model's bounding sphere when it's at `position`. Infinite when the
+    /// camera is inside the sphere.
+    pub fn projected_radius(&self, camera: &Camera, position: &Vector3D) -> f32 {
+        let centre = Point3D { x: position.x, y: position.y, z: position.z };
+        let depth = camera.project(&vec![centre])[0].depth;
+
+        if depth <= self.bounding_radius {
+            return f32::INFINITY;
+        }
+
+        self.bounding_radius * camera.near_plane_distance / depth
+    }
+
+    /// # select
+    /// The level to use for a projected radius, starting from the current level. Only moves to a
+    /// finer level once the radius is comfortably past that level's switch point, and likewise
+    /// for coarser ones.
+    pub fn select(&self, radius: f32) -> usize {
+        let mut level = self.current.min(self.levels.len() - 1);
+
+        while level > 0 && radius >= self.levels[level - 1].min_radius * (1.0 + HYSTERESIS) {
+            level -= 1;
+        }
+
+        while level < self.levels.len() - 1 && radius < self.levels[level].min_radius * (1.0 - HYSTERESIS) {
+            level += 1;
+        }
+
+        level
+    }
+
+    pub fn update(&mut self, camera: &Camera, position: &Vector3D) {
+        self.current = self.select(self.projected_radius(camera, position));
+    }
+}
+
+/// # simplify
+/// Reduces a mesh to about `target_verts` vertices by collapsing edges, shortest first. Each
+/// collapse merges the two ends of an edge into a vertex at its midpoint, and faces that collapse
+/// to fewer than three distinct vertices are dropped. Vertices no face uses are removed.
+///
+/// Works in passes, each vertex collapses at most once per pass so the shortest edges are still
+/// the shortest once their neighbours have moved.
+pub fn simplify(mesh: &Mesh, target_verts: usize) -> Mesh {
+    let mut verts = mesh.verts.clone();
+
+    // OBJ indices start at 1, work with them from 0
+    let mut faces: Vec<Vec<usize>> = mesh.faces
+        .iter()
+        .map(|face| face.vert_indices.iter().map(|index| index - 1).collect())
+        .collect();
+
+    while verts.len() > target_verts {
+        let edges: BTreeSet<(usize, usize)> = faces
+            .iter()
+            .flat_map(|face| (0..face.len()).map(move |i| {
+                let (a, b) = (face[i], face[(i + 1) % face.len()]);
+                (a.min(b), a.max(b))
+            }))
+            .filter(|(a, b)| a != b)
+            .collect();
+
+        let length_sq = |&(a, b): &(usize, usize)| {
+            let (a, b): (&Point3D, &Point3D) = (&verts[a], &verts[b]);
+            (a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)
+        };
+
+        let mut edges: Vec<(usize, usize)> = edges.into_iter().collect();
+        edges.sort_by(|a, b| length_sq(a).total_cmp(&length_sq(b)));
+
+        let mut remap: Vec<usize> = (0..verts.len()).collect();
+        let mut touched = vec![false; verts.len()];
+        let mut remaining = verts.len();
+
+        for (a, b) in edges {
+            if remaining <= target_verts {
+                break;
+            }
+
+            if touched[a] || touched[b] {
+                continue;
+            }
+
+            verts[a] = Point3D {
+                x: (verts[a].x + verts[b].x) / 2.0,
+                y: (verts[a].y + verts[b].y) / 2.0,
+                z: (verts[a].z + verts[b].z) / 2.0,
+            };
+            remap[b] = a;
+            touched[a] = true;
+            touched[b] = true;
+            remaining -= 1;
+        }
+
+        if remaining == verts.len() {
+            break;
+        }
+
+        faces = faces
+            .into_iter()
+            .filter_map(|face| {
+                let mut merged: Vec<usize> = face.iter().map(|index| remap[*index]).collect();
+                merged.dedup();
+                while merged.len() > 1 && merged.first() == merged.last() {
+                    merged.pop();
+                }
+
+                let distinct: BTreeSet<usize> = merged.iter().copied().collect();
+                (distinct.len() >= 3).then_some(merged)
+            })
+            .collect();
+
+        (verts, faces) = compact(verts, faces);
+    }
+
+    Mesh {
+        verts,
+        faces: faces
+            .into_iter()
+            .map(|face| Face { vert_indices: face.into_iter().map(|index| index + 1).collect() })
+            .collect(),
+        position: mesh.position.clone(),
+        rotation: mesh.rotation,
+    }
+}
+
+/// # simplify_cached
+/// `simplify` for the mesh loaded from `model`, reusing the result when that model has already
+/// been simplified to `target_verts`. The position and rotation are `mesh`'s.
+pub fn simplify_cached(model: &str, mesh: &Mesh, target_verts: usize) -> Mesh {
+    let simplified = SIMPLIFIED.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry((model.to_string(), target_verts))
+            .or_insert_with(|| simplify(mesh, target_verts))
+            .clone()
+    });
+
+    Mesh { position: mesh.position.clone(), rotation: mesh.rotation, ..simplified }
+}
+
+/// Drops vertices no face uses and renumbers the faces to match.
+fn compact(verts: Vec<Point3D>, faces: Vec<Vec<usize>>) -> (Vec<Point3D>, Vec<Vec<usize>>) {
+    let mut new_index = vec![None; verts.len()];
+    let mut kept = Vec::new();
+
+    for face in &faces {
+        for &index in face {
+            if new_index[index].is_none() {
+                new_index[index] = Some(kept.len());
+                kept.push(verts[index].clone());
+            }
+        }
+    }
+
+    let faces = faces
+        .into_iter()
+        .map(|face| face.into_iter().map(|index| new_index[index].unwrap()).collect())
+        .collect();
+
+    (kept, faces)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screens::level1_screen::{load_model_faces, load_model_verts};
+
+    fn load_mesh(model_name: &str) -> Mesh {
+        let model_name = model_name.to_string();
+
+        Mesh {
+            verts: load_model_verts(&model_name),
+            faces: load_model_faces(&model_name),
+            position: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        }
+    }
+
+    fn assert_valid(mesh: &Mesh) {
+        for face in &mesh.faces {
+            assert!(face.vert_indices.len() >= 3);
+            assert!(face.vert_indices.iter().all(|index| (1..=mesh.verts.len()).contains(index)));
+        }
+    }
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0)
+    }
+
+    #[test]
+    /// # test_simplify_reduces_vertices
+    fn test_simplify_reduces_vertices() {
+        let tank = load_mesh("npcs/TankBody.obj");
+
+        for target in [300, 100] {
+            let simplified = simplify(&tank, target);
+
+            assert!(simplified.verts.len() <= target, "{} verts for a target of {}", simplified.verts.len(), target);
+            assert!(simplified.verts.len() >= target / 2, "{} verts for a target of {}", simplified.verts.len(), target);
+            assert!(simplified.faces.len() < tank.faces.len());
+            assert_valid(&simplified);
+        }
+    }
+
+    #[test]
+    /// # test_simplify_keeps_the_shape
+    /// Collapsing to midpoints shouldn't grow the model or shrink it to nothing.
+    fn test_simplify_keeps_the_shape() {
+        let tank = load_mesh("npcs/TankBody.obj");
+        let simplified = simplify(&tank, 100);
+
+        let extent = |mesh: &Mesh| mesh.verts.iter().map(|vert| vert.x.abs().max(vert.y.abs()).max(vert.z.abs())).fold(0.0, f32::max);
+
+        assert!(extent(&simplified) <= extent(&tank) + 1e-4);
+        assert!(extent(&simplified) > extent(&tank) * 0.5);
+    }
+
+    #[test]
+    /// # test_simplify_nothing_to_do
+    fn test_simplify_nothing_to_do() {
+        let cube = load_mesh("cube.obj");
+        let simplified = simplify(&cube, 8);
+
+        assert_eq!(simplified.verts, cube.verts);
+        assert_eq!(simplified.faces, cube.faces);
+    }
+
+    #[test]
+    /// # test_simplify_cube
+    fn test_simplify_cube() {
+        let simplified = simplify(&load_mesh("cube.obj"), 5);
+
+        assert!(simplified.verts.len() <= 5);
+        assert_valid(&simplified);
+    }
+
+    #[test]
+    /// # test_simplify_is_cached
+    /// The second time a model is simplified to the same size it comes from the cache, only
+    /// moved to the new mesh's position.
+    fn test_simplify_is_cached() {
+        let tank = load_mesh("npcs/TankBody.obj");
+        let first = simplify_cached("test_simplify_cached", &tank, 100);
+
+        let moved = Mesh { verts: Vec::new(), faces: Vec::new(), position: Vector3D { x: 5.0, y: 0.0, z: 0.0 }, ..tank.clone() };
+        let second = simplify_cached("test_simplify_cached", &moved, 100);
+
+        assert_eq!(second.verts, first.verts);
+        assert_eq!(second.faces, first.faces);
+        assert_eq!(second.position.x, 5.0);
+    }
+
+    #[test]
+    /// # test_select_with_hysteresis
+    /// Sitting right on a switch point doesn't flip between levels, it takes a clear change in
+    /// size.
+    fn test_select_with_hysteresis() {
+        let tank = load_mesh("npcs/TankBody.obj");
+        let mut lod = Lod::generated("npcs/TankBody.obj", &tank, &[(0.3, 60.0), (0.08, 20.0)]);
+        assert_eq!(lod.levels.len(), 3);
+
+        assert_eq!(lod.select(200.0), 0);
+        assert_eq!(lod.select(59.0), 0);
+        assert_eq!(lod.select(45.0), 1);
+        assert_eq!(lod.select(5.0), 2);
+
+        lod.current = 1;
+        assert_eq!(lod.select(61.0), 1);
+        assert_eq!(lod.select(19.0), 1);
+        assert_eq!(lod.select(70.0), 0);
+        assert_eq!(lod.select(16.0), 2);
+    }
+
+    #[test]
+    /// # test_update_by_distance
+    fn test_update_by_distance() {
+        let tank = load_mesh("npcs/TankBody.obj");
+        let mut lod = Lod::generated("npcs/TankBody.obj", &tank, &AUTO_LODS);
+
+        lod.update(&camera(), &Vector3D { x: 0.0, y: 0.0, z: 10.0 });
+        assert_eq!(lod.current, 0);
+        assert_eq!(lod.current().verts.len(), tank.verts.len());
+
+        lod.update(&camera(), &Vector3D { x: 0.0, y: 0.0, z: 900.0 });
+        assert_eq!(lod.current, 2);
+        assert!(lod.current().verts.len() < tank.verts.len() / 5);
+
+        // Behind the camera is never on screen, but shouldn't blow up either
+        lod.update(&camera(), &Vector3D { x: 0.0, y: 0.0, z: -50.0 });
+        assert_eq!(lod.current, 0);
+    }
+}
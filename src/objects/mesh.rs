@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
+use crate::profile_scope;
 use crate::types::geometry::*;
+use crate::utils::profiling::Timing;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Mesh {
@@ -10,9 +12,17 @@ pub struct Mesh {
 }
 
 impl Mesh {
+    /// Radius of a sphere around the mesh's origin that contains all of its vertices, however
+    /// it's rotated.
+    pub fn radius(&self) -> f32 {
+        self.verts.iter().map(|vert| Vector3D::from(vert).length()).fold(0.0, f32::max)
+    }
+
     /// Gets a Vec of points for this mesh that have been transformed according to position
     /// and rotation.
     pub fn get_transformed_verts(&self) -> Vec<Point3D> {
+        profile_scope!(Timing::Transform);
+
         let transformed_verts: &mut Vec<Point3D> = &mut Vec::new();
 
         for vert in &self.verts {
@@ -1 +1,6 @@
-pub mod mesh;
\ No newline at end of file
+pub mod mesh;
+pub mod entity;
+pub mod lod;
+pub mod particles;
+pub mod terrain;
+pub mod vehicle;
@@ -0,0 +1,443 @@
+use std::collections::BTreeSet;
+use std::f32::consts::TAU;
+use eframe::egui::{Color32, Stroke};
+use serde::{Deserialize, Serialize};
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::depth_cue::DepthCue;
+use crate::rendering::wireframe::draw_line_3d;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+use crate::utils::noise::Random;
+
+/// Most particles alive at once. Anything emitted past this replaces the oldest.
+const MAX_PARTICLES: usize = 4000;
+
+/// Pieces drawn round an expanding ring.
+const RING_SEGMENTS: usize = 32;
+
+/// Fraction of its life a particle has left when it starts to fade out.
+const FADE_START: f32 = 0.5;
+
+/// Fraction of its upward speed a particle keeps when it bounces off the ground.
+const BOUNCE: f32 = 0.3;
+
+/// Fraction of its sideways speed and spin a particle loses each time it hits the ground.
+const GROUND_FRICTION: f32 = 0.5;
+
+/// # EmitterSettings
+/// How something blows up, as loaded from an effect's JSON. Each kind of particle is optional.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct EmitterSettings {
+    /// Downward acceleration in world units per second per second.
+    pub gravity: f32,
+    /// RGB
+    pub colour: [u8; 3],
+    pub line_width: f32,
+    pub fragments: Option<FragmentSettings>,
+    pub sparks: Option<SparkSettings>,
+    pub rings: Vec<RingSettings>,
+}
+
+impl Default for EmitterSettings {
+    fn default() -> Self {
+        Self {
+            gravity: 9.8,
+            colour: [0, 255, 0],
+            line_width: 2.0,
+            fragments: None,
+            sparks: None,
+            rings: Vec::new(),
+        }
+    }
+}
+
+/// The model's own edges breaking off and tumbling away. Ranges are `[min, max]`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct FragmentSettings {
+    /// Most edges to break off, big models have some left out.
+    pub max: usize,
+    pub speed: [f32; 2],
+    /// Extra upward speed, so the pieces get thrown up rather than just out.
+    #[serde(default)]
+    pub lift: f32,
+    /// Radians per second.
+    pub spin: [f32; 2],
+    pub lifetime: [f32; 2],
+}
+
+/// Bright streaks flying out in every direction.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SparkSettings {
+    pub count: usize,
+    pub speed: [f32; 2],
+    pub lifetime: [f32; 2],
+    /// Seconds of travel each spark's streak shows.
+    pub trail: f32,
+}
+
+/// A flat ring growing out from the explosion.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RingSettings {
+    /// World units per second the radius grows.
+    pub speed: f32,
+    pub lifetime: f32,
+    /// Above the explosion's origin.
+    #[serde(default)]
+    pub height: f32,
+}
+
+impl EmitterSettings {
+    /// Loads an effect from the assets, e.g. `effects/tank_explosion.json`.
+    pub fn load_asset(file_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let effect = Asset::get(file_name).ok_or(format!("Failed to load {}", file_name))?;
+
+        Ok(serde_json::from_slice(&effect.data)?)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum ParticleShape {
+    /// A line `half` either side of the particle's position, turning `spin` radians per second
+    /// about `axis`.
+    Fragment { half: Vector3D, axis: Vector3D, spin: f32, angle: f32 },
+    Spark { trail: f32 },
+    Ring { speed: f32 },
+}
+
+#[derive(Debug, Clone)]
+struct Particle {
+    shape: ParticleShape,
+    position: Point3D,
+    velocity: Vector3D,
+    gravity: f32,
+    age: f32,
+    lifetime: f32,
+    stroke: Stroke,
+}
+
+impl Particle {
+    /// The stroke faded out over the end of the particle's life.
+    fn stroke(&self) -> Stroke {
+        let left = 1.0 - self.age / self.lifetime;
+
+        Stroke::new(self.stroke.width, self.stroke.color.gamma_multiply((left / FADE_START).min(1.0)))
+    }
+}
+
+/// # ParticleSystem
+/// Short lived lines for explosions and debris. Particles move with the game clock in `update`
+/// and are drawn through the camera like meshes, so everything comes out the same in replays.
+#[derive(Debug, Clone)]
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    random: Random,
+}
+
+fn scaled(vector: &Vector3D, by: f32) -> Vector3D {
+    Vector3D { x: vector.x * by, y: vector.y * by, z: vector.z * by }
+}
+
+impl ParticleSystem {
+    pub fn new(seed: u32) -> Self {
+        Self { particles: Vec::new(), random: Random::new(seed) }
+    }
+
+    fn emit(&mut self, particle: Particle) {
+        if self.particles.len() >= MAX_PARTICLES {
+            self.particles.remove(0);
+        }
+
+        self.particles.push(particle);
+    }
+
+    /// # explode
+    /// Blows `mesh` apart as `settings` describes, from wherever the mesh is.
+    pub fn explode(&mut self, settings: &EmitterSettings, mesh: &Mesh) {
+        let [r, g, b] = settings.colour;
+        let stroke = Stroke::new(settings.line_width, Color32::from_rgb(r, g, b));
+        let origin = Point3D { x: mesh.position.x, y: mesh.position.y, z: mesh.position.z };
+
+        if let Some(fragments) = &settings.fragments {
+            self.break_up(fragments, mesh, settings.gravity, stroke);
+        }
+
+        if let Some(sparks) = &settings.sparks {
+            // From the middle of the model rather than its base so they don't go into the ground
+            let verts = mesh.get_transformed_verts();
+            let count = verts.len().max(1) as f32;
+            let middle = verts.iter().fold(Point3D { x: 0.0, y: 0.0, z: 0.0 }, |total, vert| Point3D {
+                x: total.x + vert.x / count,
+                y: total.y + vert.y / count,
+                z: total.z + vert.z / count,
+            });
+
+            for _ in 0..sparks.count {
+                let speed = self.random.range(sparks.speed);
+                let direction = self.random.direction();
+                let lifetime = self.random.range(sparks.lifetime);
+
+                self.emit(Particle {
+                    shape: ParticleShape::Spark { trail: sparks.trail },
+                    position: middle.clone(),
+                    velocity: scaled(&direction, speed),
+                    gravity: settings.gravity,
+                    age: 0.0,
+                    lifetime,
+                    stroke,
+                });
+            }
+        }
+
+        for ring in &settings.rings {
+            self.emit(Particle {
+                shape: ParticleShape::Ring { speed: ring.speed },
+                position: Point3D { y: origin.y + ring.height, ..origin.clone() },
+                velocity: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+                gravity: 0.0,
+                age: 0.0,
+                lifetime: ring.lifetime,
+                stroke,
+            });
+        }
+    }
+
+    /// Turns the mesh's edges into fragments flying out from its middle.
+    fn break_up(&mut self, settings: &FragmentSettings, mesh: &Mesh, gravity: f32, stroke: Stroke) {
+        let verts = mesh.get_transformed_verts();
+
+        // Faces share edges, only break each off once
+        let mut edges = BTreeSet::new();
+        for face in &mesh.faces {
+            for i in 0..face.vert_indices.len() {
+                let (a, b) = (face.vert_indices[i], face.vert_indices[(i + 1) % face.vert_indices.len()]);
+                edges.insert((a.min(b), a.max(b)));
+            }
+        }
+
+        let step = edges.len().div_ceil(settings.max.max(1)).max(1);
+
+        for (a, b) in edges.into_iter().step_by(step) {
+            // OBJ indices start at 1
+            let (start, end) = (Vector3D::from(&verts[a - 1]), Vector3D::from(&verts[b - 1]));
+            let half = scaled(&start.subtract(&end), 0.5);
+            let centre = verts[a - 1].translate(&half);
+
+            // Mostly away from the middle of the model, with some scatter
+            let outward = mesh.position.subtract(&Vector3D::from(&centre));
+            let scatter = self.random.direction();
+            let direction = if outward.length() > f32::EPSILON { outward.normalise() + scaled(&scatter, 0.5) } else { scatter };
+
+            let speed = self.random.range(settings.speed);
+            let mut velocity = direction.set_length(speed);
+            velocity.y += settings.lift;
+
+            let axis = self.random.direction();
+            let spin = self.random.range(settings.spin);
+            let lifetime = self.random.range(settings.lifetime);
+
+            self.emit(Particle {
+                shape: ParticleShape::Fragment { half, axis, spin, angle: 0.0 },
+                position: centre,
+                velocity,
+                gravity,
+                age: 0.0,
+                lifetime,
+                stroke,
+            });
+        }
+    }
+
+    /// # update
+    /// Moves everything on by `delta_time` seconds and drops particles that have had their time.
+    /// Falling particles bounce off the ground at `ground_height(x, z)`.
+    pub fn update(&mut self, delta_time: f32, ground_height: impl Fn(f32, f32) -> f32) {
+        for particle in &mut self.particles {
+            particle.age += delta_time;
+            particle.velocity.y -= particle.gravity * delta_time;
+            particle.position = particle.position.translate(&scaled(&particle.velocity, delta_time));
+
+            if let ParticleShape::Fragment { spin, angle, .. } = &mut particle.shape {
+                *angle += *spin * delta_time;
+            }
+
+            let ground = ground_height(particle.position.x, particle.position.z);
+
+            if particle.gravity > 0.0 && particle.position.y < ground {
+                particle.position.y = ground;
+                particle.velocity.y = -particle.velocity.y * BOUNCE;
+                particle.velocity.x *= 1.0 - GROUND_FRICTION;
+                particle.velocity.z *= 1.0 - GROUND_FRICTION;
+
+                if let ParticleShape::Fragment { spin, .. } = &mut particle.shape {
+                    *spin *= 1.0 - GROUND_FRICTION;
+                }
+            }
+        }
+
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+    }
+
+    /// # render
+    /// Draws every particle through the camera, depth cued like the rest of the level.
+    pub fn render(&self, camera: &Camera, depth_cue: &DepthCue, backend: &mut dyn RenderBackend) {
+        for particle in &self.particles {
+            let stroke = particle.stroke();
+            let cue = |start_depth, end_depth| depth_cue.edge_stroke(camera, stroke, start_depth, end_depth);
+
+            match &particle.shape {
+                ParticleShape::Fragment { half, axis, angle, .. } => {
+                    let half = half.rotate_around_axis(axis, *angle);
+                    let start = particle.position.translate(&scaled(&half, -1.0));
+
+                    draw_line_3d(backend, camera, &start, &particle.position.translate(&half), cue);
+                }
+                ParticleShape::Spark { trail } => {
+                    let tail = particle.position.translate(&scaled(&particle.velocity, -trail));
+
+                    draw_line_3d(backend, camera, &tail, &particle.position, cue);
+                }
+                ParticleShape::Ring { speed } => {
+                    let radius = speed * particle.age;
+                    let point = |segment: usize| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * TAU;
+
+                        particle.position.translate(&Vector3D { x: angle.cos() * radius, y: 0.0, z: angle.sin() * radius })
+                    };
+
+                    for segment in 0..RING_SEGMENTS {
+                        draw_line_3d(backend, camera, &point(segment), &point(segment + 1), cue);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl ParticleSystem {
+    /// How many particles are alive.
+    pub fn count(&self) -> usize {
+        self.particles.len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> Mesh {
+        let model_name = "cube.obj".to_string();
+
+        Mesh {
+            verts: crate::screens::level1_screen::load_model_verts(&model_name),
+            faces: crate::screens::level1_screen::load_model_faces(&model_name),
+            position: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        }
+    }
+
+    fn fragments(max: usize) -> EmitterSettings {
+        EmitterSettings {
+            fragments: Some(FragmentSettings { max, speed: [2.0, 4.0], lift: 3.0, spin: [1.0, 5.0], lifetime: [1.0, 2.0] }),
+            ..EmitterSettings::default()
+        }
+    }
+
+    fn flat(_x: f32, _z: f32) -> f32 {
+        -100.0
+    }
+
+    #[test]
+    /// # test_fragments_are_the_mesh_edges
+    /// Each edge breaks off once however many faces share it.
+    fn test_fragments_are_the_mesh_edges() {
+        let mut particles = ParticleSystem::new(1);
+        particles.explode(&fragments(1000), &cube());
+
+        assert_eq!(particles.count(), 12);
+
+        let mut limited = ParticleSystem::new(1);
+        limited.explode(&fragments(5), &cube());
+
+        assert!(limited.count() <= 5 && limited.count() > 0, "{}", limited.count());
+    }
+
+    #[test]
+    /// # test_particles_expire
+    fn test_particles_expire() {
+        let mut particles = ParticleSystem::new(1);
+        particles.explode(&fragments(1000), &cube());
+
+        particles.update(0.5, flat);
+        assert_eq!(particles.count(), 12);
+
+        particles.update(1.6, flat);
+        assert_eq!(particles.count(), 0);
+    }
+
+    #[test]
+    /// # test_gravity_and_ground
+    /// Sparks fall, and stop at the ground rather than going through it.
+    fn test_gravity_and_ground() {
+        let settings = EmitterSettings {
+            sparks: Some(SparkSettings { count: 50, speed: [1.0, 2.0], lifetime: [10.0, 10.0], trail: 0.1 }),
+            ..EmitterSettings::default()
+        };
+
+        let mut particles = ParticleSystem::new(2);
+        particles.explode(&settings, &cube());
+
+        for _ in 0..120 {
+            particles.update(1.0 / 60.0, |_, _| -1.0);
+        }
+
+        assert!(particles.particles.iter().all(|particle| particle.position.y >= -1.0));
+        assert!(particles.particles.iter().all(|particle| particle.position.y < 0.5));
+    }
+
+    #[test]
+    /// # test_fade_and_ring_growth
+    fn test_fade_and_ring_growth() {
+        let settings = EmitterSettings { rings: vec![RingSettings { speed: 4.0, lifetime: 1.0, height: 0.5 }], ..EmitterSettings::default() };
+
+        let mut particles = ParticleSystem::new(3);
+        particles.explode(&settings, &cube());
+        particles.update(0.25, flat);
+
+        let ring = &particles.particles[0];
+        assert_eq!(ring.position.y, 0.5, "rings don't fall");
+        assert_eq!(ring.stroke().color, Color32::GREEN);
+
+        particles.update(0.5, flat);
+        let ring = &particles.particles[0];
+        assert_eq!(ring.stroke().color, Color32::GREEN.gamma_multiply(0.5));
+    }
+
+    #[test]
+    /// # test_explosions_are_reproducible
+    fn test_explosions_are_reproducible() {
+        let run = || {
+            let mut particles = ParticleSystem::new(4);
+            particles.explode(&fragments(1000), &cube());
+            particles.update(0.3, flat);
+
+            particles.particles.iter().map(|particle| particle.position.clone()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    /// # test_effect_from_json
+    fn test_effect_from_json() {
+        let settings = EmitterSettings::load_asset("effects/tank_explosion.json").unwrap();
+
+        assert!(settings.fragments.is_some());
+        assert!(settings.sparks.is_some());
+        assert!(!settings.rings.is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use eframe::egui::{Color32, Stroke};
+use serde::{Deserialize, Serialize};
+use crate::player::camera::{Camera, NEAR_CLIP};
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::depth_cue::DepthCue;
+use crate::rendering::wireframe::draw_line_3d;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+use crate::utils::noise::fractal_noise;
+
+const GROUND_STROKE: Stroke = Stroke { width: 1.0, color: Color32::from_rgb(0, 110, 0) };
+const HEIGHTMAP_STROKE: Stroke = Stroke { width: 1.5, color: Color32::GREEN };
+
+/// # TerrainSettings
+/// The ground of a level as given in the level's JSON. Without any settings the ground is an
+/// empty flat grid at height 0.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TerrainSettings {
+    /// Height of the flat ground around the heightmap.
+    pub ground_height: f32,
+    /// Distance between the ground grid's lines, 0 for no grid.
+    pub grid_spacing: f32,
+    /// How far around the camera the grid goes. Past the depth cue's far distance the lines are
+    /// as dim as they get, so there's little to gain from going much further.
+    pub grid_radius: f32,
+    pub heightmap: Option<HeightmapSettings>,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self { ground_height: 0.0, grid_spacing: 10.0, grid_radius: 250.0, heightmap: None }
+    }
+}
+
+/// Where a heightmap's heights come from.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeightmapSource {
+    /// Fractal noise, the same seed always gives the same hills. `size` is the number of points
+    /// along each side and `feature_size` roughly how many world units across a hill is.
+    Noise { seed: u32, size: usize, octaves: u32, feature_size: f32 },
+    /// A greyscale PNG under `assets/`, black is the ground and white is the full height. Colour
+    /// images use their brightness.
+    Image(String),
+}
+
+/// # HeightmapSettings
+/// A patch of hills, centred on (`x`, `z`) with the points `cell_size` apart.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HeightmapSettings {
+    pub source: HeightmapSource,
+    #[serde(default)]
+    pub x: f32,
+    #[serde(default)]
+    pub z: f32,
+    pub cell_size: f32,
+    /// Height above the ground of the highest possible point.
+    pub height: f32,
+}
+
+/// # Heightmap
+/// A grid of heights, `columns` points along x by `rows` along z, with bilinear interpolation
+/// between them.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    columns: usize,
+    rows: usize,
+    /// Row by row, starting at the lowest z.
+    heights: Vec<f32>,
+    /// The corner with the lowest x and z.
+    origin_x: f32,
+    origin_z: f32,
+    cell_size: f32,
+}
+
+impl Heightmap {
+    /// # new
+    /// Places `values` (0.0 to 1.0, row by row) centred on (`x`, `z`), from `base` up to
+    /// `base + height`.
+    pub fn new(columns: usize, rows: usize, values: &[f32], settings: &HeightmapSettings, base: f32) -> Self {
+        Self {
+            columns,
+            rows,
+            heights: values.iter().map(|value| base + value * settings.height).collect(),
+            origin_x: settings.x - (columns - 1) as f32 * settings.cell_size / 2.0,
+            origin_z: settings.z - (rows - 1) as f32 * settings.cell_size / 2.0,
+            cell_size: settings.cell_size,
+        }
+    }
+
+    /// # from_noise
+    /// Generates `size` by `size` points of fractal noise. The edges fall away to the ground so the
+    /// hills don't end in a cliff.
+    pub fn from_noise(seed: u32, size: usize, octaves: u32, feature_size: f32, settings: &HeightmapSettings, base: f32) -> Self {
+        let size = size.max(2);
+        let scale = settings.cell_size / feature_size.max(f32::EPSILON);
+
+        let values: Vec<f32> = (0..size * size)
+            .map(|index| {
+                let (column, row) = (index % size, index / size);
+
+                // 0.0 at the edges to 1.0 from a quarter of the way in
+                let edge = column.min(row).min(size - 1 - column).min(size - 1 - row) as f32 / (size - 1) as f32;
+                let falloff = (edge * 4.0).min(1.0);
+                let falloff = falloff * falloff * (3.0 - 2.0 * falloff);
+
+                fractal_noise(seed, column as f32 * scale, row as f32 * scale, octaves) * falloff
+            })
+            .collect();
+
+        Self::new(size, size, &values, settings, base)
+    }
+
+    /// # from_png
+    /// Reads the heights from a PNG's brightness, one point per pixel, with the top of the image
+    /// at the highest z.
+    pub fn from_png(data: &[u8], settings: &HeightmapSettings, base: f32) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut decoder = png::Decoder::new(data);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+
+        let (columns, rows) = (info.width as usize, info.height as usize);
+        if columns < 2 || rows < 2 {
+            return Err("A heightmap image has to be at least 2 by 2 pixels".into());
+        }
+
+        let channels = info.color_type.samples();
+        let brightness = |pixel: &[u8]| match channels {
+            1 | 2 => pixel[0] as f32 / 255.0,
+            _ => (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32) / 255.0,
+        };
+
+        let pixels: Vec<&[u8]> = buffer[..info.buffer_size()].chunks_exact(channels).collect();
+        let values: Vec<f32> = (0..rows)
+            .rev()
+            .flat_map(|row| pixels[row * columns..(row + 1) * columns].iter().map(|pixel| brightness(pixel)))
+            .collect();
+
+        Ok(Self::new(columns, rows, &values, settings, base))
+    }
+
+    fn point(&self, column: usize, row: usize) -> Point3D {
+        Point3D {
+            x: self.origin_x + column as f32 * self.cell_size,
+            y: self.heights[row * self.columns + column],
+            z: self.origin_z + row as f32 * self.cell_size,
+        }
+    }
+
+    /// Whether (`x`, `z`) is over the heightmap.
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        let (column, row) = ((x - self.origin_x) / self.cell_size, (z - self.origin_z) / self.cell_size);
+
+        column >= 0.0 && row >= 0.0 && column <= (self.columns - 1) as f32 && row <= (self.rows - 1) as f32
+    }
+
+    /// # height_at
+    /// The height at (`x`, `z`), interpolated between the surrounding points. None off the edge.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        if !self.contains(x, z) {
+            return None;
+        }
+
+        let (column, row) = ((x - self.origin_x) / self.cell_size, (z - self.origin_z) / self.cell_size);
+
+        // Clamped so the far edges use the last cell rather than one off the end
+        let column0 = (column.floor() as usize).min(self.columns - 2);
+        let row0 = (row.floor() as usize).min(self.rows - 2);
+        let (tx, tz) = (column - column0 as f32, row - row0 as f32);
+
+        let height = |column, row| self.heights[row * self.columns + column];
+        let near = height(column0, row0) + (height(column0 + 1, row0) - height(column0, row0)) * tx;
+        let far = height(column0, row0 + 1) + (height(column0 + 1, row0 + 1) - height(column0, row0 + 1)) * tx;
+
+        Some(near + (far - near) * tz)
+    }
+}
+
+/// # Terrain
+/// The ground: a flat grid around the camera with optional hills from a heightmap.
+#[derive(Debug, Clone)]
+pub struct Terrain {
+    ground_height: f32,
+    grid_spacing: f32,
+    grid_radius: f32,
+    heightmap: Option<Heightmap>,
+}
+
+impl Terrain {
+    pub fn new(settings: &TerrainSettings) -> Result<Self, Box<dyn std::error::Error>> {
+        let base = settings.ground_height;
+
+        let heightmap = match &settings.heightmap {
+            Some(heightmap) => Some(match &heightmap.source {
+                HeightmapSource::Noise { seed, size, octaves, feature_size } => {
+                    Heightmap::from_noise(*seed, *size, *octaves, *feature_size, heightmap, base)
+                }
+                HeightmapSource::Image(path) => {
+                    let image = Asset::get(path).ok_or(format!("Failed to load {}", path))?;
+                    Heightmap::from_png(&image.data, heightmap, base)?
+                }
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            ground_height: settings.ground_height,
+            grid_spacing: settings.grid_spacing,
+            grid_radius: settings.grid_radius,
+            heightmap,
+        })
+    }
+
+    /// # height_at
+    /// The height of the ground at (`x`, `z`), for putting things on it.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.heightmap
+            .as_ref()
+            .and_then(|heightmap| heightmap.height_at(x, z))
+            .unwrap_or(self.ground_height)
+    }
+
+    /// # render
+    /// Draws the ground grid and the heightmap, depth cued.
+    pub fn render(&self, camera: &Camera, depth_cue: &DepthCue, backend: &mut dyn RenderBackend) {
+        self.render_ground(camera, depth_cue, backend);
+
+        if let Some(heightmap) = &self.heightmap {
+            render_heightmap(heightmap, camera, depth_cue, backend);
+        }
+    }
+
+    /// The grid is drawn out to `grid_radius` (or the far plane if that's nearer) around the
+    /// camera in cell sized pieces, so depth cueing works along each line, leaving out where the
+    /// heightmap is.
+    fn render_ground(&self, camera: &Camera, depth_cue: &DepthCue, backend: &mut dyn RenderBackend) {
+        if self.grid_spacing <= 0.0 {
+            return;
+        }
+
+        let spacing = self.grid_spacing;
+        let under_heightmap = |x: f32, z: f32| self.heightmap.as_ref().is_some_and(|heightmap| heightmap.contains(x, z));
+        let ground = |x: i32, z: i32| Point3D { x: x as f32 * spacing, y: self.ground_height, z: z as f32 * spacing };
+        let stroke = |start_depth, end_depth| depth_cue.edge_stroke(camera, GROUND_STROKE, start_depth, end_depth);
+
+        for (x, z) in self.grid_corners(camera) {
+            let corner = ground(x, z);
+
+            // The pieces running along x and along z from this corner
+            if !under_heightmap(corner.x + spacing / 2.0, corner.z) {
+                draw_line_3d(backend, camera, &corner, &ground(x + 1, z), stroke);
+            }
+
+            if !under_heightmap(corner.x, corner.z + spacing / 2.0) {
+                draw_line_3d(backend, camera, &corner, &ground(x, z + 1), stroke);
+            }
+        }
+    }
+
+    /// # grid_corners
+    /// The grid corners, in cells, whose pieces are worth drawing: those in range and not so far
+    /// behind the camera that both their pieces are.
+    fn grid_corners(&self, camera: &Camera) -> Vec<(i32, i32)> {
+        let spacing = self.grid_spacing;
+        let range = self.grid_radius.min(camera.far_plane_distance());
+        let first_x = ((camera.position.x - range) / spacing).floor() as i32;
+        let last_x = ((camera.position.x + range) / spacing).ceil() as i32;
+        let first_z = ((camera.position.z - range) / spacing).floor() as i32;
+        let last_z = ((camera.position.z + range) / spacing).ceil() as i32;
+
+        let mut corners = Vec::new();
+
+        for z in first_z..=last_z {
+            for x in first_x..=last_x {
+                let corner = Point3D { x: x as f32 * spacing, y: self.ground_height, z: z as f32 * spacing };
+                let out_of_range = (corner.x - camera.position.x).hypot(corner.z - camera.position.z) > range;
+
+                // Neither piece's other end can be more than a cell further forward
+                let behind = camera.depth(&corner) + spacing < NEAR_CLIP;
+
+                if !out_of_range && !behind {
+                    corners.push((x, z));
+                }
+            }
+        }
+
+        corners
+    }
+}
+
+fn render_heightmap(heightmap: &Heightmap, camera: &Camera, depth_cue: &DepthCue, backend: &mut dyn RenderBackend) {
+    let stroke = |start_depth, end_depth| depth_cue.edge_stroke(camera, HEIGHTMAP_STROKE, start_depth, end_depth);
+
+    for row in 0..heightmap.rows {
+        for column in 0..heightmap.columns {
+            let point = heightmap.point(column, row);
+
+            if column + 1 < heightmap.columns {
+                draw_line_3d(backend, camera, &point, &heightmap.point(column + 1, row), stroke);
+            }
+
+            if row + 1 < heightmap.rows {
+                draw_line_3d(backend, camera, &point, &heightmap.point(column, row + 1), stroke);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(source: HeightmapSource) -> HeightmapSettings {
+        HeightmapSettings { source, x: 10.0, z: -10.0, cell_size: 2.0, height: 8.0 }
+    }
+
+    /// A 3 by 3 heightmap around (10, -10) with one raised point in the middle.
+    fn peak() -> Heightmap {
+        let values = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+
+        Heightmap::new(3, 3, &values, &placement(HeightmapSource::Image(String::new())), 1.0)
+    }
+
+    #[test]
+    /// # test_height_at_points
+    fn test_height_at_points() {
+        let heightmap = peak();
+
+        assert_eq!(heightmap.height_at(10.0, -10.0), Some(9.0));
+        assert_eq!(heightmap.height_at(8.0, -12.0), Some(1.0));
+        assert_eq!(heightmap.height_at(12.0, -8.0), Some(1.0));
+    }
+
+    #[test]
+    /// # test_height_at_interpolates
+    fn test_height_at_interpolates() {
+        let heightmap = peak();
+
+        assert_eq!(heightmap.height_at(9.0, -10.0), Some(5.0));
+        assert_eq!(heightmap.height_at(9.0, -11.0), Some(3.0));
+    }
+
+    #[test]
+    /// # test_height_off_the_heightmap_is_the_ground
+    fn test_height_off_the_heightmap_is_the_ground() {
+        let terrain = Terrain {
+            ground_height: 1.0,
+            grid_spacing: 10.0,
+            grid_radius: 250.0,
+            heightmap: Some(peak()),
+        };
+
+        assert_eq!(terrain.height_at(10.0, -10.0), 9.0);
+        assert_eq!(terrain.height_at(12.5, -10.0), 1.0);
+        assert_eq!(terrain.height_at(-100.0, 40.0), 1.0);
+    }
+
+    #[test]
+    /// # test_grid_stays_in_range_and_in_front
+    fn test_grid_stays_in_range_and_in_front() {
+        let terrain = Terrain { ground_height: 0.0, grid_spacing: 10.0, grid_radius: 50.0, heightmap: None };
+        let camera = Camera::for_tests(Point3D { x: 0.0, y: 2.0, z: 0.0 }, 1000.0);
+
+        let corners = terrain.grid_corners(&camera);
+
+        assert!(corners.contains(&(0, 4)));
+        assert!(corners.contains(&(-1, 0)), "its piece along x ends in front of the camera");
+        assert!(!corners.contains(&(0, 6)), "out of range");
+        assert!(!corners.contains(&(0, -3)), "behind the camera");
+        assert!(corners.iter().all(|&(x, z)| (x as f32 * 10.0).hypot(z as f32 * 10.0) <= 50.0));
+    }
+
+    #[test]
+    /// # test_noise_heightmap_is_seeded
+    /// The same seed gives the same hills, and the edges meet the ground.
+    fn test_noise_heightmap_is_seeded() {
+        let noise = |seed| placement(HeightmapSource::Noise { seed, size: 17, octaves: 3, feature_size: 10.0 });
+        let heightmap = |seed| Heightmap::from_noise(seed, 17, 3, 10.0, &noise(seed), 0.0);
+
+        assert_eq!(heightmap(4).heights, heightmap(4).heights);
+        assert_ne!(heightmap(4).heights, heightmap(5).heights);
+
+        let hills = heightmap(4);
+        assert_eq!(hills.height_at(hills.origin_x, hills.origin_z), Some(0.0));
+        assert!(hills.heights.iter().all(|height| (0.0..=8.0).contains(height)));
+        assert!(hills.heights.iter().any(|height| *height > 1.0));
+    }
+
+    #[test]
+    /// # test_heightmap_from_png
+    /// White is the full height, and the bottom row of the image is the lowest z.
+    fn test_heightmap_from_png() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, 2, 2);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.write_header().unwrap().write_image_data(&[255, 0, 0, 0]).unwrap();
+        }
+
+        let heightmap = Heightmap::from_png(&data, &placement(HeightmapSource::Image(String::new())), 0.0).unwrap();
+
+        // Top left of the image is the lowest x and highest z
+        assert_eq!(heightmap.height_at(9.0, -9.0), Some(8.0));
+        assert_eq!(heightmap.height_at(9.0, -11.0), Some(0.0));
+        assert_eq!(heightmap.height_at(11.0, -9.0), Some(0.0));
+    }
+}
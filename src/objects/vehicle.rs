@@ -0,0 +1,297 @@
+use std::ops::Range;
+use std::str::from_utf8;
+use serde::{Deserialize, Serialize};
+use crate::animation::track::TransformAnimation;
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::objects::entity::{select_objects, AnimatedPart, Entity};
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+
+/// # VehicleSettings
+/// How a vehicle the player drives handles, loaded from a file under `assets/vehicles/`. Speeds are
+/// in world units per second and turn rates in degrees per second.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VehicleSettings {
+    pub model: String,
+    /// Degrees to turn the model about its vertical axis so its front faces +z, which is the way
+    /// vehicles drive. The pivot and cockpit are turned with it.
+    #[serde(default)]
+    pub model_yaw: f32,
+    /// OBJ objects that make up the turret, which turns separately from the hull.
+    #[serde(default)]
+    pub turret: Vec<String>,
+    /// Where the turret turns about, in the model's own space.
+    pub turret_pivot: Point3D,
+    /// Where the driver's eye is, in the model's own space, turning with the turret.
+    pub cockpit: Point3D,
+    pub max_speed: f32,
+    pub reverse_speed: f32,
+    /// Speed gained per second with the throttle open.
+    pub acceleration: f32,
+    /// Speed lost per second with the throttle closed.
+    pub friction: f32,
+    pub turn_rate: f32,
+    /// Degrees the gun and the view can go [down, up].
+    pub gun_pitch_limits: [f32; 2],
+}
+
+impl VehicleSettings {
+    /// Loads vehicle settings from a JSON file under `assets/`.
+    pub fn load_asset(file: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let asset = Asset::get(file).ok_or(format!("Missing vehicle {}", file))?;
+
+        Ok(serde_json::from_str(from_utf8(&asset.data)?)?)
+    }
+
+    /// Points from the model turned by `model_yaw`.
+    pub fn face_forward(&self, verts: &[Point3D]) -> Vec<Point3D> {
+        Mesh {
+            verts: verts.to_vec(),
+            faces: Vec::new(),
+            position: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: self.model_yaw.to_radians(), roll: 0.0 },
+        }.get_transformed_verts()
+    }
+}
+
+/// # Vehicle
+/// A tank for the player to drive. The throttle speeds it up and friction slows it down, it turns
+/// on the spot, and the turret is aimed separately with the look controls. It sits on the ground
+/// wherever it goes and stops dead when it bumps into another entity.
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    /// The vehicle as it's placed in the world. Its yaw is the hull's heading.
+    pub entity: Entity,
+    settings: VehicleSettings,
+    /// World units per second along the hull's heading, negative when reversing.
+    pub speed: f32,
+    /// Degrees the turret is turned from the hull.
+    pub turret_yaw: f32,
+    /// Degrees the gun is raised.
+    pub gun_pitch: f32,
+}
+
+impl Vehicle {
+    /// # new
+    /// A vehicle from its entity, with `objects` being the objects in the entity's model as from
+    /// `load_model_parts`. The entity's model should already be facing forward. The turret's
+    /// objects become a part of the entity.
+    pub fn new(mut settings: VehicleSettings, mut entity: Entity, objects: &[(String, Range<usize>)]) -> Result<Self, String> {
+        let mut turned = settings.face_forward(&[settings.turret_pivot.clone(), settings.cockpit.clone()]).into_iter();
+        (settings.turret_pivot, settings.cockpit) = (turned.next().unwrap(), turned.next().unwrap());
+
+        if !settings.turret.is_empty() {
+            let verts = select_objects(objects, &settings.turret, &settings.model)?;
+            entity.parts.push(AnimatedPart::new(&entity.lod, &verts, settings.turret_pivot.clone(), TransformAnimation::default()));
+        }
+
+        Ok(Self { entity, settings, speed: 0.0, turret_yaw: 0.0, gun_pitch: 0.0 })
+    }
+
+    /// Along the ground the way the hull faces.
+    pub fn forward(&self) -> Vector3D {
+        let yaw = self.entity.mesh.rotation.yaw;
+
+        Vector3D { x: yaw.sin(), y: 0.0, z: yaw.cos() }
+    }
+
+    /// # update
+    /// Drives the vehicle for a frame: forward and back are the throttle, strafing turns the hull
+    /// and looking around aims the turret. `obstacles` are what it can bump into.
+    pub fn update<'a>(&mut self, actions: &ActionState, delta_time: f32, ground_height: impl Fn(f32, f32) -> f32, obstacles: impl IntoIterator<Item = &'a Entity> + Clone) {
+        if actions.is_down(Action::Look) {
+            self.turret_yaw += actions.value(Action::LookHorizontal);
+            self.gun_pitch = (self.gun_pitch + actions.value(Action::LookVertical))
+                .clamp(self.settings.gun_pitch_limits[0], self.settings.gun_pitch_limits[1]);
+        }
+
+        let throttle = actions.axis(Action::MoveForward, Action::MoveBackward);
+        if throttle != 0.0 {
+            self.speed += throttle * self.settings.acceleration * delta_time;
+        } else {
+            let slowing = (self.settings.friction * delta_time).min(self.speed.abs());
+            self.speed -= slowing * self.speed.signum();
+        }
+        self.speed = self.speed.clamp(-self.settings.reverse_speed, self.settings.max_speed);
+
+        let turn = actions.axis(Action::StrafeLeft, Action::StrafeRight);
+        self.entity.mesh.rotation.yaw += (turn * self.settings.turn_rate * delta_time).to_radians();
+
+        let was_touching: Vec<bool> = obstacles.clone().into_iter().map(|obstacle| self.entity.touches(obstacle)).collect();
+        let from = self.entity.mesh.position.clone();
+        self.entity.mesh.position = from.clone() + self.forward().set_length(self.speed * delta_time);
+
+        // Only new bumps stop it, so it can still drive away from anything it started inside
+        if obstacles.into_iter().zip(was_touching).any(|(obstacle, was_touching)| !was_touching && self.entity.touches(obstacle)) {
+            self.entity.mesh.position = from;
+            self.speed = 0.0;
+        }
+
+        let position = &mut self.entity.mesh.position;
+        position.y = ground_height(position.x, position.z);
+
+        let turret_rotation = EulerAngles { pitch: 0.0, yaw: self.turret_yaw.to_radians(), roll: 0.0 };
+        for part in &mut self.entity.parts {
+            part.set_pose(Vector3D { x: 0.0, y: 0.0, z: 0.0 }, turret_rotation);
+        }
+    }
+
+    /// # place_camera
+    /// Puts the camera at the cockpit, looking along the gun.
+    pub fn place_camera(&self, camera: &mut Camera) {
+        let pivot = &self.settings.turret_pivot;
+        let cockpit = &self.settings.cockpit;
+
+        // Around the turret's pivot, then where the hull is in the world
+        let in_model = Mesh {
+            verts: vec![Point3D { x: cockpit.x - pivot.x, y: cockpit.y - pivot.y, z: cockpit.z - pivot.z }],
+            faces: Vec::new(),
+            position: Vector3D::from(pivot),
+            rotation: EulerAngles { pitch: 0.0, yaw: self.turret_yaw.to_radians(), roll: 0.0 },
+        }.get_transformed_verts();
+        let eye = Mesh { verts: in_model, faces: Vec::new(), ..self.entity.mesh.clone() }.get_transformed_verts().remove(0);
+
+        let (yaw, pitch) = (self.entity.mesh.rotation.yaw + self.turret_yaw.to_radians(), self.gun_pitch.to_radians());
+        let ahead = Point3D {
+            x: eye.x + yaw.sin() * pitch.cos(),
+            y: eye.y + pitch.sin(),
+            z: eye.z + yaw.cos() * pitch.cos(),
+        };
+
+        camera.position = eye;
+        camera.look_at(&ahead);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> VehicleSettings {
+        VehicleSettings {
+            model: "test".to_string(),
+            model_yaw: 0.0,
+            turret: vec!["Turret".to_string()],
+            turret_pivot: Point3D { x: 0.0, y: 0.0, z: 0.0 },
+            cockpit: Point3D { x: 0.0, y: 2.0, z: 1.0 },
+            max_speed: 10.0,
+            reverse_speed: 5.0,
+            acceleration: 5.0,
+            friction: 10.0,
+            turn_rate: 90.0,
+            gun_pitch_limits: [-10.0, 20.0],
+        }
+    }
+
+    /// A box two units across with its top half as the turret.
+    fn entity(id: &str, x: f32, z: f32) -> Entity {
+        let square = |y: f32| [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].map(|(x, z)| Point3D { x, y, z });
+
+        Entity::new(id.to_string(), "tank".to_string(), Mesh {
+            verts: [square(0.0), square(1.0)].concat(),
+            faces: Vec::new(),
+            position: Vector3D { x, y: 0.0, z },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        })
+    }
+
+    fn tank() -> Vehicle {
+        Vehicle::new(settings(), entity("player", 0.0, 0.0), &[("Hull".to_string(), 0..4), ("Turret".to_string(), 4..8)]).unwrap()
+    }
+
+    fn flat(_: f32, _: f32) -> f32 {
+        0.0
+    }
+
+    #[test]
+    /// # test_throttle_and_friction
+    /// The tank speeds up to its top speed, then rolls to a stop with the throttle closed.
+    fn test_throttle_and_friction() {
+        let mut tank = tank();
+        let mut throttle = ActionState::default();
+        throttle.set(Action::MoveForward, 1.0, false);
+
+        tank.update(&throttle, 1.0, flat, &[]);
+        assert_eq!(tank.speed, 5.0);
+        assert!((tank.entity.mesh.position.z - 5.0).abs() < 1e-5);
+
+        for _ in 0..10 {
+            tank.update(&throttle, 1.0, flat, &[]);
+        }
+        assert_eq!(tank.speed, 10.0);
+
+        tank.update(&ActionState::default(), 0.5, flat, &[]);
+        assert_eq!(tank.speed, 5.0);
+        tank.update(&ActionState::default(), 1.0, flat, &[]);
+        assert_eq!(tank.speed, 0.0);
+    }
+
+    #[test]
+    /// # test_turns_on_the_spot_and_follows_ground
+    fn test_turns_on_the_spot_and_follows_ground() {
+        let mut tank = tank();
+        let mut turn = ActionState::default();
+        turn.set(Action::StrafeLeft, 1.0, false);
+
+        tank.update(&turn, 1.0, |x, z| x + z + 3.0, &[]);
+
+        assert!((tank.entity.mesh.rotation.yaw - 90.0_f32.to_radians()).abs() < 1e-5);
+        assert_eq!(tank.entity.mesh.position, Vector3D { x: 0.0, y: 3.0, z: 0.0 });
+        assert!((tank.forward().x - 1.0).abs() < 1e-5, "faces +x: {:?}", tank.forward());
+    }
+
+    #[test]
+    /// # test_stops_at_obstacles
+    fn test_stops_at_obstacles() {
+        let mut tank = tank();
+        let obstacles = [entity("rock", 0.0, 5.0)];
+
+        let mut throttle = ActionState::default();
+        throttle.set(Action::MoveForward, 1.0, false);
+
+        for _ in 0..60 {
+            tank.update(&throttle, 1.0 / 30.0, flat, &obstacles);
+        }
+
+        let gap = obstacles[0].mesh.position.z - tank.entity.mesh.position.z;
+        assert!(gap >= tank.entity.radius + obstacles[0].radius && gap < 3.5, "stopped {} away", gap);
+
+        // Backing off still works
+        let mut reverse = ActionState::default();
+        reverse.set(Action::MoveBackward, 1.0, false);
+        tank.update(&reverse, 0.5, flat, &obstacles);
+        assert!(obstacles[0].mesh.position.z - tank.entity.mesh.position.z > gap);
+    }
+
+    #[test]
+    /// # test_turret_aims_separately
+    /// Looking around turns the turret and the cockpit camera but not the hull.
+    fn test_turret_aims_separately() {
+        let mut tank = tank();
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+
+        let mut aim = ActionState::default();
+        aim.set(Action::Look, 1.0, false);
+        aim.set(Action::LookHorizontal, 90.0, false);
+        aim.set(Action::LookVertical, 45.0, false);
+        tank.update(&aim, 0.1, flat, &[]);
+
+        assert_eq!(tank.entity.mesh.rotation.yaw, 0.0);
+        assert_eq!(tank.gun_pitch, 20.0);
+
+        let render = tank.entity.render_mesh();
+        assert_eq!(render.verts[..4], tank.entity.lod.levels()[0].verts[..4]);
+        assert!((render.verts[4].x + 1.0).abs() < 1e-5 && (render.verts[4].z - 1.0).abs() < 1e-5, "{:?}", render.verts[4]);
+
+        tank.place_camera(&mut camera);
+        assert!((camera.position.x - 1.0).abs() < 1e-5 && (camera.position.y - 2.0).abs() < 1e-5, "{:?}", camera.position);
+
+        let pitch = 20.0_f32.to_radians();
+        let expected = Vector3D { x: pitch.cos(), y: pitch.sin(), z: 0.0 };
+        assert!(camera.rotation_vector.dot_product(&expected) > 0.9999, "{:?}", camera.rotation_vector);
+    }
+}
@@ -1,6 +1,18 @@
 use std::time::{SystemTime, UNIX_EPOCH};
+use crate::console::commands::{number, Command, CommandHandler};
+use crate::{log_trace, profile_scope};
 use crate::types::geometry::*;
+use crate::utils::profiling::Timing;
 
+/// How far in front of the camera a line has to be to be drawn, `project_line` cuts off anything
+/// closer.
+pub const NEAR_CLIP: f32 = 0.5;
+
+/// The narrowest and widest the field of view can be set, in degrees.
+const MIN_VIEW_ANGLE: f32 = 10.0;
+const MAX_VIEW_ANGLE: f32 = 170.0;
+
+#[derive(Clone)]
 pub struct Camera {
     pub position: Point3D,
     pub rotation: EulerAngles,
@@ -33,6 +45,20 @@ impl Camera {
         }
     }
 
+    pub fn view_angle(&self) -> f32 {
+        self.view_angle
+    }
+
+    /// Changes the field of view, in degrees across the viewport.
+    pub fn set_view_angle(&mut self, view_angle: f32) {
+        self.view_angle = view_angle;
+        self.near_plane_distance = Self::calc_near_plane_distance(self.view_angle, &self.viewport);
+    }
+
+    pub fn far_plane_distance(&self) -> f32 {
+        self.far_plane_distance
+    }
+
     pub fn update_viewport_size(&mut self, viewport_size: Rectangle) {
         self.viewport = viewport_size;
         self.near_plane_distance = Self::calc_near_plane_distance(self.view_angle, &self.viewport);
@@ -47,46 +73,59 @@ impl Camera {
     }
 
      fn rotate_pitch(&mut self, pitch_delta: f32) -> Vector3D {
-         println!("Pitch Delta: {:?}", pitch_delta);
+         log_trace!("Pitch delta: {:?}", pitch_delta);
 
          let world_up_vector = Vector3D {x: 0.0, y: 1.0, z: 0.0 };
 
          let x_axis = self.rotation_vector.cross_product(&world_up_vector).normalise();
 
-         println!("X Axis: {:?}", x_axis);
+         log_trace!("X axis: {:?}", x_axis);
 
          self.rotation_vector.rotate_around_axis(&x_axis, pitch_delta)
     }
 
     fn rotate_yaw(&mut self, yaw_delta: f32) -> Vector3D {
-        println!("Yaw Delta: {:?}", yaw_delta);
+        log_trace!("Yaw delta: {:?}", yaw_delta);
 
         let world_up_vector = Vector3D {x: 0.0, y: 1.0, z: 0.0 };
 
         self.rotation_vector.rotate_around_axis(&world_up_vector, yaw_delta)
     }
 
+    /// Turns the camera to face a point, level with no roll.
+    pub fn look_at(&mut self, target: &Point3D) {
+        let direction = Vector3D::from(&self.position).subtract(&Vector3D::from(target));
+
+        if direction.length() == 0.0 {
+            return;
+        }
+
+        let direction = direction.normalise();
+
+        self.rotation = EulerAngles { pitch: direction.y.asin(), yaw: (-direction.x).atan2(direction.z), roll: 0.0 };
+        self.rotation_vector = direction;
+    }
+
+    /// Points the camera by its angles rather than by turning it.
+    pub fn set_rotation(&mut self, rotation: EulerAngles) {
+        self.rotation = rotation;
+        self.rotation_vector = Vector3D::from(rotation);
+    }
+
     pub fn move_strafe(&mut self, delta: f32) {
         // move camera sideways by delta
-      //  let camera_vector: Vector3D = self.rotation.into();
         let camera_vector_rotated_90 = self.rotation_vector.rotate_yaw(90.0_f32.to_radians());
 
         let scaled_vector = camera_vector_rotated_90.set_length(delta);
 
         self.position = self.position.translate(&scaled_vector);
-
-      //  println!("Camera Vector: {:?}", self.position);
     }
 
     pub fn move_forward(&mut self, delta: f32) {
         // move camera along its vector by delta
-     //   let camera_vector: Vector3D = self.rotation.into();
-
         let scaled_vector = self.rotation_vector.set_length(delta);
 
         self.position = self.position.translate(&scaled_vector);
-
-     //   println!("Camera Vector: {:?}", self.position);
     }
 
     fn calc_near_plane_distance(view_angle: f32, viewport: &Rectangle) -> f32 {
@@ -123,75 +162,144 @@ impl Camera {
     /// touches the near plane, and the end point holds the 2d (x, y) coordinates of the projected
     /// point.
     pub fn to_2d(&self, points_3d: &Vec<Point3D>) -> Vec<Point2D> {
+        self.project(points_3d)
+            .into_iter()
+            .map(|point| Point2D { x: point.x, y: point.y })
+            .collect()
+    }
+
+    /// # project
+    /// Projects the points as `to_2d` does, keeping each point's camera space depth for depth
+    /// cueing.
+    pub fn project(&self, points_3d: &Vec<Point3D>) -> Vec<ProjectedPoint> {
+        profile_scope!(Timing::Projection);
+
         //TODO: Is any point within our view? If not scram
 
-        let mut projected_points: Vec<Point2D> = Vec::new();
+        let mut projected_points: Vec<ProjectedPoint> = Vec::new();
 
-        unsafe{
-            COUNTER += 1;
+        log_trace!("Projecting {} verts from {:?} facing {:?}", points_3d.len(), self.position, self.rotation_vector);
+
+        for point in points_3d {
+            projected_points.push(self.project_point(point));
         }
 
-        let mut vert_count = 0;
+        projected_points
+    }
 
+    /// # depth
+    /// How far in front of the camera a point is, along the look direction. Negative if it's
+    /// behind.
+    pub fn depth(&self, point: &Point3D) -> f32 {
+        let localised_vertex_vector = Vector3D::from(point).subtract(&Vector3D::from(&self.position));
 
+        // `subtract` gives the vector from the point to the camera, so flip it
+        -localised_vertex_vector.dot_product(&self.rotation_vector)
+    }
 
-        for point in points_3d {
-           // let debug = true && point.eq(points_3d.first().unwrap());
-            let mut debug = false;
-            unsafe {
-                if COUNTER % 60 == 0 {
-                    debug = true;
-                }
-            }
+    /// Projects a single point, see `to_2d`.
+    pub fn project_point(&self, point: &Point3D) -> ProjectedPoint {
+        let vertex_vector_ws = Vector3D::from(point);
 
-            vert_count += 1;
+        let camera_position_vector_ws = Vector3D::from(&self.position);
 
-            if debug {
-             //   println!(" ");
-             //   println!("{} Project Vertex", vert_count);
-             //   println!("**************");
+        let localised_vertex_vector = &vertex_vector_ws.subtract(&camera_position_vector_ws);
 
-               // println!("Yaw: {:?}", self.rotation.yaw.to_degrees());
-               // println!("Pitch: {:?}", self.rotation.pitch.to_degrees());
-               // println!("Roll: {:?}", self.rotation.roll.to_degrees());
-               // println!("Position: {:?}", self.position);
-               // println!("**************");
-            }
+        let angle_between_vectors = self.rotation_vector.angle_to_other_vector(&localised_vertex_vector); // Get the angle between the vertex vector and the camera look vector
 
-            if debug {
-                //   println!("Opposite side: {:?}", opposite_side);
-            }
+        let h = self.near_plane_distance / angle_between_vectors.cos();
 
-            let vertex_vector_ws = Vector3D::from(point);
+        let (axis, angle) = self.rotation_vector.get_rotation_to_z_forward();
 
-            let camera_position_vector_ws = Vector3D::from(&self.position);
+        let localised_vertex_vector = localised_vertex_vector.reorient_to_local_space(&self.rotation_vector);
 
-            let localised_vertex_vector = &vertex_vector_ws.subtract(&camera_position_vector_ws);
+        let scaled_localised_vertex_vector = localised_vertex_vector.set_length(h); // Shorten vertex vector to touch near plane...
 
-            let angle_between_vectors = self.rotation_vector.angle_to_other_vector(&localised_vertex_vector); // Get the angle between the vertex vector and the camera look vector
+        let viewport_width_div_2 = &self.viewport.width / 2.0;
+        let viewport_height_div_2 = &self.viewport.height / 2.0;
+
+        ProjectedPoint {
+            x: scaled_localised_vertex_vector.x + viewport_width_div_2,
+            y: scaled_localised_vertex_vector.y + viewport_height_div_2,
+            depth: self.depth(point),
+        }
+    }
 
-            let h = self.near_plane_distance / angle_between_vectors.cos();
+    /// # project_line
+    /// Projects a world space line, cutting off any part closer than `NEAR_CLIP` so lines running
+    /// past the camera don't wrap around the screen. None if the whole line is too close or behind.
+    pub fn project_line(&self, start: &Point3D, end: &Point3D) -> Option<[ProjectedPoint; 2]> {
+        let (start_depth, end_depth) = (self.depth(start), self.depth(end));
 
-            let (axis, angle) = self.rotation_vector.get_rotation_to_z_forward();
+        if start_depth < NEAR_CLIP && end_depth < NEAR_CLIP {
+            return None;
+        }
 
-            let localised_vertex_vector = localised_vertex_vector.reorient_to_local_space(&self.rotation_vector);
+        // Depth changes linearly along the line, so find where it crosses the clip distance
+        let clip = |from: &Point3D, from_depth: f32, to: &Point3D, to_depth: f32| {
+            let t = (NEAR_CLIP - from_depth) / (to_depth - from_depth);
 
-            let scaled_localised_vertex_vector = localised_vertex_vector.set_length(h); // Shorten vertex vector to touch near plane...
+            Point3D {
+                x: from.x + (to.x - from.x) * t,
+                y: from.y + (to.y - from.y) * t,
+                z: from.z + (to.z - from.z) * t,
+            }
+        };
 
-            let viewport_width_div_2 = &self.viewport.width / 2.0;
-            let viewport_height_div_2 = &self.viewport.height / 2.0;
+        let start = if start_depth < NEAR_CLIP { clip(start, start_depth, end, end_depth) } else { start.clone() };
+        let end = if end_depth < NEAR_CLIP { clip(end, end_depth, &start, start_depth) } else { end.clone() };
 
-            projected_points.push(Point2D {
-                x: scaled_localised_vertex_vector.x + viewport_width_div_2,
-                y: scaled_localised_vertex_vector.y + viewport_height_div_2,
-            });
-        }
+        Some([self.project_point(&start), self.project_point(&end)])
+    }
+}
 
-        projected_points
+#[cfg(test)]
+impl Camera {
+    /// # for_tests
+    /// An 800 by 600 camera with a 90 degree field of view at `position`, looking along the z
+    /// axis.
+    pub fn for_tests(position: Point3D, far_plane_distance: f32) -> Self {
+        Self::new(
+            position,
+            EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+            Vector3D { x: 0.0, y: 0.0, z: 1.0 },
+            90.0,
+            Rectangle { width: 800.0, height: 600.0 },
+            far_plane_distance,
+        )
     }
 }
 
-static mut COUNTER: i32 = 0;
+impl CommandHandler for Camera {
+    fn commands(&self) -> Vec<Command> {
+        vec![
+            Command { name: "teleport", usage: "<x> <y> <z>", help: "Moves the camera" },
+            Command { name: "fov", usage: "[degrees]", help: "Shows or sets the field of view" },
+        ]
+    }
+
+    fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        match command {
+            "teleport" => {
+                self.position = Point3D { x: number(args, 0, "x")?, y: number(args, 1, "y")?, z: number(args, 2, "z")? };
+
+                Ok(format!("Camera at {:.1} {:.1} {:.1}", self.position.x, self.position.y, self.position.z))
+            }
+            "fov" if args.is_empty() => Ok(format!("Field of view {:.0}", self.view_angle)),
+            "fov" => {
+                let view_angle = number(args, 0, "degrees")?;
+
+                if !(MIN_VIEW_ANGLE..=MAX_VIEW_ANGLE).contains(&view_angle) {
+                    return Err(format!("The field of view has to be from {} to {}", MIN_VIEW_ANGLE, MAX_VIEW_ANGLE));
+                }
+                self.set_view_angle(view_angle);
+
+                Ok(format!("Field of view {:.0}", self.view_angle))
+            }
+            _ => Err(format!("Camera can't {}", command)),
+        }
+    }
+}
 
 
 #[cfg(test)]
@@ -211,4 +319,69 @@ mod tests {
 
         assert_eq!(near_plane_distance, 400.0);
     }
+
+    #[test]
+    /// # test_project_keeps_depth
+    /// Depth is measured along the look direction, so a point off to the side is no deeper than
+    /// one straight ahead at the same distance along it.
+    fn test_project_keeps_depth() {
+        let camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: -5.0 }, 1000.0);
+
+        let projected = camera.project(&vec![
+            Point3D { x: 0.0, y: 0.0, z: 5.0 },
+            Point3D { x: 3.0, y: 2.0, z: 5.0 },
+            Point3D { x: 0.0, y: 0.0, z: -7.0 },
+        ]);
+
+        assert!((projected[0].depth - 10.0).abs() < 1e-5);
+        assert!((projected[1].depth - 10.0).abs() < 1e-5);
+        assert!((projected[2].depth + 2.0).abs() < 1e-5);
+        assert_eq!((projected[0].x, projected[0].y), (400.0, 300.0));
+    }
+
+    #[test]
+    /// # test_look_at_matches_angles
+    /// Looking at a point gives the same direction as setting the angles it works out.
+    fn test_look_at_matches_angles() {
+        let mut camera = Camera::for_tests(Point3D { x: 25.0, y: 1.0, z: 0.0 }, 1000.0);
+
+        camera.look_at(&Point3D { x: 0.0, y: 1.0, z: 0.0 });
+        assert!((camera.rotation.yaw - 90.0_f32.to_radians()).abs() < 1e-5);
+        assert!(camera.rotation_vector.angle_to_other_vector(&Vector3D { x: -1.0, y: 0.0, z: 0.0 }) < 1e-3);
+
+        camera.look_at(&Point3D { x: 20.0, y: 8.0, z: 9.0 });
+        let looking = camera.rotation_vector.clone();
+        camera.set_rotation(camera.rotation);
+        assert!(camera.rotation_vector.angle_to_other_vector(&looking) < 1e-3);
+    }
+
+    #[test]
+    /// # test_camera_commands
+    fn test_camera_commands() {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+
+        assert!(camera.run("teleport", &["1", "2.5", "-3"]).is_ok());
+        assert_eq!(camera.position, Point3D { x: 1.0, y: 2.5, z: -3.0 });
+        assert_eq!(camera.run("teleport", &["1", "up"]), Err("y isn't a number: up".to_string()));
+
+        assert!(camera.run("fov", &["60"]).is_ok());
+        assert_eq!(camera.view_angle(), 60.0);
+        assert!(camera.near_plane_distance > 400.0, "narrower is further: {}", camera.near_plane_distance);
+        assert!(camera.run("fov", &["180"]).is_err());
+    }
+
+    #[test]
+    /// # test_project_line_clips_behind_camera
+    /// Lines running past the camera are cut off at the near clip distance rather than wrapping
+    /// around.
+    fn test_project_line_clips_behind_camera() {
+        let camera = Camera::for_tests(Point3D { x: 0.0, y: 2.0, z: 0.0 }, 100.0);
+
+        let [start, end] = camera.project_line(&Point3D { x: 0.0, y: 0.0, z: -10.0 }, &Point3D { x: 0.0, y: 0.0, z: 10.0 }).unwrap();
+        assert!((start.depth - NEAR_CLIP).abs() < 1e-4);
+        assert!((end.depth - 10.0).abs() < 1e-4);
+        assert!(start.y > end.y, "the ground gets closer to the horizon further away");
+
+        assert!(camera.project_line(&Point3D { x: 0.0, y: 0.0, z: -10.0 }, &Point3D { x: 5.0, y: 0.0, z: -1.0 }).is_none());
+    }
 }
@@ -0,0 +1,416 @@
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::input::recording::CameraStart;
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::types::geometry::*;
+
+/// Degrees the orbit camera turns for each unit of look input, the same as the mouse look.
+const ORBIT_TURN: f32 = 1.0;
+
+/// How far above or below the target the orbit camera can go, in degrees.
+const ORBIT_PITCH_LIMITS: [f32; 2] = [-10.0, 85.0];
+
+/// Fraction of the distance the orbit camera zooms in a second.
+const ORBIT_ZOOM_RATE: f32 = 1.5;
+
+/// # Target
+/// Where the thing a camera follows is and which way it faces, usually an entity's mesh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub position: Vector3D,
+    pub rotation: EulerAngles,
+}
+
+impl Target {
+    pub fn of(mesh: &Mesh) -> Self {
+        Self { position: mesh.position.clone(), rotation: mesh.rotation }
+    }
+
+    /// Along the ground the way the target's model faces, which is its +z turned by its yaw.
+    pub fn forward(&self) -> Vector3D {
+        Vector3D { x: self.rotation.yaw.sin(), y: 0.0, z: self.rotation.yaw.cos() }
+    }
+
+    /// A point `height` above the target.
+    fn above(&self, height: f32) -> Point3D {
+        Point3D { x: self.position.x, y: self.position.y + height, z: self.position.z }
+    }
+}
+
+/// # OrbitController
+/// Circles the target at a distance, looking at it. Looking around turns the camera around the
+/// target and moving forward or back zooms in and out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitController {
+    /// Degrees around the target, 0 is behind it on the world's -z side.
+    pub yaw: f32,
+    /// Degrees above the target.
+    pub pitch: f32,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// How far above the target's origin to look, so the camera looks at its middle.
+    pub look_height: f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self { yaw: 0.0, pitch: 20.0, distance: 20.0, min_distance: 5.0, max_distance: 100.0, look_height: 1.0 }
+    }
+}
+
+impl OrbitController {
+    pub fn update(&mut self, camera: &mut Camera, actions: &ActionState, target: &Target, delta_time: f32) {
+        if actions.is_down(Action::Look) {
+            self.yaw += actions.value(Action::LookHorizontal) * ORBIT_TURN;
+            self.pitch = (self.pitch - actions.value(Action::LookVertical) * ORBIT_TURN).clamp(ORBIT_PITCH_LIMITS[0], ORBIT_PITCH_LIMITS[1]);
+        }
+
+        let zoom = actions.axis(Action::MoveForward, Action::MoveBackward);
+        self.distance = (self.distance * (1.0 - zoom * ORBIT_ZOOM_RATE * delta_time).max(0.1)).clamp(self.min_distance, self.max_distance);
+
+        self.place(camera, target);
+    }
+
+    /// # place
+    /// Puts the camera on the orbit around the target, looking at it.
+    pub fn place(&self, camera: &mut Camera, target: &Target) {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        let centre = target.above(self.look_height);
+
+        camera.position = Point3D {
+            x: centre.x + yaw.sin() * pitch.cos() * self.distance,
+            y: centre.y + pitch.sin() * self.distance,
+            z: centre.z - yaw.cos() * pitch.cos() * self.distance,
+        };
+        camera.look_at(&centre);
+    }
+}
+
+/// # ChaseController
+/// Follows behind the target on a damped spring, so it swings wide on turns and catches up after
+/// sudden moves rather than being stuck rigidly to the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChaseController {
+    /// How far behind the target the camera sits once it's caught up.
+    pub distance: f32,
+    pub height: f32,
+    pub look_height: f32,
+    /// Pull towards where the camera should be for each unit it's away, per second squared.
+    pub stiffness: f32,
+    /// Drag on the camera's speed, per second. Twice the square root of the stiffness settles
+    /// fastest without overshooting.
+    pub damping: f32,
+    velocity: Vector3D,
+    /// Whether the camera has been put behind the target yet. The first frame snaps it there.
+    following: bool,
+}
+
+impl Default for ChaseController {
+    fn default() -> Self {
+        Self {
+            distance: 12.0,
+            height: 4.0,
+            look_height: 1.5,
+            stiffness: 30.0,
+            damping: 2.0 * 30.0_f32.sqrt(),
+            velocity: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            following: false,
+        }
+    }
+}
+
+impl ChaseController {
+    /// Where the camera is trying to get to.
+    pub fn ideal_position(&self, target: &Target) -> Point3D {
+        let behind = target.forward().set_length(-self.distance);
+
+        Point3D {
+            x: target.position.x + behind.x,
+            y: target.position.y + self.height,
+            z: target.position.z + behind.z,
+        }
+    }
+
+    /// # update
+    /// Moves the camera a frame along the spring towards its ideal position, then looks at the
+    /// target.
+    pub fn update(&mut self, camera: &mut Camera, target: &Target, delta_time: f32) {
+        let ideal = self.ideal_position(target);
+
+        if !self.following {
+            camera.position = ideal;
+            self.velocity = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+            self.following = true;
+        } else {
+            let stretch = Vector3D::from(&camera.position).subtract(&Vector3D::from(&ideal));
+            let spring = |stretch: f32, velocity: f32| velocity + (stretch * self.stiffness - velocity * self.damping) * delta_time;
+
+            self.velocity = Vector3D {
+                x: spring(stretch.x, self.velocity.x),
+                y: spring(stretch.y, self.velocity.y),
+                z: spring(stretch.z, self.velocity.z),
+            };
+            camera.position = camera.position.translate(&Vector3D {
+                x: self.velocity.x * delta_time,
+                y: self.velocity.y * delta_time,
+                z: self.velocity.z * delta_time,
+            });
+        }
+
+        camera.look_at(&target.above(self.look_height));
+    }
+
+    /// Lets go of the camera, so next time it starts behind the target again.
+    pub fn release(&mut self) {
+        self.following = false;
+    }
+}
+
+/// # FreeFlyController
+/// Flies anywhere, ignoring the ground, for looking around a level while debugging. Moves along
+/// where it's looking, strafes, and goes straight up and down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FreeFlyController {
+    /// World units per second.
+    pub speed: f32,
+}
+
+impl Default for FreeFlyController {
+    fn default() -> Self {
+        Self { speed: 20.0 }
+    }
+}
+
+impl FreeFlyController {
+    pub fn update(&mut self, camera: &mut Camera, actions: &ActionState, delta_time: f32) {
+        if actions.is_down(Action::Look) {
+            camera.rotate(actions.value(Action::LookHorizontal), actions.value(Action::LookVertical));
+        }
+
+        let step = self.speed * delta_time;
+
+        let forward = actions.axis(Action::MoveForward, Action::MoveBackward);
+        if forward != 0.0 {
+            camera.move_forward(forward * step);
+        }
+
+        let strafe = actions.axis(Action::StrafeLeft, Action::StrafeRight);
+        if strafe != 0.0 {
+            camera.move_strafe(strafe * step);
+        }
+
+        camera.position.y += actions.axis(Action::MoveUp, Action::MoveDown) * step;
+    }
+}
+
+/// The ways the camera can be driven, switched between in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    /// The player's own view, which the level moves.
+    FirstPerson,
+    Orbit,
+    Chase,
+    FreeFly,
+}
+
+impl CameraMode {
+    pub fn next(&self) -> Self {
+        match self {
+            CameraMode::FirstPerson => CameraMode::Orbit,
+            CameraMode::Orbit => CameraMode::Chase,
+            CameraMode::Chase => CameraMode::FreeFly,
+            CameraMode::FreeFly => CameraMode::FirstPerson,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CameraMode::FirstPerson => "FIRST PERSON",
+            CameraMode::Orbit => "ORBIT CAMERA",
+            CameraMode::Chase => "CHASE CAMERA",
+            CameraMode::FreeFly => "FREE CAMERA",
+        }
+    }
+}
+
+/// # CameraControllers
+/// The camera controllers and which one is driving. First person is left to whoever owns this,
+/// and the first person view is put back as it was when switching round to it again.
+#[derive(Debug, Clone)]
+pub struct CameraControllers {
+    pub mode: CameraMode,
+    pub orbit: OrbitController,
+    pub chase: ChaseController,
+    pub free_fly: FreeFlyController,
+    /// The first person camera from before switching away from it.
+    first_person: Option<CameraStart>,
+}
+
+impl Default for CameraControllers {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::FirstPerson,
+            orbit: OrbitController::default(),
+            chase: ChaseController::default(),
+            free_fly: FreeFlyController::default(),
+            first_person: None,
+        }
+    }
+}
+
+impl CameraControllers {
+    /// Changes to the next mode.
+    pub fn switch(&mut self, camera: &mut Camera) {
+        self.set_mode(self.mode.next(), camera);
+    }
+
+    pub fn set_mode(&mut self, mode: CameraMode, camera: &mut Camera) {
+        if self.mode == CameraMode::FirstPerson {
+            self.first_person = Some(CameraStart::from_camera(camera));
+        }
+
+        if mode == CameraMode::FirstPerson && let Some(first_person) = self.first_person.take() {
+            first_person.apply(camera);
+        }
+
+        self.chase.release();
+        self.mode = mode;
+    }
+
+    /// # update
+    /// Drives the camera for a frame. Returns false in first person, when it's up to the caller.
+    /// The orbit and chase cameras stay put without a target.
+    pub fn update(&mut self, camera: &mut Camera, actions: &ActionState, target: Option<&Target>, delta_time: f32) -> bool {
+        match (self.mode, target) {
+            (CameraMode::FirstPerson, _) => return false,
+            (CameraMode::Orbit, Some(target)) => self.orbit.update(camera, actions, target, delta_time),
+            (CameraMode::Chase, Some(target)) => self.chase.update(camera, target, delta_time),
+            (CameraMode::FreeFly, _) => self.free_fly.update(camera, actions, delta_time),
+            _ => {}
+        }
+
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0)
+    }
+
+    fn target(x: f32, z: f32, yaw_degrees: f32) -> Target {
+        Target {
+            position: Vector3D { x, y: 0.0, z },
+            rotation: EulerAngles { pitch: 0.0, yaw: yaw_degrees.to_radians(), roll: 0.0 },
+        }
+    }
+
+    fn assert_near(point: &Point3D, expected: [f32; 3]) {
+        let error = Vector3D::from(point).subtract(&Vector3D { x: expected[0], y: expected[1], z: expected[2] });
+        assert!(error.length() < 1e-3, "{:?} isn't {:?}", point, expected);
+    }
+
+    /// Whether the camera is looking straight at a point.
+    fn looking_at(camera: &Camera, point: &Point3D) -> bool {
+        let to_point = Vector3D::from(&camera.position).subtract(&Vector3D::from(point));
+        camera.rotation_vector.angle_to_other_vector(&to_point) < 1e-3
+    }
+
+    #[test]
+    /// # test_orbit_positions
+    /// The orbit camera sits on a sphere around the target, behind it at yaw 0 and turning with
+    /// the look input, and zooms with forward and back.
+    fn test_orbit_positions() {
+        let mut camera = camera();
+        let target = target(10.0, 5.0, 0.0);
+        let mut orbit = OrbitController { pitch: 0.0, distance: 10.0, look_height: 0.0, ..OrbitController::default() };
+
+        orbit.place(&mut camera, &target);
+        assert_near(&camera.position, [10.0, 0.0, -5.0]);
+        assert!(looking_at(&camera, &Point3D { x: 10.0, y: 0.0, z: 5.0 }));
+
+        let mut actions = ActionState::default();
+        actions.set(Action::Look, 1.0, false);
+        actions.set(Action::LookHorizontal, 90.0, false);
+        actions.set(Action::LookVertical, -90.0, false);
+        orbit.update(&mut camera, &actions, &target, 0.0);
+
+        // Round to the side, and up as far as it's allowed
+        let pitch = ORBIT_PITCH_LIMITS[1].to_radians();
+        assert_near(&camera.position, [10.0 + 10.0 * pitch.cos(), 10.0 * pitch.sin(), 5.0]);
+
+        let mut zoom_in = ActionState::default();
+        zoom_in.set(Action::MoveForward, 1.0, false);
+        for _ in 0..100 {
+            orbit.update(&mut camera, &zoom_in, &target, 0.1);
+        }
+        assert_eq!(orbit.distance, orbit.min_distance);
+    }
+
+    #[test]
+    /// # test_chase_settles_behind_target
+    /// The chase camera starts behind the target, lags when the target moves, then settles behind
+    /// it again.
+    fn test_chase_settles_behind_target() {
+        let mut camera = camera();
+        let mut chase = ChaseController::default();
+
+        chase.update(&mut camera, &target(0.0, 0.0, 0.0), 1.0 / 60.0);
+        assert_near(&camera.position, [0.0, chase.height, -chase.distance]);
+
+        // Turned to face +x, so behind is -x
+        let moved = target(20.0, 0.0, 90.0);
+        chase.update(&mut camera, &moved, 1.0 / 60.0);
+        assert!(camera.position.x < 1.0, "lags behind: {:?}", camera.position);
+        assert!(looking_at(&camera, &moved.above(chase.look_height)));
+
+        for _ in 0..300 {
+            chase.update(&mut camera, &moved, 1.0 / 60.0);
+        }
+        assert_near(&camera.position, [20.0 - chase.distance, chase.height, 0.0]);
+    }
+
+    #[test]
+    /// # test_free_fly_goes_anywhere
+    fn test_free_fly_goes_anywhere() {
+        let mut camera = camera();
+        let mut free_fly = FreeFlyController::default();
+
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveForward, 1.0, false);
+        actions.set(Action::MoveDown, 1.0, false);
+        free_fly.update(&mut camera, &actions, 0.5);
+
+        assert_near(&camera.position, [0.0, -free_fly.speed * 0.5, free_fly.speed * 0.5]);
+    }
+
+    #[test]
+    /// # test_switching_back_restores_first_person
+    fn test_switching_back_restores_first_person() {
+        let mut camera = camera();
+        let mut controllers = CameraControllers::default();
+        let no_input = ActionState::default();
+        let target = target(0.0, 30.0, 0.0);
+
+        assert!(!controllers.update(&mut camera, &no_input, Some(&target), 0.1));
+
+        controllers.switch(&mut camera);
+        assert_eq!(controllers.mode, CameraMode::Orbit);
+        assert!(controllers.update(&mut camera, &no_input, Some(&target), 0.1));
+        assert_ne!(camera.position, Point3D { x: 0.0, y: 0.0, z: 0.0 });
+
+        while controllers.mode != CameraMode::FirstPerson {
+            controllers.switch(&mut camera);
+            controllers.update(&mut camera, &no_input, Some(&target), 0.1);
+        }
+
+        assert_eq!(camera.position, Point3D { x: 0.0, y: 0.0, z: 0.0 });
+        assert_eq!(camera.rotation_vector, Vector3D { x: 0.0, y: 0.0, z: 1.0 });
+    }
+}
@@ -1 +1,2 @@
-pub mod camera;
\ No newline at end of file
+pub mod camera;
+pub mod controllers;
@@ -0,0 +1,51 @@
+use std::f32::consts::TAU;
+use eframe::egui::{Color32, Painter, Pos2, Shape, Stroke, Vec2};
+use crate::rendering::stroke_font::{text_segments, TextStyle};
+
+/// Segments used when a backend has no circle of its own and draws one as a polygon.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// # RenderBackend
+/// Somewhere to draw a frame. Screens and HUDs only ever draw through this so the same frame can
+/// go to the window (egui's `Painter`) or to an off screen image (`Raster`) for tests.
+///
+/// Everything is in screen space: pixels from the top left with Y down.
+pub trait RenderBackend {
+    fn line(&mut self, points: [Pos2; 2], stroke: Stroke);
+
+    /// A closed polygon, filled and then outlined. Use `Color32::TRANSPARENT` or `Stroke::NONE` to
+    /// skip either. The fill should be convex as egui can't fill anything else.
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke);
+
+    fn circle(&mut self, centre: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        let points: Vec<Pos2> = (0..CIRCLE_SEGMENTS)
+            .map(|i| {
+                let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+                centre + Vec2::angled(angle) * radius
+            })
+            .collect();
+
+        self.polygon(&points, fill, stroke);
+    }
+
+    /// Stroke font text, see `stroke_font`.
+    fn text(&mut self, text: &str, position: Pos2, style: &TextStyle, stroke: Stroke) {
+        for segment in text_segments(text, position, style) {
+            self.line(segment, stroke);
+        }
+    }
+}
+
+impl RenderBackend for Painter {
+    fn line(&mut self, points: [Pos2; 2], stroke: Stroke) {
+        self.line_segment(points, stroke);
+    }
+
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        self.add(Shape::convex_polygon(points.to_vec(), fill, stroke));
+    }
+
+    fn circle(&mut self, centre: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        Painter::circle(self, centre, radius, fill, stroke);
+    }
+}
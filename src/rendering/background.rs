@@ -0,0 +1,349 @@
+use std::f32::consts::TAU;
+use eframe::egui::{Color32, Pos2, Stroke};
+use serde::{Deserialize, Serialize};
+use crate::player::camera::{Camera, NEAR_CLIP};
+use crate::rendering::backend::RenderBackend;
+use crate::types::geometry::*;
+use crate::utils::assets::Asset;
+use crate::utils::noise::{fractal_noise, lattice_value};
+
+/// Points around the horizon the horizon line and mountain silhouette are made of.
+const HORIZON_SAMPLES: usize = 192;
+
+/// Points along each edge of the moon's crescent.
+const MOON_SAMPLES: usize = 24;
+
+/// Points along each side of a volcano.
+const VOLCANO_SAMPLES: usize = 6;
+
+/// # LayerShape
+/// What a background layer draws. Directions are in degrees: `yaw` round from straight ahead at
+/// the start of a level the same way the camera turns, `elevation` up from the horizon.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LayerShape {
+    /// Dots scattered over the sky above `min_elevation`, `size` pixels across give or take.
+    Stars {
+        seed: u32,
+        count: usize,
+        #[serde(default)]
+        min_elevation: f32,
+        #[serde(default = "default_star_size")]
+        size: f32,
+    },
+    /// A line all the way round at eye level.
+    Horizon,
+    /// `phase` is how much of it is lit, from 0.0 (new) through 0.5 (half) to 1.0 (full). The lit
+    /// side faces right and `tilt` turns it anticlockwise.
+    Moon {
+        yaw: f32,
+        elevation: f32,
+        radius: f32,
+        phase: f32,
+        #[serde(default)]
+        tilt: f32,
+    },
+    /// A cone sat on the horizon with a crater `crater` wide at the top.
+    Volcano { yaw: f32, width: f32, height: f32, crater: f32 },
+    /// A jagged range all the way round, roughly `peaks` of them.
+    Mountains { seed: u32, peaks: f32, min_elevation: f32, max_elevation: f32 },
+}
+
+fn default_star_size() -> f32 {
+    2.0
+}
+
+fn default_colour() -> [u8; 3] {
+    [0, 200, 0]
+}
+
+fn default_width() -> f32 {
+    1.5
+}
+
+/// # BackgroundLayer
+/// One layer from a background file, its shape plus the colour (RGB) and line width to draw it
+/// with.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BackgroundLayer {
+    #[serde(flatten)]
+    pub shape: LayerShape,
+    #[serde(default = "default_colour")]
+    pub colour: [u8; 3],
+    #[serde(default = "default_width")]
+    pub line_width: f32,
+}
+
+/// A layer worked out as directions from the camera.
+#[derive(Debug, Clone)]
+struct LayerOutline {
+    lines: Vec<Vec<Vector3D>>,
+    /// Directions with a size in pixels.
+    dots: Vec<(Vector3D, f32)>,
+    stroke: Stroke,
+}
+
+/// # Background
+/// Things so far away that only turning the camera moves them: stars, the horizon, the moon and
+/// distant scenery. Drawn before anything else so the level is on top.
+#[derive(Debug, Clone)]
+pub struct Background {
+    layers: Vec<LayerOutline>,
+}
+
+/// The direction `yaw` degrees round and `elevation` degrees up, turning the same way as the
+/// camera (yaw 0 is +z and 90 is -x).
+pub fn direction(yaw: f32, elevation: f32) -> Vector3D {
+    let (yaw, elevation) = (yaw.to_radians(), elevation.to_radians());
+
+    Vector3D {
+        x: -yaw.sin() * elevation.cos(),
+        y: elevation.sin(),
+        z: yaw.cos() * elevation.cos(),
+    }
+}
+
+impl Background {
+    pub fn new(layers: &[BackgroundLayer]) -> Self {
+        Self { layers: layers.iter().map(outline).collect() }
+    }
+
+    /// Loads a background file from the assets, a JSON list of layers drawn first to last.
+    pub fn load_asset(file_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let background = Asset::get(file_name).ok_or(format!("Failed to load {}", file_name))?;
+        let layers: Vec<BackgroundLayer> = serde_json::from_slice(&background.data)?;
+
+        Ok(Self::new(&layers))
+    }
+
+    /// # render
+    /// Draws every layer around the camera's position, so moving doesn't change anything but
+    /// turning does.
+    pub fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        // Anywhere out in front works as the projection only cares about direction
+        let distance = camera.far_plane_distance();
+        let place = |direction: &Vector3D| camera.position.translate(&direction.set_length(distance));
+
+        for layer in &self.layers {
+            for line in &layer.lines {
+                for pair in line.windows(2) {
+                    if let Some([start, end]) = camera.project_line(&place(&pair[0]), &place(&pair[1])) {
+                        backend.line([Pos2::new(start.x, start.y), Pos2::new(end.x, end.y)], layer.stroke);
+                    }
+                }
+            }
+
+            for (direction, size) in &layer.dots {
+                let point = place(direction);
+
+                if camera.depth(&point) > NEAR_CLIP {
+                    let projected = camera.project_point(&point);
+                    backend.circle(Pos2::new(projected.x, projected.y), size / 2.0, layer.stroke.color, Stroke::NONE);
+                }
+            }
+        }
+    }
+}
+
+fn outline(layer: &BackgroundLayer) -> LayerOutline {
+    let [r, g, b] = layer.colour;
+    let stroke = Stroke::new(layer.line_width, Color32::from_rgb(r, g, b));
+
+    let mut lines = Vec::new();
+    let mut dots = Vec::new();
+
+    match layer.shape {
+        LayerShape::Stars { seed, count, min_elevation, size } => {
+            // Even over the sky rather than bunched up overhead
+            let lowest = min_elevation.to_radians().sin();
+
+            dots = (0..count as i32)
+                .map(|star| {
+                    let yaw = lattice_value(seed, star, 0) * 360.0;
+                    let height = lowest + lattice_value(seed, star, 1) * (1.0 - lowest);
+                    let brightness = 0.5 + lattice_value(seed, star, 2);
+
+                    (direction(yaw, height.asin().to_degrees()), size * brightness)
+                })
+                .collect();
+        }
+        LayerShape::Horizon => {
+            lines.push((0..=HORIZON_SAMPLES).map(|sample| direction(sample as f32 / HORIZON_SAMPLES as f32 * 360.0, 0.0)).collect());
+        }
+        LayerShape::Moon { yaw, elevation, radius, phase, tilt } => {
+            let centre = direction(yaw, elevation);
+            let right = centre.cross_product(&Vector3D { x: 0.0, y: 1.0, z: 0.0 }).normalise();
+            let up = right.cross_product(&centre).normalise();
+            let (tilt, radius) = (tilt.to_radians(), radius.to_radians());
+
+            // A point on the moon's disc, (x, y) in radii from its centre with x towards the lit side
+            let disc = |x: f32, y: f32| {
+                let (x, y) = (x * tilt.cos() - y * tilt.sin(), x * tilt.sin() + y * tilt.cos());
+
+                centre.set_length(radius.cos()) + right.set_length(x * radius.sin()) + up.set_length(y * radius.sin())
+            };
+
+            // Round the lit edge from bottom to top then back down the terminator, which bulges
+            // out to the lit edge when new and to the other side when full
+            let terminator = 1.0 - 2.0 * phase.clamp(0.0, 1.0);
+            let angle = |sample: usize| (sample as f32 / MOON_SAMPLES as f32 - 0.5) * TAU / 2.0;
+
+            let edge = (0..=MOON_SAMPLES).map(|sample| disc(angle(sample).cos(), angle(sample).sin()));
+            let back = (0..=MOON_SAMPLES).rev().map(|sample| disc(terminator * angle(sample).cos(), angle(sample).sin()));
+
+            lines.push(edge.chain(back).collect());
+        }
+        LayerShape::Volcano { yaw, width, height, crater } => {
+            // Concave sides up to the crater's rim, with the crater dipping a little between them
+            let side = |sample: usize, sign: f32| {
+                let t = sample as f32 / VOLCANO_SAMPLES as f32;
+                let across = width / 2.0 + (crater / 2.0 - width / 2.0) * t;
+
+                direction(yaw + sign * across, height * t.powf(1.6))
+            };
+
+            let mut silhouette: Vec<Vector3D> = (0..=VOLCANO_SAMPLES).map(|sample| side(sample, -1.0)).collect();
+            silhouette.push(direction(yaw, height * 0.9));
+            silhouette.extend((0..=VOLCANO_SAMPLES).rev().map(|sample| side(sample, 1.0)));
+
+            lines.push(silhouette);
+        }
+        LayerShape::Mountains { seed, peaks, min_elevation, max_elevation } => {
+            let elevations: Vec<f32> = (0..HORIZON_SAMPLES)
+                .map(|sample| {
+                    // Going round a circle in the noise so the silhouette joins up
+                    let angle = sample as f32 / HORIZON_SAMPLES as f32 * TAU;
+                    let radius = peaks / TAU;
+                    let noise = fractal_noise(seed, angle.cos() * radius, angle.sin() * radius, 4);
+
+                    // Folded so the peaks come to a point
+                    let ridge = 1.0 - (noise * 2.0 - 1.0).abs();
+
+                    min_elevation + ridge * (max_elevation - min_elevation)
+                })
+                .collect();
+
+            lines.push(
+                (0..=HORIZON_SAMPLES)
+                    .map(|sample| direction(sample as f32 / HORIZON_SAMPLES as f32 * 360.0, elevations[sample % HORIZON_SAMPLES]))
+                    .collect(),
+            );
+        }
+    }
+
+    LayerOutline { lines, dots, stroke }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::recorder::Recorder;
+
+    fn camera(position: Point3D, look: Vector3D) -> Camera {
+        let mut camera = Camera::for_tests(position, 200.0);
+        camera.rotation_vector = look;
+
+        camera
+    }
+
+    fn layer(shape: LayerShape) -> BackgroundLayer {
+        BackgroundLayer { shape, colour: default_colour(), line_width: default_width() }
+    }
+
+    fn sky() -> Background {
+        Background::new(&[
+            layer(LayerShape::Stars { seed: 3, count: 200, min_elevation: 10.0, size: 2.0 }),
+            layer(LayerShape::Horizon),
+            layer(LayerShape::Moon { yaw: 10.0, elevation: 20.0, radius: 3.0, phase: 0.3, tilt: 0.0 }),
+            layer(LayerShape::Volcano { yaw: -15.0, width: 12.0, height: 8.0, crater: 2.0 }),
+            layer(LayerShape::Mountains { seed: 2, peaks: 12.0, min_elevation: 1.0, max_elevation: 6.0 }),
+        ])
+    }
+
+    fn render(background: &Background, camera: &Camera) -> Vec<Pos2> {
+        let mut output = Recorder::default();
+        background.render(camera, &mut output);
+
+        output.points()
+    }
+
+    #[test]
+    /// # test_direction_matches_camera
+    /// Yaw turns the same way as the camera, which starts level 1 at yaw 90 looking down -x.
+    fn test_direction_matches_camera() {
+        let ahead = direction(0.0, 0.0);
+        let left = direction(90.0, 0.0);
+        let up = direction(0.0, 90.0);
+
+        assert!((ahead.z - 1.0).abs() < 1e-6);
+        assert!((left.x + 1.0).abs() < 1e-6);
+        assert!((up.y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    /// # test_moving_doesnt_move_the_background
+    fn test_moving_doesnt_move_the_background() {
+        let look = Vector3D { x: 0.0, y: 0.0, z: 1.0 };
+
+        let here = render(&sky(), &camera(Point3D { x: 0.0, y: 0.0, z: 0.0 }, look.clone()));
+        let there = render(&sky(), &camera(Point3D { x: 40.0, y: 3.0, z: -75.0 }, look));
+
+        assert!(here.len() > 200);
+        assert_eq!(here.len(), there.len());
+        // Lines cut off at the near clip end up far off screen, where rounding shows more
+        assert!(here.iter().zip(&there).all(|(a, b)| a.distance(*b) < 0.05 + a.to_vec2().length() * 1e-4));
+    }
+
+    #[test]
+    /// # test_turning_moves_the_background
+    fn test_turning_moves_the_background() {
+        let origin = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+
+        let ahead = render(&sky(), &camera(origin.clone(), Vector3D { x: 0.0, y: 0.0, z: 1.0 }));
+        let turned = render(&sky(), &camera(origin, Vector3D { x: 1.0, y: 0.0, z: 0.0 }));
+
+        assert_ne!(ahead, turned);
+    }
+
+    #[test]
+    /// # test_stars_stay_above_min_elevation
+    fn test_stars_stay_above_min_elevation() {
+        let stars = outline(&layer(LayerShape::Stars { seed: 9, count: 500, min_elevation: 30.0, size: 2.0 }));
+
+        assert_eq!(stars.dots.len(), 500);
+        assert!(stars.dots.iter().all(|(direction, _)| direction.y >= 30.0_f32.to_radians().sin() - 1e-5));
+    }
+
+    #[test]
+    /// # test_outlines_join_up
+    /// The horizon, mountains and moon are closed loops.
+    fn test_outlines_join_up() {
+        let shapes = [
+            LayerShape::Horizon,
+            LayerShape::Mountains { seed: 2, peaks: 12.0, min_elevation: 1.0, max_elevation: 6.0 },
+            LayerShape::Moon { yaw: 0.0, elevation: 20.0, radius: 3.0, phase: 0.3, tilt: 45.0 },
+        ];
+
+        for shape in shapes {
+            let line = &outline(&layer(shape.clone())).lines[0];
+            let gap = line[0].subtract(line.last().unwrap()).length();
+
+            assert!(gap < 1e-4, "{:?} has a gap of {}", shape, gap);
+        }
+    }
+
+    #[test]
+    /// # test_layers_from_json
+    fn test_layers_from_json() {
+        let layers: Vec<BackgroundLayer> = serde_json::from_str(r#"[
+            {"type": "stars", "seed": 1, "count": 10},
+            {"type": "horizon", "colour": [0, 100, 0], "line_width": 1.0},
+            {"type": "volcano", "yaw": 30, "width": 10, "height": 6, "crater": 2}
+        ]"#).unwrap();
+
+        assert_eq!(layers[0].shape, LayerShape::Stars { seed: 1, count: 10, min_elevation: 0.0, size: 2.0 });
+        assert_eq!(layers[1].colour, [0, 100, 0]);
+        assert_eq!(layers[2].line_width, default_width());
+    }
+}
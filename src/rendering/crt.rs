@@ -0,0 +1,332 @@
+use std::collections::VecDeque;
+use eframe::egui::{Color32, Pos2, Stroke, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::profile_count;
+use crate::rendering::backend::RenderBackend;
+use crate::utils::profiling::Counter;
+
+/// Extra strokes drawn under every line for the glow, widest first, as (width multiplier, opacity).
+const GLOW_PASSES: [(f32, f32); 2] = [(4.0, 0.08), (2.5, 0.18)];
+
+/// Seconds a line stays visible on the phosphor after it was last drawn.
+const PHOSPHOR_DECAY: f32 = 0.2;
+/// How bright a line's afterglow starts, compared with the line itself.
+const PHOSPHOR_INTENSITY: f32 = 0.35;
+/// Most lines kept glowing at once, the oldest are dropped first. Keeps busy scenes from slowing
+/// down as the afterglow piles up.
+const MAX_AFTERGLOW_LINES: usize = 20_000;
+
+/// Furthest, in pixels, the beam wanders from where it was aimed.
+const JITTER_AMOUNT: f32 = 0.6;
+
+/// Lines longer than this many pixels start to dim, as the beam spends less time on each point.
+const FALLOFF_LENGTH: f32 = 250.0;
+/// The dimmest a long line gets.
+const MIN_FALLOFF_INTENSITY: f32 = 0.55;
+
+/// The individual effects, so they can be listed and toggled on the options screen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrtEffect {
+    Glow,
+    Phosphor,
+    Jitter,
+    Falloff,
+}
+
+impl CrtEffect {
+    pub const ALL: [CrtEffect; 4] = [CrtEffect::Glow, CrtEffect::Phosphor, CrtEffect::Jitter, CrtEffect::Falloff];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CrtEffect::Glow => "GLOW",
+            CrtEffect::Phosphor => "PHOSPHOR",
+            CrtEffect::Jitter => "BEAM JITTER",
+            CrtEffect::Falloff => "LONG LINE FALLOFF",
+        }
+    }
+}
+
+/// Which vector monitor effects are switched on.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CrtSettings {
+    pub glow: bool,
+    pub phosphor: bool,
+    pub jitter: bool,
+    pub falloff: bool,
+}
+
+impl Default for CrtSettings {
+    fn default() -> Self {
+        Self { glow: true, phosphor: true, jitter: false, falloff: true }
+    }
+}
+
+impl CrtSettings {
+    pub fn enabled(&self, effect: CrtEffect) -> bool {
+        match effect {
+            CrtEffect::Glow => self.glow,
+            CrtEffect::Phosphor => self.phosphor,
+            CrtEffect::Jitter => self.jitter,
+            CrtEffect::Falloff => self.falloff,
+        }
+    }
+
+    pub fn toggle(&mut self, effect: CrtEffect) {
+        let enabled = match effect {
+            CrtEffect::Glow => &mut self.glow,
+            CrtEffect::Phosphor => &mut self.phosphor,
+            CrtEffect::Jitter => &mut self.jitter,
+            CrtEffect::Falloff => &mut self.falloff,
+        };
+
+        *enabled = !*enabled;
+    }
+}
+
+/// A line still glowing on the phosphor after being drawn.
+struct Afterglow {
+    points: [Pos2; 2],
+    stroke: Stroke,
+    age: f32,
+}
+
+/// # Crt
+/// Makes line output look like it's on an arcade vector monitor: a soft glow around each line,
+/// phosphor that keeps glowing for a moment after the beam has moved on, a slightly unsteady beam
+/// and long lines that are dimmer than short ones.
+///
+/// It sits in front of any other backend. Each frame call `begin_frame` with the real backend and
+/// render into the `CrtFrame` it returns. The `Crt` has to live across frames for the phosphor.
+pub struct Crt {
+    pub settings: CrtSettings,
+    afterglow: VecDeque<Afterglow>,
+    /// Counts frames so the jitter changes every frame but is the same for the same frame.
+    frame: u32,
+}
+
+impl Crt {
+    pub fn new(settings: CrtSettings) -> Self {
+        Self { settings, afterglow: VecDeque::new(), frame: 0 }
+    }
+
+    /// # begin_frame
+    /// Ages the afterglow by `delta_time`, draws what's still glowing from earlier frames to
+    /// `output` and returns a backend to render the new frame through.
+    pub fn begin_frame<'a>(&'a mut self, output: &'a mut dyn RenderBackend, delta_time: f32) -> CrtFrame<'a> {
+        self.frame = self.frame.wrapping_add(1);
+
+        if !self.settings.phosphor {
+            self.afterglow.clear();
+        }
+
+        for glow in &mut self.afterglow {
+            glow.age += delta_time;
+        }
+        self.afterglow.retain(|glow| glow.age < PHOSPHOR_DECAY);
+
+        for glow in &self.afterglow {
+            let fade = PHOSPHOR_INTENSITY * (1.0 - glow.age / PHOSPHOR_DECAY);
+            output.line(glow.points, Stroke::new(glow.stroke.width, glow.stroke.color.gamma_multiply(fade)));
+        }
+
+        CrtFrame { crt: self, output, line_index: 0 }
+    }
+}
+
+/// A value from -1.0 to 1.0 that's always the same for the same seed.
+fn noise(seed: u32) -> f32 {
+    let mut x = seed.wrapping_mul(0x9E37_79B9);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 13;
+
+    (x & 0xFFFF) as f32 / 0xFFFF as f32 * 2.0 - 1.0
+}
+
+/// # CrtFrame
+/// One frame being drawn through a `Crt`, see `Crt::begin_frame`.
+pub struct CrtFrame<'a> {
+    crt: &'a mut Crt,
+    output: &'a mut dyn RenderBackend,
+    line_index: u32,
+}
+
+impl CrtFrame<'_> {
+    fn jitter(&mut self, point: Pos2) -> Pos2 {
+        let seed = self.crt.frame.wrapping_mul(7919).wrapping_add(self.line_index.wrapping_mul(4));
+        self.line_index = self.line_index.wrapping_add(1);
+
+        point + Vec2::new(noise(seed), noise(seed.wrapping_add(1))) * JITTER_AMOUNT
+    }
+
+    fn glow(&mut self, draw: &mut dyn FnMut(&mut dyn RenderBackend, Stroke), stroke: Stroke) {
+        if !self.crt.settings.glow {
+            return;
+        }
+
+        for (width, opacity) in GLOW_PASSES {
+            draw(&mut *self.output, Stroke::new(stroke.width * width, stroke.color.gamma_multiply(opacity)));
+        }
+    }
+}
+
+impl RenderBackend for CrtFrame<'_> {
+    fn line(&mut self, points: [Pos2; 2], stroke: Stroke) {
+        if stroke.is_empty() {
+            return;
+        }
+
+        profile_count!(Counter::Segments, 1);
+
+        let points = if self.crt.settings.jitter { [self.jitter(points[0]), self.jitter(points[1])] } else { points };
+
+        let length = (points[1] - points[0]).length();
+        let stroke = if self.crt.settings.falloff && length > FALLOFF_LENGTH {
+            Stroke::new(stroke.width, stroke.color.gamma_multiply((FALLOFF_LENGTH / length).max(MIN_FALLOFF_INTENSITY)))
+        } else {
+            stroke
+        };
+
+        self.glow(&mut |output, glow| output.line(points, glow), stroke);
+        self.output.line(points, stroke);
+
+        if self.crt.settings.phosphor {
+            if self.crt.afterglow.len() >= MAX_AFTERGLOW_LINES {
+                self.crt.afterglow.pop_front();
+            }
+
+            self.crt.afterglow.push_back(Afterglow { points, stroke, age: 0.0 });
+        }
+    }
+
+    /// The fill goes straight through, the outline is drawn as lines so it gets the effects.
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        self.output.polygon(points, fill, Stroke::NONE);
+
+        for i in 0..points.len() {
+            self.line([points[i], points[(i + 1) % points.len()]], stroke);
+        }
+    }
+
+    fn circle(&mut self, centre: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        if !stroke.is_empty() {
+            self.glow(&mut |output, glow| output.circle(centre, radius, Color32::TRANSPARENT, glow), stroke);
+        }
+
+        self.output.circle(centre, radius, fill, stroke);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::recorder::Recorder;
+
+    /// Everything switched off, lines are drawn exactly as given.
+    const OFF: CrtSettings = CrtSettings { glow: false, phosphor: false, jitter: false, falloff: false };
+
+    const LINE: [Pos2; 2] = [Pos2::new(10.0, 10.0), Pos2::new(20.0, 10.0)];
+
+    fn draw_frame(crt: &mut Crt, lines: [([Pos2; 2], Stroke); 1], delta_time: f32) -> Vec<([Pos2; 2], Stroke)> {
+        let mut output = Recorder::default();
+        let mut frame = crt.begin_frame(&mut output, delta_time);
+
+        for (points, stroke) in lines {
+            frame.line(points, stroke);
+        }
+
+        output.lines
+    }
+
+    #[test]
+    /// # test_everything_off_passes_lines_through
+    fn test_everything_off_passes_lines_through() {
+        let mut crt = Crt::new(OFF);
+        let stroke = Stroke::new(2.0, Color32::GREEN);
+
+        assert_eq!(draw_frame(&mut crt, [(LINE, stroke)], 0.016), vec![(LINE, stroke)]);
+        assert_eq!(draw_frame(&mut crt, [(LINE, stroke)], 0.016), vec![(LINE, stroke)]);
+    }
+
+    #[test]
+    /// # test_glow_is_drawn_under_the_line
+    /// The glow passes come first, wider and fainter, and the line itself is drawn last on top.
+    fn test_glow_is_drawn_under_the_line() {
+        let mut crt = Crt::new(CrtSettings { glow: true, ..OFF });
+        let stroke = Stroke::new(2.0, Color32::GREEN);
+
+        let lines = draw_frame(&mut crt, [(LINE, stroke)], 0.016);
+
+        assert_eq!(lines.len(), 1 + GLOW_PASSES.len());
+        assert_eq!(*lines.last().unwrap(), (LINE, stroke));
+        for (_, glow) in &lines[..GLOW_PASSES.len()] {
+            assert!(glow.width > stroke.width);
+            assert!(glow.color.a() < stroke.color.a());
+        }
+    }
+
+    #[test]
+    /// # test_phosphor_fades_out
+    fn test_phosphor_fades_out() {
+        let mut crt = Crt::new(CrtSettings { phosphor: true, ..OFF });
+        let stroke = Stroke::new(1.0, Color32::WHITE);
+        let elsewhere = [Pos2::new(50.0, 50.0), Pos2::new(60.0, 50.0)];
+
+        draw_frame(&mut crt, [(LINE, stroke)], 0.05);
+
+        // The old line is still glowing, fainter, underneath the new one
+        let lines = draw_frame(&mut crt, [(elsewhere, stroke)], 0.05);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].0, LINE);
+        assert!(lines[0].1.color.a() < stroke.color.a());
+
+        // Once it's older than the decay time it's gone
+        let lines = draw_frame(&mut crt, [(elsewhere, stroke)], PHOSPHOR_DECAY);
+        assert!(lines.iter().all(|(points, _)| *points == elsewhere));
+    }
+
+    #[test]
+    /// # test_long_lines_are_dimmer
+    fn test_long_lines_are_dimmer() {
+        let mut crt = Crt::new(CrtSettings { falloff: true, ..OFF });
+        let stroke = Stroke::new(1.0, Color32::WHITE);
+        let long = [Pos2::new(0.0, 0.0), Pos2::new(FALLOFF_LENGTH * 10.0, 0.0)];
+
+        assert_eq!(draw_frame(&mut crt, [(LINE, stroke)], 0.016)[0].1, stroke);
+
+        let dimmed = draw_frame(&mut crt, [(long, stroke)], 0.016)[0].1;
+        assert_eq!(dimmed.color, Color32::WHITE.gamma_multiply(MIN_FALLOFF_INTENSITY));
+    }
+
+    #[test]
+    /// # test_jitter_is_small_and_repeatable
+    fn test_jitter_is_small_and_repeatable() {
+        let stroke = Stroke::new(1.0, Color32::WHITE);
+        let settings = CrtSettings { jitter: true, ..OFF };
+
+        let first = draw_frame(&mut Crt::new(settings), [(LINE, stroke)], 0.016);
+        let again = draw_frame(&mut Crt::new(settings), [(LINE, stroke)], 0.016);
+        assert_eq!(first, again);
+
+        let [start, end] = first[0].0;
+        assert!(start != LINE[0] || end != LINE[1]);
+        assert!(start.distance(LINE[0]) <= JITTER_AMOUNT * 2.0_f32.sqrt());
+        assert!(end.distance(LINE[1]) <= JITTER_AMOUNT * 2.0_f32.sqrt());
+    }
+
+    #[test]
+    /// # test_toggle
+    fn test_toggle() {
+        let mut settings = OFF;
+
+        for effect in CrtEffect::ALL {
+            settings.toggle(effect);
+            assert!(settings.enabled(effect));
+        }
+
+        settings.toggle(CrtEffect::Glow);
+        assert!(!settings.glow);
+    }
+}
@@ -0,0 +1,363 @@
+use std::f32::consts::TAU;
+use eframe::egui::{Color32, Stroke};
+use crate::objects::mesh::Mesh;
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::wireframe::draw_line_3d;
+use crate::types::geometry::{Face, Point3D, Vector3D};
+
+/// Length of the world axes, and of a mesh's local axes.
+const WORLD_AXES_LENGTH: f32 = 5.0;
+const LOCAL_AXES_LENGTH: f32 = 2.0;
+
+const NORMAL_LENGTH: f32 = 0.5;
+
+/// Segments in each circle of a bounding sphere.
+const SPHERE_SEGMENTS: usize = 24;
+
+/// How far out the camera's frustum is drawn. The real far plane is too far to see it from the
+/// debug camera.
+const FRUSTUM_LENGTH: f32 = 20.0;
+
+/// Where the debug camera sits, behind and above the game camera.
+const DEBUG_CAMERA_BACK: f32 = 12.0;
+const DEBUG_CAMERA_UP: f32 = 6.0;
+
+const X_COLOUR: Color32 = Color32::RED;
+const Y_COLOUR: Color32 = Color32::GREEN;
+const Z_COLOUR: Color32 = Color32::LIGHT_BLUE;
+
+/// # DebugCategory
+/// Kinds of debug lines, switched on and off separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCategory {
+    /// The world's X, Y and Z axes from the origin, in red, green and blue.
+    WorldAxes,
+    /// Each mesh's own axes, turned and moved as its vertices are.
+    LocalAxes,
+    /// A line out of the middle of every face.
+    Normals,
+    /// Each mesh's bounding sphere and the box around its vertices.
+    Bounds,
+    /// The game camera's look vector and frustum. The scene is drawn from a debug camera behind
+    /// it while this is on, so they can be seen.
+    Frustum,
+}
+
+impl DebugCategory {
+    pub const ALL: [DebugCategory; 5] = [
+        DebugCategory::WorldAxes,
+        DebugCategory::LocalAxes,
+        DebugCategory::Normals,
+        DebugCategory::Bounds,
+        DebugCategory::Frustum,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugCategory::WorldAxes => "axes",
+            DebugCategory::LocalAxes => "local-axes",
+            DebugCategory::Normals => "normals",
+            DebugCategory::Bounds => "bounds",
+            DebugCategory::Frustum => "frustum",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DebugCategory> {
+        DebugCategory::ALL.into_iter().find(|category| category.name() == name)
+    }
+}
+
+/// # DebugLine
+/// A world space line to draw over the scene.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugLine {
+    pub start: Point3D,
+    pub end: Point3D,
+    pub colour: Color32,
+}
+
+/// # DebugDraw
+/// Immediate mode debug lines: fill it while drawing a frame, then `render` draws them over the
+/// scene. Lines in categories that are off are dropped as they're added, and the helpers don't do
+/// any work for them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugDraw {
+    enabled: Vec<DebugCategory>,
+    lines: Vec<DebugLine>,
+}
+
+impl DebugDraw {
+    pub fn new(enabled: &[DebugCategory]) -> Self {
+        Self { enabled: enabled.to_vec(), lines: Vec::new() }
+    }
+
+    pub fn is_enabled(&self, category: DebugCategory) -> bool {
+        self.enabled.contains(&category)
+    }
+
+    pub fn line(&mut self, category: DebugCategory, start: Point3D, end: Point3D, colour: Color32) {
+        if self.is_enabled(category) {
+            self.lines.push(DebugLine { start, end, colour });
+        }
+    }
+
+    pub fn world_axes(&mut self) {
+        let origin = Point3D { x: 0.0, y: 0.0, z: 0.0 };
+
+        for (axis, colour) in axes(WORLD_AXES_LENGTH) {
+            self.line(DebugCategory::WorldAxes, origin.clone(), axis, colour);
+        }
+    }
+
+    /// # local_axes
+    /// The mesh's axes, put through the same transform as its vertices.
+    pub fn local_axes(&mut self, mesh: &Mesh) {
+        if !self.is_enabled(DebugCategory::LocalAxes) {
+            return;
+        }
+
+        let (tips, colours): (Vec<Point3D>, Vec<Color32>) = axes(LOCAL_AXES_LENGTH).into_iter().unzip();
+        let axes = Mesh { verts: [vec![Point3D { x: 0.0, y: 0.0, z: 0.0 }], tips].concat(), faces: Vec::new(), ..mesh.clone() };
+        let transformed = axes.get_transformed_verts();
+
+        for (tip, colour) in transformed[1..].iter().zip(colours) {
+            self.line(DebugCategory::LocalAxes, transformed[0].clone(), tip.clone(), colour);
+        }
+    }
+
+    /// # normals
+    /// A line out of the middle of each face, from the winding of its first three corners. `verts`
+    /// are the mesh's transformed vertices.
+    pub fn normals(&mut self, verts: &[Point3D], faces: &[Face]) {
+        if !self.is_enabled(DebugCategory::Normals) {
+            return;
+        }
+
+        for face in faces.iter().filter(|face| face.vert_indices.len() >= 3) {
+            // OBJ indices start at 1
+            let corners: Vec<Vector3D> = face.vert_indices.iter().map(|index| Vector3D::from(&verts[index - 1])).collect();
+            let normal = corners[0].subtract(&corners[1]).cross_product(&corners[0].subtract(&corners[2]));
+
+            if normal.length() == 0.0 {
+                continue;
+            }
+
+            let centre = scale(&corners.iter().cloned().fold(Vector3D { x: 0.0, y: 0.0, z: 0.0 }, |sum, corner| sum + corner), 1.0 / corners.len() as f32);
+            let tip = centre.clone() + normal.set_length(NORMAL_LENGTH);
+
+            self.line(DebugCategory::Normals, point(&centre), point(&tip), Color32::YELLOW);
+        }
+    }
+
+    /// # bounds
+    /// A sphere as three circles about `centre`, and the box around `verts`.
+    pub fn bounds(&mut self, centre: &Point3D, radius: f32, verts: &[Point3D]) {
+        if !self.is_enabled(DebugCategory::Bounds) {
+            return;
+        }
+
+        let circle_point = |angle: f32, axis: usize| {
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => Vector3D { x: 0.0, y: sin, z: cos },
+                1 => Vector3D { x: sin, y: 0.0, z: cos },
+                _ => Vector3D { x: sin, y: cos, z: 0.0 },
+            };
+
+            point(&(Vector3D::from(centre) + scale(&offset, radius)))
+        };
+
+        for axis in 0..3 {
+            for segment in 0..SPHERE_SEGMENTS {
+                let (from, to) = (segment as f32 / SPHERE_SEGMENTS as f32 * TAU, (segment + 1) as f32 / SPHERE_SEGMENTS as f32 * TAU);
+                self.line(DebugCategory::Bounds, circle_point(from, axis), circle_point(to, axis), Color32::DARK_GREEN);
+            }
+        }
+
+        let Some(first) = verts.first() else {
+            return;
+        };
+
+        let (min, max) = verts.iter().fold((first.clone(), first.clone()), |(min, max), vert| (
+            Point3D { x: min.x.min(vert.x), y: min.y.min(vert.y), z: min.z.min(vert.z) },
+            Point3D { x: max.x.max(vert.x), y: max.y.max(vert.y), z: max.z.max(vert.z) },
+        ));
+
+        // Corner i has max on the axes whose bit is set
+        let corner = |i: usize| Point3D {
+            x: if i & 1 == 0 { min.x } else { max.x },
+            y: if i & 2 == 0 { min.y } else { max.y },
+            z: if i & 4 == 0 { min.z } else { max.z },
+        };
+
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(DebugCategory::Bounds, corner(i), corner(i | bit), Color32::GOLD);
+                }
+            }
+        }
+    }
+
+    /// # frustum
+    /// The camera's look vector and the edges of what it can see, out to `FRUSTUM_LENGTH`.
+    pub fn frustum(&mut self, camera: &Camera) {
+        if !self.is_enabled(DebugCategory::Frustum) {
+            return;
+        }
+
+        let position = Vector3D::from(&camera.position);
+        let forward = camera.rotation_vector.normalise();
+        let world_up = Vector3D { x: 0.0, y: 1.0, z: 0.0 };
+
+        // Straight up or down there's no telling which way is right, any will do
+        let right = forward.cross_product(&world_up);
+        let right = if right.length() < 1e-4 { Vector3D { x: 1.0, y: 0.0, z: 0.0 } } else { right.normalise() };
+        let up = right.cross_product(&forward);
+
+        let half_width = camera.viewport.width / 2.0 / camera.near_plane_distance * FRUSTUM_LENGTH;
+        let half_height = camera.viewport.height / 2.0 / camera.near_plane_distance * FRUSTUM_LENGTH;
+        let centre = position.clone() + scale(&forward, FRUSTUM_LENGTH);
+
+        let corners: Vec<Point3D> = [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|(x, y)| point(&(centre.clone() + scale(&right, x * half_width) + scale(&up, y * half_height))))
+            .collect();
+
+        for (index, corner) in corners.iter().enumerate() {
+            self.line(DebugCategory::Frustum, camera.position.clone(), corner.clone(), Color32::WHITE);
+            self.line(DebugCategory::Frustum, corner.clone(), corners[(index + 1) % corners.len()].clone(), Color32::WHITE);
+        }
+
+        self.line(DebugCategory::Frustum, camera.position.clone(), point(&centre), Color32::RED);
+    }
+
+    /// Draws the lines through the camera, over whatever's already there.
+    pub fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        for line in &self.lines {
+            draw_line_3d(backend, camera, &line.start, &line.end, |_, _| Stroke::new(1.0, line.colour));
+        }
+    }
+}
+
+#[cfg(test)]
+impl DebugDraw {
+    pub fn lines(&self) -> &[DebugLine] {
+        &self.lines
+    }
+}
+
+/// # debug_camera
+/// A camera behind and above `camera` looking past it, for seeing its frustum.
+pub fn debug_camera(camera: &Camera) -> Camera {
+    let forward = camera.rotation_vector.normalise();
+    let position = Vector3D::from(&camera.position) + scale(&forward, -DEBUG_CAMERA_BACK) + Vector3D { x: 0.0, y: DEBUG_CAMERA_UP, z: 0.0 };
+    let target = Vector3D::from(&camera.position) + scale(&forward, FRUSTUM_LENGTH / 2.0);
+
+    let mut debug = camera.clone();
+    debug.position = point(&position);
+    debug.look_at(&point(&target));
+
+    debug
+}
+
+/// The ends of the X, Y and Z axes from the origin, with their colours.
+fn axes(length: f32) -> [(Point3D, Color32); 3] {
+    [
+        (Point3D { x: length, y: 0.0, z: 0.0 }, X_COLOUR),
+        (Point3D { x: 0.0, y: length, z: 0.0 }, Y_COLOUR),
+        (Point3D { x: 0.0, y: 0.0, z: length }, Z_COLOUR),
+    ]
+}
+
+fn scale(vector: &Vector3D, factor: f32) -> Vector3D {
+    Vector3D { x: vector.x * factor, y: vector.y * factor, z: vector.z * factor }
+}
+
+fn point(vector: &Vector3D) -> Point3D {
+    Point3D { x: vector.x, y: vector.y, z: vector.z }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::geometry::EulerAngles;
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 1.0, z: 0.0 }, 1000.0)
+    }
+
+    #[test]
+    /// # test_only_enabled_categories_draw
+    fn test_only_enabled_categories_draw() {
+        let mut debug = DebugDraw::new(&[DebugCategory::WorldAxes]);
+
+        debug.world_axes();
+        debug.frustum(&camera());
+        debug.line(DebugCategory::Normals, Point3D { x: 0.0, y: 0.0, z: 0.0 }, Point3D { x: 1.0, y: 0.0, z: 0.0 }, Color32::WHITE);
+
+        assert_eq!(debug.lines().len(), 3);
+        assert_eq!(debug.lines()[0].end, Point3D { x: WORLD_AXES_LENGTH, y: 0.0, z: 0.0 });
+    }
+
+    #[test]
+    /// # test_local_axes_follow_mesh
+    /// A mesh turned a quarter about Y and moved has its axes turned and moved with it.
+    fn test_local_axes_follow_mesh() {
+        let mut debug = DebugDraw::new(&[DebugCategory::LocalAxes]);
+        let mesh = Mesh {
+            verts: vec![],
+            faces: vec![],
+            position: Vector3D { x: 10.0, y: 0.0, z: 5.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 90.0_f32.to_radians(), roll: 0.0 },
+        };
+
+        debug.local_axes(&mesh);
+
+        let lines = debug.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].start, Point3D { x: 10.0, y: 0.0, z: 5.0 });
+        // The X axis ends up along Z one way or the other, and Y stays up
+        assert!((lines[0].end.x - 10.0).abs() < 1e-4 && (lines[0].end.z - 5.0).abs() > LOCAL_AXES_LENGTH - 1e-4, "{:?}", lines[0]);
+        assert!((lines[1].end.y - LOCAL_AXES_LENGTH).abs() < 1e-4, "{:?}", lines[1]);
+    }
+
+    #[test]
+    /// # test_normals_and_bounds
+    fn test_normals_and_bounds() {
+        let mut debug = DebugDraw::new(&[DebugCategory::Normals, DebugCategory::Bounds]);
+        let verts = vec![
+            Point3D { x: 0.0, y: 0.0, z: 0.0 },
+            Point3D { x: 1.0, y: 0.0, z: 0.0 },
+            Point3D { x: 0.0, y: 1.0, z: 0.0 },
+        ];
+
+        debug.normals(&verts, &[Face { vert_indices: vec![1, 2, 3] }]);
+        let normal = &debug.lines()[0];
+        assert!((normal.start.x - 1.0 / 3.0).abs() < 1e-5);
+        assert!(normal.end.x == normal.start.x && normal.end.y == normal.start.y);
+        assert!(((normal.end.z - normal.start.z).abs() - NORMAL_LENGTH).abs() < 1e-5);
+
+        debug.bounds(&Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1.0, &verts);
+        assert_eq!(debug.lines().len(), 1 + 3 * SPHERE_SEGMENTS + 12);
+    }
+
+    #[test]
+    /// # test_frustum_from_debug_camera
+    /// The debug camera sees the whole of the game camera's frustum.
+    fn test_frustum_from_debug_camera() {
+        let camera = camera();
+        let mut debug = DebugDraw::new(&[DebugCategory::Frustum]);
+        debug.frustum(&camera);
+        assert_eq!(debug.lines().len(), 9);
+
+        let viewer = debug_camera(&camera);
+        assert!(viewer.depth(&camera.position) > 0.0);
+
+        for line in debug.lines() {
+            assert!(viewer.depth(&line.end) > 0.0, "{:?}", line);
+        }
+    }
+}
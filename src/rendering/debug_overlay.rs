@@ -0,0 +1,163 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
+use crate::utils::profiling::{self, Counter, FrameStats, Timing};
+
+/// Frames shown in the frame time graph, which is also what the FPS is averaged over.
+const GRAPH_FRAMES: usize = 120;
+
+/// The frame time at the top of the graph, in milliseconds. Slower frames go off the top.
+const GRAPH_MAX_MS: f32 = 50.0;
+
+/// Frame times the graph marks, 60 and 30 FPS.
+const GRAPH_MARKS_MS: [f32; 2] = [1000.0 / 60.0, 1000.0 / 30.0];
+
+const WIDTH: f32 = 300.0;
+const GRAPH_HEIGHT: f32 = 60.0;
+const LINE_HEIGHT: f32 = 16.0;
+const MARGIN: f32 = 10.0;
+
+/// # DebugOverlay
+/// FPS, a graph of recent frame times and where the last frame's time went, with how much was
+/// drawn, in the top right corner. The timings come from the `profile_scope!`s in the renderer.
+#[derive(Debug, Default)]
+pub struct DebugOverlay {
+    pub open: bool,
+    /// Recent frame times in milliseconds, oldest first.
+    frame_times: VecDeque<f32>,
+    /// The last whole frame measured.
+    stats: FrameStats,
+}
+
+impl DebugOverlay {
+    /// Adds a frame that took `delta_time` seconds, and what was measured over it.
+    pub fn update(&mut self, delta_time: f32, stats: FrameStats) {
+        if self.frame_times.len() == GRAPH_FRAMES {
+            self.frame_times.pop_front();
+        }
+
+        self.frame_times.push_back(delta_time * 1000.0);
+        self.stats = stats;
+    }
+
+    /// Frames per second over the frames in the graph.
+    pub fn fps(&self) -> f32 {
+        let total: f32 = self.frame_times.iter().sum();
+
+        if total > 0.0 { self.frame_times.len() as f32 * 1000.0 / total } else { 0.0 }
+    }
+
+    /// # lines
+    /// The overlay's text. Drawing is what's left of rendering the level once the transforms and
+    /// projections done for it are taken away. The counts add up every view of the level drawn.
+    pub fn lines(&self) -> Vec<String> {
+        let frame_ms = self.frame_times.back().copied().unwrap_or(0.0);
+        let mut lines = vec![format!("FPS {:.0} ({:.1} MS)", self.fps(), frame_ms)];
+
+        if !profiling::ENABLED {
+            lines.push("BUILT WITHOUT PROFILING".to_string());
+            return lines;
+        }
+
+        let stats = &self.stats;
+        let ms = |time: Duration| time.as_secs_f32() * 1000.0;
+        let (transform, projection) = (stats.time(Timing::Transform), stats.time(Timing::Projection));
+        let draw = stats.time(Timing::Render).saturating_sub(transform + projection);
+
+        lines.extend([
+            format!("TRANSFORM {:.2} MS", ms(transform)),
+            format!("PROJECTION {:.2} MS", ms(projection)),
+            format!("DRAW {:.2} MS", ms(draw)),
+            format!("VIEWS {}", stats.count(Counter::Views)),
+            format!("MESHES {}", stats.count(Counter::MeshesDrawn)),
+            format!("FACES {} EDGES {}", stats.count(Counter::Faces), stats.count(Counter::Edges)),
+            format!("SEGMENTS {}", stats.count(Counter::Segments)),
+        ]);
+
+        lines
+    }
+
+    pub fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        if !self.open {
+            return;
+        }
+
+        let lines = self.lines();
+        let (left, right) = (camera.viewport.width - WIDTH - MARGIN, camera.viewport.width - MARGIN);
+        let graph_top = MARGIN + lines.len() as f32 * LINE_HEIGHT + MARGIN;
+        let bottom = graph_top + GRAPH_HEIGHT + MARGIN;
+
+        backend.polygon(
+            &[Pos2::new(left, MARGIN), Pos2::new(right, MARGIN), Pos2::new(right, bottom), Pos2::new(left, bottom)],
+            Color32::from_black_alpha(200),
+            Stroke::new(1.0, Color32::DARK_GREEN),
+        );
+
+        let mut style = TextStyle::new(10.0);
+        style.align = Align2::LEFT_TOP;
+
+        for (index, line) in lines.iter().enumerate() {
+            let position = Pos2::new(left + MARGIN, MARGIN * 1.5 + index as f32 * LINE_HEIGHT);
+            backend.text(line, position, &style, Stroke::new(1.0, Color32::GREEN));
+        }
+
+        // Newest frame on the right, one bar per frame
+        let graph_bottom = graph_top + GRAPH_HEIGHT;
+        let bar_width = (WIDTH - 2.0 * MARGIN) / GRAPH_FRAMES as f32;
+        let height = |ms: f32| (ms / GRAPH_MAX_MS).min(1.0) * GRAPH_HEIGHT;
+
+        for ms in GRAPH_MARKS_MS {
+            let y = graph_bottom - height(ms);
+            backend.line([Pos2::new(left + MARGIN, y), Pos2::new(right - MARGIN, y)], Stroke::new(1.0, Color32::DARK_GREEN));
+        }
+
+        let first = GRAPH_FRAMES - self.frame_times.len();
+
+        for (index, ms) in self.frame_times.iter().enumerate() {
+            let x = left + MARGIN + (first + index) as f32 * bar_width;
+            let colour = if *ms > GRAPH_MARKS_MS[1] { Color32::RED } else if *ms > GRAPH_MARKS_MS[0] { Color32::YELLOW } else { Color32::GREEN };
+
+            backend.line([Pos2::new(x, graph_bottom), Pos2::new(x, graph_bottom - height(*ms))], Stroke::new(1.0, colour));
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_debug_overlay_lines
+    /// FPS is averaged over recent frames, and drawing's time doesn't include the transforms and
+    /// projections rendering did.
+    fn test_debug_overlay_lines() {
+        let mut overlay = DebugOverlay::default();
+
+        for _ in 0..GRAPH_FRAMES * 2 {
+            overlay.update(0.02, FrameStats::default());
+        }
+        assert!((overlay.fps() - 50.0).abs() < 0.01);
+
+        // A 5 ms render with 2 ms of transforming and 1 ms of projecting inside it
+        let stats = FrameStats::default()
+            .with_time(Timing::Render, Duration::from_millis(5))
+            .with_time(Timing::Transform, Duration::from_millis(2))
+            .with_time(Timing::Projection, Duration::from_millis(1))
+            .with_count(Counter::Views, 2)
+            .with_count(Counter::MeshesDrawn, 2);
+        overlay.update(0.02, stats);
+
+        assert_eq!(overlay.lines()[..6], [
+            "FPS 50 (20.0 MS)",
+            "TRANSFORM 2.00 MS",
+            "PROJECTION 1.00 MS",
+            "DRAW 2.00 MS",
+            "VIEWS 2",
+            "MESHES 2",
+        ]);
+    }
+}
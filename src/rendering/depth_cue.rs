@@ -0,0 +1,194 @@
+use eframe::egui::{Color32, Stroke};
+use serde::{Deserialize, Serialize};
+use crate::player::camera::Camera;
+
+/// How the fade progresses between the near and far distances. Each maps 0.0 (at `near`) to 0.0
+/// and 1.0 (at `far`) to 1.0.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum DepthCurve {
+    Linear,
+    /// Slow to start, most of the fade happens in the distance.
+    EaseIn,
+    /// Fades quickly close up and levels off in the distance.
+    EaseOut,
+    SmoothStep,
+    /// Like fog, higher densities fade faster close up.
+    Exponential { density: f32 },
+}
+
+impl DepthCurve {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            DepthCurve::Linear => t,
+            DepthCurve::EaseIn => t * t,
+            DepthCurve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            DepthCurve::SmoothStep => t * t * (3.0 - 2.0 * t),
+            DepthCurve::Exponential { density } if *density > 0.0 => (1.0 - (-density * t).exp()) / (1.0 - (-density).exp()),
+            DepthCurve::Exponential { .. } => t,
+        }
+    }
+}
+
+/// # DepthCue
+/// Makes distant lines dimmer and thinner so depth reads in a wireframe world. Anything nearer than
+/// `near` is drawn as given, by `far` (the camera's far plane if unset) lines are down to the
+/// minimum intensity and width. In between the fade follows the curves. The default `far` is well
+/// short of the far plane so the fade happens across what's usually in view.
+///
+/// With a fog colour set lines fade towards the fog instead of towards black.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DepthCue {
+    pub enabled: bool,
+    pub near: f32,
+    pub far: Option<f32>,
+    pub intensity_curve: DepthCurve,
+    pub width_curve: DepthCurve,
+    pub min_intensity: f32,
+    /// The thinnest a line gets, as a multiple of its stroke width.
+    pub min_width: f32,
+    /// RGB
+    pub fog: Option<[u8; 3]>,
+}
+
+impl Default for DepthCue {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            near: 5.0,
+            far: Some(200.0),
+            intensity_curve: DepthCurve::EaseOut,
+            width_curve: DepthCurve::Linear,
+            min_intensity: 0.2,
+            min_width: 0.4,
+            fog: None,
+        }
+    }
+}
+
+impl DepthCue {
+    /// How far through the fade `depth` is, from 0.0 at `near` to 1.0 at `far`.
+    fn fade(&self, camera: &Camera, depth: f32) -> f32 {
+        let far = self.far.unwrap_or(camera.far_plane_distance());
+
+        if far <= self.near {
+            return if depth > self.near { 1.0 } else { 0.0 };
+        }
+
+        ((depth - self.near) / (far - self.near)).clamp(0.0, 1.0)
+    }
+
+    /// # stroke
+    /// The stroke to draw a line at camera space `depth` with.
+    pub fn stroke(&self, camera: &Camera, stroke: Stroke, depth: f32) -> Stroke {
+        if !self.enabled {
+            return stroke;
+        }
+
+        let fade = self.fade(camera, depth);
+        let intensity = 1.0 - self.intensity_curve.apply(fade) * (1.0 - self.min_intensity);
+        let width = 1.0 - self.width_curve.apply(fade) * (1.0 - self.min_width);
+
+        let colour = match self.fog {
+            Some([r, g, b]) => stroke.color.lerp_to_gamma(Color32::from_rgb(r, g, b), 1.0 - intensity),
+            None => stroke.color.gamma_multiply(intensity),
+        };
+
+        Stroke::new(stroke.width * width, colour)
+    }
+
+    /// The stroke for an edge between two points, going by the middle of the edge.
+    pub fn edge_stroke(&self, camera: &Camera, stroke: Stroke, start_depth: f32, end_depth: f32) -> Stroke {
+        self.stroke(camera, stroke, (start_depth + end_depth) / 2.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::geometry::*;
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 105.0)
+    }
+
+    fn linear() -> DepthCue {
+        DepthCue { far: None, intensity_curve: DepthCurve::Linear, width_curve: DepthCurve::Linear, ..DepthCue::default() }
+    }
+
+    #[test]
+    /// # test_curves_run_from_zero_to_one
+    fn test_curves_run_from_zero_to_one() {
+        let curves = [
+            DepthCurve::Linear,
+            DepthCurve::EaseIn,
+            DepthCurve::EaseOut,
+            DepthCurve::SmoothStep,
+            DepthCurve::Exponential { density: 4.0 },
+        ];
+
+        for curve in curves {
+            assert!(curve.apply(0.0).abs() < 1e-6, "{:?}", curve);
+            assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", curve);
+            assert!(curve.apply(0.25) < curve.apply(0.75), "{:?}", curve);
+        }
+
+        assert!(DepthCurve::EaseOut.apply(0.5) > DepthCurve::Linear.apply(0.5));
+        assert!(DepthCurve::EaseIn.apply(0.5) < DepthCurve::Linear.apply(0.5));
+    }
+
+    #[test]
+    /// # test_near_lines_are_untouched
+    fn test_near_lines_are_untouched() {
+        let stroke = Stroke::new(2.0, Color32::GREEN);
+
+        assert_eq!(linear().stroke(&camera(), stroke, 1.0), stroke);
+        assert_eq!(linear().stroke(&camera(), stroke, 5.0), stroke);
+    }
+
+    #[test]
+    /// # test_far_lines_are_dim_and_thin
+    /// At and beyond the camera's far plane lines are at the minimum.
+    fn test_far_lines_are_dim_and_thin() {
+        let cue = linear();
+        let stroke = Stroke::new(2.0, Color32::GREEN);
+
+        for depth in [105.0, 500.0] {
+            let far = cue.stroke(&camera(), stroke, depth);
+            assert!((far.width - 2.0 * cue.min_width).abs() < 1e-5);
+            assert_eq!(far.color, Color32::GREEN.gamma_multiply(cue.min_intensity));
+        }
+
+        // Half way there with linear curves
+        let middle = cue.stroke(&camera(), stroke, 55.0);
+        assert!((middle.width - 2.0 * 0.7).abs() < 1e-5);
+    }
+
+    #[test]
+    /// # test_far_overrides_camera
+    fn test_far_overrides_camera() {
+        let cue = DepthCue { far: Some(15.0), ..linear() };
+
+        assert_eq!(cue.stroke(&camera(), Stroke::new(1.0, Color32::GREEN), 15.0).color, Color32::GREEN.gamma_multiply(cue.min_intensity));
+    }
+
+    #[test]
+    /// # test_fog_colour
+    fn test_fog_colour() {
+        let cue = DepthCue { fog: Some([0, 0, 255]), min_intensity: 0.0, ..linear() };
+
+        assert_eq!(cue.stroke(&camera(), Stroke::new(1.0, Color32::GREEN), 200.0).color, Color32::BLUE);
+    }
+
+    #[test]
+    /// # test_disabled
+    fn test_disabled() {
+        let cue = DepthCue { enabled: false, ..linear() };
+        let stroke = Stroke::new(2.0, Color32::GREEN);
+
+        assert_eq!(cue.stroke(&camera(), stroke, 1000.0), stroke);
+    }
+}
@@ -0,0 +1,13 @@
+pub mod background;
+pub mod backend;
+pub mod crt;
+pub mod debug_draw;
+pub mod debug_overlay;
+pub mod depth_cue;
+pub mod raster;
+#[cfg(test)]
+pub mod recorder;
+pub mod stroke_font;
+pub mod svg;
+pub mod viewport;
+pub mod wireframe;
\ No newline at end of file
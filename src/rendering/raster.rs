@@ -0,0 +1,354 @@
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use eframe::egui::{Color32, Pos2, Stroke};
+use crate::rendering::backend::RenderBackend;
+
+/// How far apart two channels can be before `Raster::difference` counts the pixel as changed.
+#[cfg(test)]
+const CHANNEL_TOLERANCE: u8 = 16;
+
+/// # Raster
+/// An in memory RGBA image that frames can be rendered into without a window, e.g. for snapshot
+/// tests. Lines are drawn with hard edges (a pixel is either covered or not) so the output is the
+/// same on every machine.
+///
+/// Pixels are kept premultiplied like egui's `Color32` and blended source over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color32>,
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, background: Color32) -> Self {
+        Self { width, height, pixels: vec![background; width * height] }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, colour: Color32) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+
+        let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+        let keep = 255 - colour.a() as u16;
+        let mix = |src: u8, dst: u8| (src as u16 + (dst as u16 * keep + 127) / 255).min(255) as u8;
+
+        *pixel = Color32::from_rgba_premultiplied(
+            mix(colour.r(), pixel.r()),
+            mix(colour.g(), pixel.g()),
+            mix(colour.b(), pixel.b()),
+            mix(colour.a(), pixel.a()),
+        );
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let data: Vec<u8> = self.pixels.iter().flat_map(|pixel| pixel.to_srgba_unmultiplied()).collect();
+        encoder.write_header()?.write_image_data(&data)?;
+
+        Ok(())
+    }
+
+    /// Saves as a binary PPM. PPM has no alpha so the image is flattened onto black.
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut data = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        data.extend(self.pixels.iter().flat_map(|pixel| [pixel.r(), pixel.g(), pixel.b()]));
+
+        fs::write(path, data)?;
+        Ok(())
+    }
+}
+
+/// Snapshot test helpers, for comparing rendered frames with golden images.
+#[cfg(test)]
+impl Raster {
+    pub fn pixel(&self, x: usize, y: usize) -> Color32 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Loads an 8 bit RGBA PNG, as written by `save_png`.
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut reader = png::Decoder::new(File::open(path)?).read_info()?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data)?;
+
+        if info.color_type != png::ColorType::Rgba || info.bit_depth != png::BitDepth::Eight {
+            return Err(format!("Expected an 8 bit RGBA PNG, got {:?} {:?}", info.color_type, info.bit_depth).into());
+        }
+
+        let pixels = data[..info.buffer_size()]
+            .chunks_exact(4)
+            .map(|rgba| Color32::from_rgba_unmultiplied(rgba[0], rgba[1], rgba[2], rgba[3]))
+            .collect();
+
+        Ok(Self { width: info.width as usize, height: info.height as usize, pixels })
+    }
+
+    /// # difference
+    /// The fraction of pixels (0.0 to 1.0) that differ noticeably between two rasters. Rasters of
+    /// different sizes are completely different.
+    pub fn difference(&self, other: &Raster) -> f32 {
+        if self.width != other.width || self.height != other.height {
+            return 1.0;
+        }
+
+        let changed = self.pixels
+            .iter()
+            .zip(&other.pixels)
+            .filter(|(a, b)| a.to_array().iter().zip(b.to_array()).any(|(a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE))
+            .count();
+
+        changed as f32 / self.pixels.len() as f32
+    }
+}
+
+/// Squared distance from `point` to the segment between `start` and `end`.
+fn distance_sq_to_segment(point: Pos2, start: Pos2, end: Pos2) -> f32 {
+    let along = end - start;
+    let length_sq = along.length_sq();
+
+    let t = if length_sq > 0.0 { ((point - start).dot(along) / length_sq).clamp(0.0, 1.0) } else { 0.0 };
+
+    (start + along * t).distance_sq(point)
+}
+
+impl RenderBackend for Raster {
+    /// Covers every pixel whose centre is less than half the stroke width from the line. Strokes
+    /// thinner than a pixel are drawn a pixel wide so they don't vanish.
+    fn line(&mut self, [start, end]: [Pos2; 2], stroke: Stroke) {
+        if stroke.is_empty() || !start.is_finite() || !end.is_finite() {
+            return;
+        }
+
+        let half_width = stroke.width.max(1.0) / 2.0;
+
+        // Only visit the part of the bounding box that's on the raster
+        let min_x = (start.x.min(end.x) - half_width).floor().max(0.0) as i64;
+        let max_x = (start.x.max(end.x) + half_width).ceil().min(self.width as f32) as i64;
+        let min_y = (start.y.min(end.y) - half_width).floor().max(0.0) as i64;
+        let max_y = (start.y.max(end.y) + half_width).ceil().min(self.height as f32) as i64;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let centre = Pos2::new(x as f32 + 0.5, y as f32 + 0.5);
+
+                if distance_sq_to_segment(centre, start, end) < half_width * half_width {
+                    self.blend(x, y, stroke.color);
+                }
+            }
+        }
+    }
+
+    /// Fills with the even-odd rule, a pixel is filled if its centre is inside.
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        if points.len() < 2 || points.iter().any(|point| !point.is_finite()) {
+            return;
+        }
+
+        let edges: Vec<(Pos2, Pos2)> = (0..points.len()).map(|i| (points[i], points[(i + 1) % points.len()])).collect();
+
+        if fill != Color32::TRANSPARENT {
+            let min_y = points.iter().map(|point| point.y).fold(f32::INFINITY, f32::min).floor().max(0.0) as i64;
+            let max_y = points.iter().map(|point| point.y).fold(f32::NEG_INFINITY, f32::max).ceil().min(self.height as f32) as i64;
+
+            for y in min_y..max_y {
+                let centre_y = y as f32 + 0.5;
+
+                let mut crossings: Vec<f32> = edges
+                    .iter()
+                    .filter(|(a, b)| (a.y <= centre_y) != (b.y <= centre_y))
+                    .map(|(a, b)| a.x + (centre_y - a.y) / (b.y - a.y) * (b.x - a.x))
+                    .collect();
+                crossings.sort_by(f32::total_cmp);
+
+                for span in crossings.chunks_exact(2) {
+                    let start = (span[0] - 0.5).ceil() as i64;
+                    let end = (span[1] - 0.5).floor() as i64;
+
+                    for x in start.max(0)..=end.min(self.width as i64 - 1) {
+                        self.blend(x, y, fill);
+                    }
+                }
+            }
+        }
+
+        for (start, end) in edges {
+            self.line([start, end], stroke);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::mesh::Mesh;
+    use crate::player::camera::Camera;
+    use crate::rendering::wireframe::draw_wireframe;
+    use crate::screens::level1_screen::{load_model_faces, load_model_verts};
+    use crate::types::geometry::*;
+
+    /// Most of the frame a snapshot can differ from its golden image by, to allow for small
+    /// floating point differences moving the odd pixel.
+    const SNAPSHOT_TOLERANCE: f32 = 0.002;
+
+    fn temp_path(file_name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("vectar-{}-{}", std::process::id(), file_name))
+    }
+
+    /// # assert_matches_golden
+    /// Compares the raster with `tests/golden/<name>.png`. Run the tests with `UPDATE_GOLDEN=1` to
+    /// write the golden images after an intended change to the rendering.
+    fn assert_matches_golden(raster: &Raster, name: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            raster.save_png(&path).unwrap();
+        }
+
+        let golden = Raster::load_png(&path).unwrap_or_else(|error| panic!("Failed to load {}: {}", path.display(), error));
+        let difference = raster.difference(&golden);
+
+        if difference > SNAPSHOT_TOLERANCE {
+            let actual = temp_path(&format!("{}-actual.png", name));
+            raster.save_png(&actual).unwrap();
+            panic!("{} differs from its golden image by {:.2}%, see {}", name, difference * 100.0, actual.display());
+        }
+    }
+
+    fn render_cube(rotation: EulerAngles) -> Raster {
+        let model_name = "cube.obj".to_string();
+        let cube = Mesh {
+            verts: load_model_verts(&model_name),
+            faces: load_model_faces(&model_name),
+            position: Vector3D { x: 0.0, y: -1.0, z: 0.0 },
+            rotation,
+        };
+
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: -5.0 }, 1000.0);
+        camera.update_viewport_size(Rectangle { width: 160.0, height: 120.0 });
+
+        let mut raster = Raster::new(160, 120, Color32::BLACK);
+        draw_wireframe(&mut raster, &camera, &cube, Stroke::new(1.0, Color32::GREEN));
+
+        raster
+    }
+
+    #[test]
+    /// # test_line_covers_pixels
+    fn test_line_covers_pixels() {
+        let mut raster = Raster::new(8, 4, Color32::BLACK);
+        raster.line([Pos2::new(1.0, 1.5), Pos2::new(6.0, 1.5)], Stroke::new(1.0, Color32::WHITE));
+
+        for x in 0..8 {
+            let expected = if (1..6).contains(&x) { Color32::WHITE } else { Color32::BLACK };
+            assert_eq!(raster.pixel(x, 1), expected, "x = {}", x);
+        }
+
+        assert_eq!(raster.pixel(3, 0), Color32::BLACK);
+        assert_eq!(raster.pixel(3, 2), Color32::BLACK);
+    }
+
+    #[test]
+    /// # test_lines_off_the_raster_are_clipped
+    fn test_lines_off_the_raster_are_clipped() {
+        let mut raster = Raster::new(4, 4, Color32::BLACK);
+        raster.line([Pos2::new(-100.0, 2.5), Pos2::new(100.0, 2.5)], Stroke::new(1.0, Color32::WHITE));
+        raster.line([Pos2::new(f32::NAN, 0.0), Pos2::new(2.0, 2.0)], Stroke::new(1.0, Color32::WHITE));
+
+        assert_eq!(raster.pixel(0, 2), Color32::WHITE);
+        assert_eq!(raster.pixel(3, 2), Color32::WHITE);
+        assert_eq!(raster.pixel(0, 0), Color32::BLACK);
+    }
+
+    #[test]
+    /// # test_translucent_lines_blend
+    fn test_translucent_lines_blend() {
+        let mut raster = Raster::new(1, 1, Color32::BLACK);
+        raster.line([Pos2::new(0.0, 0.5), Pos2::new(1.0, 0.5)], Stroke::new(1.0, Color32::WHITE.gamma_multiply(0.5)));
+
+        assert!((raster.pixel(0, 0).r() as i32 - 128).abs() <= 1);
+    }
+
+    #[test]
+    /// # test_polygon_fill
+    fn test_polygon_fill() {
+        let mut raster = Raster::new(10, 10, Color32::BLACK);
+        let square = [Pos2::new(2.0, 2.0), Pos2::new(8.0, 2.0), Pos2::new(8.0, 8.0), Pos2::new(2.0, 8.0)];
+        raster.polygon(&square, Color32::RED, Stroke::NONE);
+
+        assert_eq!(raster.pixel(5, 5), Color32::RED);
+        assert_eq!(raster.pixel(2, 2), Color32::RED);
+        assert_eq!(raster.pixel(8, 5), Color32::BLACK);
+        assert_eq!(raster.pixel(1, 5), Color32::BLACK);
+    }
+
+    #[test]
+    /// # test_png_round_trip
+    fn test_png_round_trip() {
+        let mut raster = Raster::new(16, 8, Color32::BLACK);
+        raster.text("HI", Pos2::new(1.0, 1.0), &crate::rendering::stroke_font::TextStyle::new(6.0), Stroke::new(1.0, Color32::GREEN));
+
+        let path = temp_path("round-trip.png");
+        raster.save_png(&path).unwrap();
+        let loaded = Raster::load_png(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(loaded, raster);
+    }
+
+    #[test]
+    /// # test_ppm_output
+    fn test_ppm_output() {
+        let raster = Raster::new(3, 2, Color32::from_rgb(1, 2, 3));
+
+        let path = temp_path("output.ppm");
+        raster.save_ppm(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert!(data.starts_with(b"P6\n3 2\n255\n"));
+        assert_eq!(&data[data.len() - 3..], &[1, 2, 3]);
+        assert_eq!(data.len(), 11 + 3 * 2 * 3);
+    }
+
+    #[test]
+    /// # test_difference
+    fn test_difference() {
+        let black = Raster::new(10, 10, Color32::BLACK);
+        let mut one_pixel = black.clone();
+        one_pixel.line([Pos2::new(0.0, 0.5), Pos2::new(1.0, 0.5)], Stroke::new(1.0, Color32::WHITE));
+
+        assert_eq!(black.difference(&black), 0.0);
+        assert_eq!(black.difference(&one_pixel), 0.01);
+        assert_eq!(black.difference(&Raster::new(5, 5, Color32::BLACK)), 1.0);
+    }
+
+    #[test]
+    /// # test_cube_front_snapshot
+    fn test_cube_front_snapshot() {
+        assert_matches_golden(&render_cube(EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 }), "cube_front");
+    }
+
+    #[test]
+    /// # test_cube_rotated_snapshot
+    fn test_cube_rotated_snapshot() {
+        let rotated = render_cube(EulerAngles { pitch: 0.4, yaw: 0.7, roll: 0.2 });
+
+        assert_matches_golden(&rotated, "cube_rotated");
+        assert!(rotated.difference(&render_cube(EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 })) > SNAPSHOT_TOLERANCE);
+    }
+}
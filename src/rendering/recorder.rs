@@ -0,0 +1,39 @@
+use eframe::egui::{Color32, Pos2, Stroke};
+use crate::rendering::backend::RenderBackend;
+
+/// # Recorder
+/// A backend for tests that keeps everything drawn to it, in the order it was drawn, so tests can
+/// check what a renderer drew without rasterising it.
+#[derive(Default)]
+pub struct Recorder {
+    pub lines: Vec<([Pos2; 2], Stroke)>,
+    pub polygons: Vec<(Vec<Pos2>, Color32, Stroke)>,
+    pub circles: Vec<(Pos2, f32, Color32, Stroke)>,
+}
+
+impl Recorder {
+    /// Every point drawn: the ends of the lines, then the polygons' corners, then the circles'
+    /// centres.
+    pub fn points(&self) -> Vec<Pos2> {
+        self.lines
+            .iter()
+            .flat_map(|(points, _)| *points)
+            .chain(self.polygons.iter().flat_map(|(points, _, _)| points.iter().copied()))
+            .chain(self.circles.iter().map(|(centre, _, _, _)| *centre))
+            .collect()
+    }
+}
+
+impl RenderBackend for Recorder {
+    fn line(&mut self, points: [Pos2; 2], stroke: Stroke) {
+        self.lines.push((points, stroke));
+    }
+
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        self.polygons.push((points.to_vec(), fill, stroke));
+    }
+
+    fn circle(&mut self, centre: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        self.circles.push((centre, radius, fill, stroke));
+    }
+}
@@ -0,0 +1,340 @@
+//! # Stroke Font
+//! A Hershey style vector font in the spirit of the old arcade vector monitors. Every glyph is a
+//! handful of polylines on a small grid 4 units wide and 6 units tall with the baseline at 0, so
+//! text is nothing more than line segments and can be scaled, rotated and skewed freely before
+//! being handed to the painter (or projected into the 3D world).
+//!
+//! Lowercase letters are drawn as uppercase, and anything without a glyph is drawn as a `?`.
+
+use eframe::egui::{Align2, Pos2, Stroke, Vec2};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::types::geometry::*;
+
+const GLYPH_WIDTH: f32 = 4.0;
+const GLYPH_HEIGHT: f32 = 6.0;
+const GLYPH_ADVANCE: f32 = 6.0;
+const LINE_ADVANCE: f32 = 9.0;
+
+type Polyline = &'static [(i8, i8)];
+
+fn glyph(c: char) -> &'static [Polyline] {
+    match c.to_ascii_uppercase() {
+        'A' => &[&[(0, 0), (0, 4), (2, 6), (4, 4), (4, 0)], &[(0, 3), (4, 3)]],
+        'B' => &[&[(0, 0), (0, 6), (3, 6), (4, 5), (4, 4), (3, 3), (0, 3)], &[(3, 3), (4, 2), (4, 1), (3, 0), (0, 0)]],
+        'C' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)]],
+        'D' => &[&[(0, 0), (0, 6), (2, 6), (4, 4), (4, 2), (2, 0), (0, 0)]],
+        'E' => &[&[(4, 0), (0, 0), (0, 6), (4, 6)], &[(0, 3), (3, 3)]],
+        'F' => &[&[(0, 0), (0, 6), (4, 6)], &[(0, 3), (3, 3)]],
+        'G' => &[&[(2, 3), (4, 3), (4, 0), (0, 0), (0, 6), (4, 6), (4, 5)]],
+        'H' => &[&[(0, 0), (0, 6)], &[(4, 0), (4, 6)], &[(0, 3), (4, 3)]],
+        'I' => &[&[(0, 0), (4, 0)], &[(2, 0), (2, 6)], &[(0, 6), (4, 6)]],
+        'J' => &[&[(0, 1), (1, 0), (3, 0), (4, 1), (4, 6)]],
+        'K' => &[&[(0, 0), (0, 6)], &[(4, 6), (0, 3), (4, 0)]],
+        'L' => &[&[(0, 6), (0, 0), (4, 0)]],
+        'M' => &[&[(0, 0), (0, 6), (2, 4), (4, 6), (4, 0)]],
+        'N' => &[&[(0, 0), (0, 6), (4, 0), (4, 6)]],
+        'O' => &[&[(0, 0), (0, 6), (4, 6), (4, 0), (0, 0)]],
+        'P' => &[&[(0, 0), (0, 6), (4, 6), (4, 3), (0, 3)]],
+        'Q' => &[&[(0, 0), (0, 6), (4, 6), (4, 2), (2, 0), (0, 0)], &[(2, 2), (4, 0)]],
+        'R' => &[&[(0, 0), (0, 6), (4, 6), (4, 3), (0, 3)], &[(1, 3), (4, 0)]],
+        'S' => &[&[(0, 0), (4, 0), (4, 3), (0, 3), (0, 6), (4, 6)]],
+        'T' => &[&[(0, 6), (4, 6)], &[(2, 6), (2, 0)]],
+        'U' => &[&[(0, 6), (0, 0), (4, 0), (4, 6)]],
+        'V' => &[&[(0, 6), (2, 0), (4, 6)]],
+        'W' => &[&[(0, 6), (1, 0), (2, 3), (3, 0), (4, 6)]],
+        'X' => &[&[(0, 0), (4, 6)], &[(0, 6), (4, 0)]],
+        'Y' => &[&[(0, 6), (2, 3), (4, 6)], &[(2, 3), (2, 0)]],
+        'Z' => &[&[(0, 6), (4, 6), (0, 0), (4, 0)]],
+        '0' => &[&[(0, 0), (0, 6), (4, 6), (4, 0), (0, 0), (4, 6)]],
+        '1' => &[&[(1, 5), (2, 6), (2, 0)], &[(1, 0), (3, 0)]],
+        '2' => &[&[(0, 6), (4, 6), (4, 3), (0, 3), (0, 0), (4, 0)]],
+        '3' => &[&[(0, 6), (4, 6), (4, 0), (0, 0)], &[(0, 3), (4, 3)]],
+        '4' => &[&[(0, 6), (0, 3), (4, 3)], &[(4, 6), (4, 0)]],
+        '5' => &[&[(4, 6), (0, 6), (0, 3), (4, 3), (4, 0), (0, 0)]],
+        '6' => &[&[(4, 6), (0, 6), (0, 0), (4, 0), (4, 3), (0, 3)]],
+        '7' => &[&[(0, 6), (4, 6), (4, 0)]],
+        '8' => &[&[(0, 0), (0, 6), (4, 6), (4, 0), (0, 0)], &[(0, 3), (4, 3)]],
+        '9' => &[&[(4, 3), (0, 3), (0, 6), (4, 6), (4, 0), (0, 0)]],
+        ' ' => &[],
+        '.' => &[&[(2, 0), (2, 1)]],
+        ',' => &[&[(2, 1), (1, -1)]],
+        ':' => &[&[(2, 1), (2, 2)], &[(2, 4), (2, 5)]],
+        ';' => &[&[(2, 1), (1, -1)], &[(2, 4), (2, 5)]],
+        '-' => &[&[(1, 3), (3, 3)]],
+        '+' => &[&[(1, 3), (3, 3)], &[(2, 2), (2, 4)]],
+        '*' => &[&[(2, 1), (2, 5)], &[(0, 2), (4, 4)], &[(0, 4), (4, 2)]],
+        '/' => &[&[(0, 0), (4, 6)]],
+        '=' => &[&[(1, 2), (3, 2)], &[(1, 4), (3, 4)]],
+        '_' => &[&[(0, -1), (4, -1)]],
+        '!' => &[&[(2, 6), (2, 2)], &[(2, 0), (2, 1)]],
+        '\'' => &[&[(2, 6), (2, 4)]],
+        '"' => &[&[(1, 6), (1, 4)], &[(3, 6), (3, 4)]],
+        '(' => &[&[(3, 6), (1, 4), (1, 2), (3, 0)]],
+        ')' => &[&[(1, 6), (3, 4), (3, 2), (1, 0)]],
+        '[' => &[&[(3, 6), (1, 6), (1, 0), (3, 0)]],
+        ']' => &[&[(1, 6), (3, 6), (3, 0), (1, 0)]],
+        '{' => &[&[(3, 6), (2, 5), (2, 4), (1, 3), (2, 2), (2, 1), (3, 0)]],
+        '}' => &[&[(1, 6), (2, 5), (2, 4), (3, 3), (2, 2), (2, 1), (1, 0)]],
+        '<' => &[&[(3, 5), (1, 3), (3, 1)]],
+        '>' => &[&[(1, 5), (3, 3), (1, 1)]],
+        '%' => &[&[(0, 0), (4, 6)], &[(0, 6), (0, 5)], &[(4, 1), (4, 0)]],
+        '#' => &[&[(1, 0), (1, 6)], &[(3, 0), (3, 6)], &[(0, 2), (4, 2)], &[(0, 4), (4, 4)]],
+        _ => &[&[(0, 5), (1, 6), (3, 6), (4, 5), (4, 4), (2, 3), (2, 2)], &[(2, 0), (2, 1)]],
+    }
+}
+
+/// How a run of stroke text should be laid out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+    /// Height of a capital letter in pixels (or world units for 3D text).
+    pub size: f32,
+    /// Radians, clockwise on screen.
+    pub rotation: f32,
+    /// Horizontal shear as a fraction of the height, positive leans right like italics.
+    pub skew: f32,
+    /// Which point of the text block `position` refers to.
+    pub align: Align2,
+}
+
+impl TextStyle {
+    pub fn new(size: f32) -> Self {
+        Self {
+            size,
+            rotation: 0.0,
+            skew: 0.0,
+            align: Align2::LEFT_TOP,
+        }
+    }
+}
+
+/// # measure
+/// Size of the text block in glyph grid units, before any scaling or rotation is applied.
+fn measure(text: &str) -> (f32, f32) {
+    let lines: Vec<&str> = text.lines().collect();
+
+    let longest = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as f32;
+    let width = if longest > 0.0 { (longest - 1.0) * GLYPH_ADVANCE + GLYPH_WIDTH } else { 0.0 };
+    let height = if lines.is_empty() { 0.0 } else { (lines.len() - 1) as f32 * LINE_ADVANCE + GLYPH_HEIGHT };
+
+    (width, height)
+}
+
+/// # text_size
+/// Size in pixels of the unrotated text block for the given style.
+pub fn text_size(text: &str, style: &TextStyle) -> Vec2 {
+    let (width, height) = measure(text);
+    Vec2::new(width, height) * (style.size / GLYPH_HEIGHT)
+}
+
+/// # layout
+/// Lays the text out in 2D and returns each stroke as a line segment in "text space". X runs along
+/// the text and Y runs up the glyphs, with the origin at the point picked by `align` and the size,
+/// skew and alignment already applied. Rotation is left to the caller because it differs between
+/// screen space and world space.
+fn layout(text: &str, style: &TextStyle) -> Vec<[(f32, f32); 2]> {
+    let scale = style.size / GLYPH_HEIGHT;
+    let (width, height) = measure(text);
+
+    // Shift so the alignment point sits on the origin. The top of the first line is at 0.
+    let offset_x = -width * style.align.x().to_factor();
+    let offset_y = height * style.align.y().to_factor();
+
+    let mut segments = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let baseline = -(line_number as f32) * LINE_ADVANCE - GLYPH_HEIGHT;
+
+        for (column, c) in line.chars().enumerate() {
+            let left = column as f32 * GLYPH_ADVANCE;
+
+            for polyline in glyph(c) {
+                for pair in polyline.windows(2) {
+                    let to_text_space = |(x, y): (i8, i8)| {
+                        let x = x as f32 + left + offset_x;
+                        let y = y as f32 + baseline + offset_y;
+                        ((x + y * style.skew) * scale, y * scale)
+                    };
+
+                    segments.push([to_text_space(pair[0]), to_text_space(pair[1])]);
+                }
+            }
+        }
+    }
+
+    segments
+}
+
+/// # text_segments
+/// Turns a string into screen space line segments ready for `RenderBackend::line`. Draw text with
+/// `RenderBackend::text`.
+pub fn text_segments(text: &str, position: Pos2, style: &TextStyle) -> Vec<[Pos2; 2]> {
+    let (sin, cos) = style.rotation.sin_cos();
+
+    // Text space has Y up, the screen has Y down.
+    let to_screen = |(x, y): (f32, f32)| Pos2::new(position.x + x * cos + y * sin, position.y + x * sin - y * cos);
+
+    layout(text, style)
+        .into_iter()
+        .map(|[start, end]| [to_screen(start), to_screen(end)])
+        .collect()
+}
+
+/// Where a run of text sits in the world. The text runs along `right` and the glyphs stand up
+/// along `up`, both of which should be normalised.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldTextPlacement {
+    pub position: Point3D,
+    pub right: Vector3D,
+    pub up: Vector3D,
+}
+
+/// # text_segments_3d
+/// Places text in the world and projects it through the camera. `style.size` is in world units and
+/// `style.rotation` is ignored, rotate the placement's `right` and `up` instead.
+///
+/// Any stroke with an end behind the camera is dropped rather than projected inside out.
+pub fn text_segments_3d(camera: &Camera, text: &str, placement: &WorldTextPlacement, style: &TextStyle) -> Vec<[Pos2; 2]> {
+    let WorldTextPlacement { position, right, up } = placement;
+
+    let to_world = |(x, y): (f32, f32)| Point3D {
+        x: position.x + right.x * x + up.x * y,
+        y: position.y + right.y * x + up.y * y,
+        z: position.z + right.z * x + up.z * y,
+    };
+
+    let in_front = |point: &Point3D| {
+        Vector3D::from(&camera.position).subtract(&Vector3D::from(point)).dot_product(&camera.rotation_vector) > 0.0
+    };
+
+    let segments: Vec<[Point3D; 2]> = layout(text, style)
+        .into_iter()
+        .map(|[start, end]| [to_world(start), to_world(end)])
+        .filter(|[start, end]| in_front(start) && in_front(end))
+        .collect();
+
+    let points_3d: Vec<Point3D> = segments.iter().flat_map(|segment| segment.iter().cloned()).collect();
+
+    camera
+        .to_2d(&points_3d)
+        .chunks(2)
+        .map(|pair| [Pos2::new(pair[0].x, pair[0].y), Pos2::new(pair[1].x, pair[1].y)])
+        .collect()
+}
+
+/// # draw_text_3d
+/// Draws stroke text placed in the world, see `text_segments_3d`.
+pub fn draw_text_3d(
+    backend: &mut dyn RenderBackend,
+    camera: &Camera,
+    text: &str,
+    placement: &WorldTextPlacement,
+    style: &TextStyle,
+    stroke: Stroke,
+) {
+    for segment in text_segments_3d(camera, text, placement, style) {
+        backend.line(segment, stroke);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_pos_approx_eq(actual: Pos2, expected: Pos2) {
+        assert!((actual - expected).length() < 1e-3, "Positions not equal: {:?} {:?}", actual, expected);
+    }
+
+    #[test]
+    /// # test_glyph_l
+    /// 'L' is two strokes, down the left and along the baseline. With the top left at the origin
+    /// and a 12px height the baseline is 12px down the screen.
+    fn test_glyph_l() {
+        let segments = text_segments("L", Pos2::ZERO, &TextStyle::new(12.0));
+
+        assert_eq!(segments.len(), 2);
+        assert_pos_approx_eq(segments[0][0], Pos2::new(0.0, 0.0));
+        assert_pos_approx_eq(segments[0][1], Pos2::new(0.0, 12.0));
+        assert_pos_approx_eq(segments[1][1], Pos2::new(8.0, 12.0));
+    }
+
+    #[test]
+    /// # test_lowercase_matches_uppercase
+    fn test_lowercase_matches_uppercase() {
+        let style = TextStyle::new(10.0);
+
+        assert_eq!(text_segments("vectar", Pos2::ZERO, &style), text_segments("VECTAR", Pos2::ZERO, &style));
+    }
+
+    #[test]
+    /// # test_text_size
+    /// Three glyphs are two advances plus one glyph wide.
+    fn test_text_size() {
+        let size = text_size("ABC", &TextStyle::new(12.0));
+
+        assert!((size.x - 32.0).abs() < 1e-5, "width: {}", size.x);
+        assert!((size.y - 12.0).abs() < 1e-5, "height: {}", size.y);
+    }
+
+    #[test]
+    /// # test_centre_alignment
+    /// Centred text should be symmetrical about the position.
+    fn test_centre_alignment() {
+        let mut style = TextStyle::new(12.0);
+        style.align = Align2::CENTER_CENTER;
+
+        let segments = text_segments("-", Pos2::new(100.0, 100.0), &style);
+
+        assert_pos_approx_eq(segments[0][0], Pos2::new(98.0, 100.0));
+        assert_pos_approx_eq(segments[0][1], Pos2::new(102.0, 100.0));
+    }
+
+    #[test]
+    /// # test_rotation
+    /// Rotating 90 degrees clockwise should make the text run down the screen.
+    fn test_rotation() {
+        let mut style = TextStyle::new(6.0);
+        style.rotation = std::f32::consts::FRAC_PI_2;
+
+        let segments = text_segments("_", Pos2::ZERO, &style);
+
+        // The underscore sits one unit below the baseline, i.e. 7 units from the top.
+        assert_pos_approx_eq(segments[0][0], Pos2::new(-7.0, 0.0));
+        assert_pos_approx_eq(segments[0][1], Pos2::new(-7.0, 4.0));
+    }
+
+    #[test]
+    /// # test_skew
+    /// Skewing leans the top of the glyph over to the right and leaves the baseline alone.
+    fn test_skew() {
+        let mut style = TextStyle::new(6.0);
+        style.skew = 0.5;
+        style.align = Align2::LEFT_BOTTOM;
+
+        let segments = text_segments("L", Pos2::ZERO, &style);
+
+        assert_pos_approx_eq(segments[0][0], Pos2::new(3.0, -6.0));
+        assert_pos_approx_eq(segments[0][1], Pos2::new(0.0, 0.0));
+    }
+
+    #[test]
+    /// # test_text_behind_camera_is_dropped
+    fn test_text_behind_camera_is_dropped() {
+        let camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        let mut placement = WorldTextPlacement {
+            position: Point3D { x: 0.0, y: 0.0, z: 10.0 },
+            right: Vector3D { x: -1.0, y: 0.0, z: 0.0 },
+            up: Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+        };
+        let style = TextStyle::new(1.0);
+
+        let in_front = text_segments_3d(&camera, "HI", &placement, &style);
+
+        placement.position.z = -10.0;
+        let behind = text_segments_3d(&camera, "HI", &placement, &style);
+
+        assert_eq!(in_front.len(), 6);
+        assert!(behind.is_empty());
+    }
+}
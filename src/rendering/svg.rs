@@ -0,0 +1,195 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use eframe::egui::{Color32, Pos2, Stroke};
+use crate::rendering::backend::RenderBackend;
+
+/// # SvgFrame
+/// Records a frame as SVG, one element per line, polygon or circle in the order they were drawn
+/// with the exact screen space coordinates, colours and stroke widths. Handy for documentation,
+/// plotters and as diffable regression fixtures.
+///
+/// Coordinates are written to two decimal places so tiny floating point differences don't show
+/// up as changes.
+pub struct SvgFrame {
+    width: f32,
+    height: f32,
+    background: Color32,
+    elements: String,
+}
+
+impl SvgFrame {
+    pub fn new(width: f32, height: f32, background: Color32) -> Self {
+        Self { width, height, background, elements: String::new() }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+/// `fill` or `stroke` attributes for a colour, including the opacity if it's translucent.
+fn paint(attribute: &str, colour: Color32) -> String {
+    if colour.a() == 0 {
+        return format!(" {}=\"none\"", attribute);
+    }
+
+    let [r, g, b, a] = colour.to_srgba_unmultiplied();
+    let mut paint = format!(" {}=\"#{:02x}{:02x}{:02x}\"", attribute, r, g, b);
+
+    if a < 255 {
+        let _ = write!(paint, " {}-opacity=\"{:.3}\"", attribute, a as f32 / 255.0);
+    }
+
+    paint
+}
+
+fn stroke_attributes(stroke: Stroke) -> String {
+    if stroke.is_empty() {
+        return " stroke=\"none\"".to_string();
+    }
+
+    format!("{} stroke-width=\"{:.2}\"", paint("stroke", stroke.color), stroke.width)
+}
+
+impl RenderBackend for SvgFrame {
+    fn line(&mut self, [start, end]: [Pos2; 2], stroke: Stroke) {
+        if stroke.is_empty() || !start.is_finite() || !end.is_finite() {
+            return;
+        }
+
+        let _ = writeln!(
+            self.elements,
+            "<line x1=\"{:.2}\" y1=\"{:.2}\" x2=\"{:.2}\" y2=\"{:.2}\"{}/>",
+            start.x, start.y, end.x, end.y, stroke_attributes(stroke),
+        );
+    }
+
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        if points.len() < 2 || points.iter().any(|point| !point.is_finite()) {
+            return;
+        }
+
+        let points: Vec<String> = points.iter().map(|point| format!("{:.2},{:.2}", point.x, point.y)).collect();
+
+        let _ = writeln!(
+            self.elements,
+            "<polygon points=\"{}\"{}{}/>",
+            points.join(" "), paint("fill", fill), stroke_attributes(stroke),
+        );
+    }
+
+    fn circle(&mut self, centre: Pos2, radius: f32, fill: Color32, stroke: Stroke) {
+        if !centre.is_finite() || !radius.is_finite() {
+            return;
+        }
+
+        let _ = writeln!(
+            self.elements,
+            "<circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"{:.2}\"{}{}/>",
+            centre.x, centre.y, radius, paint("fill", fill), stroke_attributes(stroke),
+        );
+    }
+}
+
+impl std::fmt::Display for SvgFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\" stroke-linecap=\"round\" stroke-linejoin=\"round\">",
+            w = self.width, h = self.height,
+        )?;
+        writeln!(f, "<rect width=\"100%\" height=\"100%\"{}/>", paint("fill", self.background))?;
+        f.write_str(&self.elements)?;
+        writeln!(f, "</svg>")
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::mesh::Mesh;
+    use crate::player::camera::Camera;
+    use crate::rendering::wireframe::draw_wireframe;
+    use crate::screens::level1_screen::{load_model_faces, load_model_verts};
+    use crate::types::geometry::*;
+
+    #[test]
+    /// # test_line_element
+    fn test_line_element() {
+        let mut frame = SvgFrame::new(100.0, 50.0, Color32::BLACK);
+        frame.line([Pos2::new(1.0, 2.0), Pos2::new(3.5, 4.25)], Stroke::new(2.0, Color32::GREEN));
+
+        assert!(frame.to_string().contains(
+            "<line x1=\"1.00\" y1=\"2.00\" x2=\"3.50\" y2=\"4.25\" stroke=\"#00ff00\" stroke-width=\"2.00\"/>\n"
+        ));
+    }
+
+    #[test]
+    /// # test_translucent_colours
+    /// egui colours are premultiplied, SVG wants them straight with a separate opacity.
+    fn test_translucent_colours() {
+        let mut frame = SvgFrame::new(100.0, 50.0, Color32::BLACK);
+        frame.circle(Pos2::new(5.0, 5.0), 3.0, Color32::from_rgba_unmultiplied(255, 0, 0, 51), Stroke::NONE);
+
+        assert!(frame.to_string().contains(
+            "<circle cx=\"5.00\" cy=\"5.00\" r=\"3.00\" fill=\"#ff0000\" fill-opacity=\"0.200\" stroke=\"none\"/>\n"
+        ));
+    }
+
+    #[test]
+    /// # test_polygon_element
+    fn test_polygon_element() {
+        let mut frame = SvgFrame::new(100.0, 50.0, Color32::BLACK);
+        frame.polygon(&[Pos2::new(0.0, 0.0), Pos2::new(10.0, 0.0), Pos2::new(5.0, 8.0)], Color32::TRANSPARENT, Stroke::new(1.0, Color32::WHITE));
+
+        assert!(frame.to_string().contains(
+            "<polygon points=\"0.00,0.00 10.00,0.00 5.00,8.00\" fill=\"none\" stroke=\"#ffffff\" stroke-width=\"1.00\"/>\n"
+        ));
+    }
+
+    #[test]
+    /// # test_unprojectable_points_are_skipped
+    fn test_unprojectable_points_are_skipped() {
+        let mut frame = SvgFrame::new(100.0, 50.0, Color32::BLACK);
+        frame.line([Pos2::new(f32::INFINITY, 0.0), Pos2::new(1.0, 1.0)], Stroke::new(1.0, Color32::WHITE));
+        frame.line([Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)], Stroke::NONE);
+
+        assert_eq!(frame.elements, "");
+    }
+
+    #[test]
+    /// # test_cube_svg_snapshot
+    /// The cube's projected edges, one `<line>` per face edge. Run the tests with `UPDATE_GOLDEN=1`
+    /// to rewrite `tests/golden/cube_front.svg` after an intended change.
+    fn test_cube_svg_snapshot() {
+        let model_name = "cube.obj".to_string();
+        let cube = Mesh {
+            verts: load_model_verts(&model_name),
+            faces: load_model_faces(&model_name),
+            position: Vector3D { x: 0.0, y: -1.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        };
+
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: -5.0 }, 1000.0);
+        camera.update_viewport_size(Rectangle { width: 160.0, height: 120.0 });
+
+        let mut frame = SvgFrame::new(160.0, 120.0, Color32::BLACK);
+        draw_wireframe(&mut frame, &camera, &cube, Stroke::new(1.0, Color32::GREEN));
+        let svg = frame.to_string();
+
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/cube_front.svg");
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(&path, &svg).unwrap();
+        }
+
+        assert_eq!(svg.matches("<line ").count(), 24);
+        assert_eq!(svg, fs::read_to_string(&path).unwrap());
+    }
+}
@@ -0,0 +1,320 @@
+use eframe::egui::{Align2, Color32, Pos2, Rect, Stroke, Vec2};
+use crate::console::commands::{Command, CommandHandler};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
+use crate::types::geometry::{Point3D, Rectangle, Vector3D};
+
+/// How far the quad layout's top, front and side cameras are from what they look at.
+const EDITOR_DISTANCE: f32 = 40.0;
+
+/// # ViewportFrame
+/// Draws into a rectangle of another backend. Everything given to it is relative to the
+/// rectangle's top left, and anything outside it is clipped off, so a camera with a viewport the
+/// size of the rectangle can draw as if it had the whole window.
+pub struct ViewportFrame<'a> {
+    output: &'a mut dyn RenderBackend,
+    rect: Rect,
+}
+
+impl<'a> ViewportFrame<'a> {
+    pub fn new(output: &'a mut dyn RenderBackend, rect: Rect) -> Self {
+        Self { output, rect }
+    }
+
+    /// The part of a line in the viewport, in the viewport's own coordinates, using Liang-Barsky.
+    fn clip_line(&self, [start, end]: [Pos2; 2]) -> Option<[Pos2; 2]> {
+        let size = self.rect.size();
+        let delta = end - start;
+        let (mut from, mut to) = (0.0_f32, 1.0_f32);
+
+        // Each edge as how far along the line moves towards it, and how far inside it the start is
+        for (towards, inside) in [(-delta.x, start.x), (delta.x, size.x - start.x), (-delta.y, start.y), (delta.y, size.y - start.y)] {
+            if towards == 0.0 {
+                if inside < 0.0 {
+                    return None;
+                }
+                continue;
+            }
+
+            let t = inside / towards;
+            if towards < 0.0 {
+                from = from.max(t);
+            } else {
+                to = to.min(t);
+            }
+        }
+
+        (from <= to).then(|| [start + delta * from, start + delta * to])
+    }
+
+    /// The part of a convex polygon in the viewport, using Sutherland-Hodgman.
+    fn clip_polygon(&self, points: &[Pos2]) -> Vec<Pos2> {
+        let size = self.rect.size();
+        // How far inside each edge a point is, negative if it's outside
+        let edges: [&dyn Fn(Pos2) -> f32; 4] = [&|point| point.x, &|point| size.x - point.x, &|point| point.y, &|point| size.y - point.y];
+
+        edges.iter().fold(points.to_vec(), |points, inside| {
+            let mut clipped = Vec::new();
+
+            for (index, point) in points.iter().enumerate() {
+                let next = points[(index + 1) % points.len()];
+                let (here, there) = (inside(*point), inside(next));
+
+                if here >= 0.0 {
+                    clipped.push(*point);
+                }
+
+                if (here >= 0.0) != (there >= 0.0) {
+                    clipped.push(*point + (next - *point) * (here / (here - there)));
+                }
+            }
+
+            clipped
+        })
+    }
+}
+
+impl RenderBackend for ViewportFrame<'_> {
+    fn line(&mut self, points: [Pos2; 2], stroke: Stroke) {
+        if let Some([start, end]) = self.clip_line(points) {
+            let offset = self.rect.min.to_vec2();
+
+            self.output.line([start + offset, end + offset], stroke);
+        }
+    }
+
+    /// The fill is clipped as a polygon, the outline as lines so it doesn't run along the edge.
+    fn polygon(&mut self, points: &[Pos2], fill: Color32, stroke: Stroke) {
+        let clipped = self.clip_polygon(points);
+
+        if clipped.len() >= 3 && fill != Color32::TRANSPARENT {
+            let offset = self.rect.min.to_vec2();
+            let clipped: Vec<Pos2> = clipped.iter().map(|point| *point + offset).collect();
+
+            self.output.polygon(&clipped, fill, Stroke::NONE);
+        }
+
+        if stroke.is_empty() {
+            return;
+        }
+
+        for (index, point) in points.iter().enumerate() {
+            self.line([*point, points[(index + 1) % points.len()]], stroke);
+        }
+    }
+}
+
+/// # Layout
+/// How the window is shared between cameras.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Layout {
+    #[default]
+    Single,
+    /// The player's camera on the left and looking down on them on the right.
+    Split,
+    /// The player's camera with a rear view mirror at the top.
+    PictureInPicture,
+    /// The player's camera and views from the top, front and side, as in an editor.
+    Quad,
+}
+
+impl Layout {
+    pub const ALL: [Layout; 4] = [Layout::Single, Layout::Split, Layout::PictureInPicture, Layout::Quad];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Layout::Single => "single",
+            Layout::Split => "split",
+            Layout::PictureInPicture => "pip",
+            Layout::Quad => "quad",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Layout> {
+        Layout::ALL.into_iter().find(|layout| layout.name() == name)
+    }
+
+    /// # views
+    /// The layout's views of the window, the player's camera first. The others are worked out
+    /// from it each frame. Later views are drawn over earlier ones.
+    pub fn views(&self, camera: &Camera, window: &Rectangle) -> Vec<View> {
+        let (width, height) = (window.width, window.height);
+        let rect = |x: f32, y: f32, w: f32, h: f32| Rect::from_min_size(Pos2::new(x, y), Vec2::new(w, h));
+        let position = Vector3D::from(&camera.position);
+        let looking = camera.rotation_vector.normalise();
+        let from = |offset: Vector3D, target: Vector3D| {
+            let mut view = camera.clone();
+            view.position = point(&(position.clone() + offset));
+            view.look_at(&point(&target));
+            view
+        };
+
+        match self {
+            Layout::Single => vec![View::new(camera.clone(), rect(0.0, 0.0, width, height), None)],
+            Layout::Split => vec![
+                View::new(camera.clone(), rect(0.0, 0.0, width / 2.0, height), None),
+                // Looking down from a little behind, as straight down has no up
+                View::new(from(Vector3D { x: -looking.x, y: EDITOR_DISTANCE, z: -looking.z }, position.clone()), rect(width / 2.0, 0.0, width / 2.0, height), Some("MAP")),
+            ],
+            Layout::PictureInPicture => vec![
+                View::new(camera.clone(), rect(0.0, 0.0, width, height), None),
+                View::new(from(Vector3D { x: 0.0, y: 0.0, z: 0.0 }, position.clone() + Vector3D { x: -looking.x, y: 0.0, z: -looking.z }), rect(width * 0.375, 10.0, width / 4.0, height / 5.0), Some("REAR")),
+            ],
+            Layout::Quad => vec![
+                View::new(camera.clone(), rect(0.0, 0.0, width / 2.0, height / 2.0), None),
+                View::new(from(Vector3D { x: 0.0, y: EDITOR_DISTANCE, z: -1.0 }, position.clone()), rect(width / 2.0, 0.0, width / 2.0, height / 2.0), Some("TOP")),
+                View::new(from(Vector3D { x: 0.0, y: 0.0, z: -EDITOR_DISTANCE }, position.clone()), rect(0.0, height / 2.0, width / 2.0, height / 2.0), Some("FRONT")),
+                View::new(from(Vector3D { x: EDITOR_DISTANCE, y: 0.0, z: 0.0 }, position.clone()), rect(width / 2.0, height / 2.0, width / 2.0, height / 2.0), Some("SIDE")),
+            ],
+        }
+    }
+}
+
+/// # View
+/// A camera drawing into part of the window. Views with a `label` show that rather than the
+/// game's HUD, which is only for the player's own view.
+pub struct View {
+    pub camera: Camera,
+    pub rect: Rect,
+    pub label: Option<&'static str>,
+}
+
+impl View {
+    /// A view of `camera` in `rect`, the camera's viewport made the size of it.
+    pub fn new(mut camera: Camera, rect: Rect, label: Option<&'static str>) -> Self {
+        camera.update_viewport_size(Rectangle { width: rect.width(), height: rect.height() });
+
+        Self { camera, rect, label }
+    }
+
+    /// # side_by_side
+    /// A view for each camera, splitting `window` into equal columns, the first on the left.
+    pub fn side_by_side(cameras: Vec<Camera>, window: &Rectangle) -> Vec<View> {
+        let width = window.width / cameras.len().max(1) as f32;
+
+        cameras
+            .into_iter()
+            .enumerate()
+            .map(|(index, camera)| View::new(camera, Rect::from_min_size(Pos2::new(index as f32 * width, 0.0), Vec2::new(width, window.height)), None))
+            .collect()
+    }
+
+    /// Fills the view with the clear colour, so nothing an earlier view drew under it shows
+    /// through.
+    pub fn clear(&self, backend: &mut dyn RenderBackend) {
+        let (width, height) = (self.camera.viewport.width, self.camera.viewport.height);
+        let corners = [Pos2::new(0.0, 0.0), Pos2::new(width, 0.0), Pos2::new(width, height), Pos2::new(0.0, height)];
+        backend.polygon(&corners, Color32::BLACK, Stroke::NONE);
+    }
+
+    /// Draws the view's label and a border round it, for views that aren't the player's own.
+    pub fn render_label(&self, backend: &mut dyn RenderBackend) {
+        let Some(label) = self.label else {
+            return;
+        };
+
+        let (width, height) = (self.camera.viewport.width, self.camera.viewport.height);
+        let corners = [Pos2::new(0.0, 0.0), Pos2::new(width, 0.0), Pos2::new(width, height), Pos2::new(0.0, height)];
+        backend.polygon(&corners, Color32::TRANSPARENT, Stroke::new(2.0, Color32::DARK_GREEN));
+
+        let mut style = TextStyle::new(12.0);
+        style.align = Align2::LEFT_TOP;
+        backend.text(label, Pos2::new(8.0, 8.0), &style, Stroke::new(1.0, Color32::GREEN));
+    }
+}
+
+/// # Views
+/// The layout the game draws with, and the console command to change it.
+#[derive(Debug, Default)]
+pub struct Views {
+    pub layout: Layout,
+}
+
+impl CommandHandler for Views {
+    fn commands(&self) -> Vec<Command> {
+        vec![Command { name: "layout", usage: "[single|split|pip|quad]", help: "Shows or sets how the window is split between cameras" }]
+    }
+
+    fn complete(&self, command: &str, index: usize) -> Vec<String> {
+        match (command, index) {
+            ("layout", 0) => Layout::ALL.iter().map(|layout| layout.name().to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        match command {
+            "layout" => {
+                if let Some(name) = args.first() {
+                    self.layout = Layout::from_name(name).ok_or(format!("No layout {}", name))?;
+                }
+
+                Ok(format!("Layout {}", self.layout.name()))
+            }
+            _ => Err(format!("Unknown command {}", command)),
+        }
+    }
+}
+
+fn point(vector: &Vector3D) -> Point3D {
+    Point3D { x: vector.x, y: vector.y, z: vector.z }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::recorder::Recorder;
+
+    #[test]
+    /// # test_viewport_offsets_and_clips
+    /// Lines are moved into the viewport's rectangle and cut at its edges, and ones outside it
+    /// aren't drawn.
+    fn test_viewport_offsets_and_clips() {
+        let mut output = Recorder::default();
+        let mut frame = ViewportFrame::new(&mut output, Rect::from_min_size(Pos2::new(100.0, 50.0), Vec2::new(200.0, 100.0)));
+
+        frame.line([Pos2::new(10.0, 10.0), Pos2::new(20.0, 20.0)], Stroke::new(1.0, Color32::GREEN));
+        frame.line([Pos2::new(-100.0, 50.0), Pos2::new(300.0, 50.0)], Stroke::new(1.0, Color32::GREEN));
+        frame.line([Pos2::new(-10.0, -10.0), Pos2::new(-20.0, 500.0)], Stroke::new(1.0, Color32::GREEN));
+        frame.polygon(&[Pos2::new(150.0, 50.0), Pos2::new(250.0, 50.0), Pos2::new(250.0, 90.0)], Color32::GREEN, Stroke::new(1.0, Color32::GREEN));
+
+        let lines: Vec<[Pos2; 2]> = output.lines.iter().map(|(points, _)| *points).collect();
+        assert_eq!(lines[..2], [
+            [Pos2::new(110.0, 60.0), Pos2::new(120.0, 70.0)],
+            [Pos2::new(100.0, 100.0), Pos2::new(300.0, 100.0)],
+        ]);
+        // The polygon's outline is drawn as lines clipped the same way, one side is all outside
+        assert_eq!(output.lines.len(), 4);
+
+        let (polygon, _, _) = &output.polygons[0];
+        assert!(polygon.iter().all(|point| point.x <= 300.0), "{:?}", polygon);
+        assert!(polygon.contains(&Pos2::new(250.0, 100.0)));
+    }
+
+    #[test]
+    /// # test_layout_views
+    /// Every layout's views fit in the window with cameras the size of their rectangles, and
+    /// only the player's own view gets the HUD.
+    fn test_layout_views() {
+        let window = Rectangle { width: 800.0, height: 600.0 };
+        let camera = Camera::for_tests(Point3D { x: 0.0, y: 1.0, z: 0.0 }, 1000.0);
+        let screen = Rect::from_min_size(Pos2::ZERO, Vec2::new(window.width, window.height));
+
+        for layout in Layout::ALL {
+            let views = layout.views(&camera, &window);
+
+            assert!(views[0].label.is_none());
+            assert!(views[1..].iter().all(|view| view.label.is_some()));
+
+            for view in &views {
+                assert!(screen.contains_rect(view.rect), "{:?} {:?}", layout, view.rect);
+                assert_eq!((view.camera.viewport.width, view.camera.viewport.height), (view.rect.width(), view.rect.height()));
+                assert!(view.camera.depth(&camera.position) >= 0.0 || view.label == Some("REAR"), "{:?} can see the player", view.label);
+            }
+        }
+
+        assert_eq!(Layout::Quad.views(&camera, &window).len(), 4);
+    }
+}
@@ -0,0 +1,145 @@
+use eframe::egui::{Color32, Pos2, Stroke};
+use crate::objects::mesh::Mesh;
+use crate::player::camera::{Camera, NEAR_CLIP};
+use crate::rendering::backend::RenderBackend;
+use crate::types::geometry::{Point3D, ProjectedPoint};
+
+/// # RenderMode
+/// How meshes are drawn. Wireframes show every edge, filled faces cover the ones behind them and
+/// hidden line is the classic vector look, black faces so only the nearest edges show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Wireframe,
+    Filled,
+    HiddenLine,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 3] = [RenderMode::Wireframe, RenderMode::Filled, RenderMode::HiddenLine];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Wireframe => "wireframe",
+            RenderMode::Filled => "filled",
+            RenderMode::HiddenLine => "hidden-line",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<RenderMode> {
+        RenderMode::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// A face projected onto the screen, `depth` being the average of its corners'.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreenFace {
+    pub points: Vec<Pos2>,
+    pub depth: f32,
+}
+
+impl ScreenFace {
+    /// The face with these projected corners, or `None` if any of it is behind the near clip as it
+    /// can't be filled properly.
+    pub fn new(corners: &[&ProjectedPoint]) -> Option<Self> {
+        if corners.is_empty() || corners.iter().any(|corner| corner.depth < NEAR_CLIP) {
+            return None;
+        }
+
+        Some(Self {
+            points: corners.iter().map(|corner| Pos2::new(corner.x, corner.y)).collect(),
+            depth: corners.iter().map(|corner| corner.depth).sum::<f32>() / corners.len() as f32,
+        })
+    }
+}
+
+/// # draw_faces
+/// Draws faces for the `Filled` and `HiddenLine` modes, furthest first so the nearer ones cover
+/// them (the painter's algorithm). `stroke` is given each face's depth, e.g. for depth cueing.
+pub fn draw_faces(backend: &mut dyn RenderBackend, mut faces: Vec<ScreenFace>, mode: RenderMode, stroke: impl Fn(f32) -> Stroke) {
+    faces.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+
+    for face in faces {
+        let stroke = stroke(face.depth);
+        let fill = match mode {
+            RenderMode::Filled => stroke.color.gamma_multiply(0.35),
+            _ => Color32::BLACK,
+        };
+
+        backend.polygon(&face.points, fill, stroke);
+    }
+}
+
+/// # draw_wireframe
+/// Projects the mesh through the camera and draws the edges of every face.
+pub fn draw_wireframe(backend: &mut dyn RenderBackend, camera: &Camera, mesh: &Mesh, stroke: Stroke) {
+    let points_2d = camera.to_2d(&mesh.get_transformed_verts());
+
+    for face in &mesh.faces {
+        let num_verts = face.vert_indices.len();
+
+        for i in 0..num_verts {
+            // OBJ indices start at 1
+            let start = &points_2d[face.vert_indices[i] - 1];
+            let end = &points_2d[face.vert_indices[(i + 1) % num_verts] - 1];
+
+            backend.line([Pos2::new(start.x, start.y), Pos2::new(end.x, end.y)], stroke);
+        }
+    }
+}
+
+/// # draw_line_3d
+/// Draws a world space line if any of it is in front of the camera and not past the far plane.
+/// `stroke` is given the camera space depth of each end, e.g. for depth cueing.
+pub fn draw_line_3d(backend: &mut dyn RenderBackend, camera: &Camera, start: &Point3D, end: &Point3D, stroke: impl Fn(f32, f32) -> Stroke) {
+    let Some([start, end]) = camera.project_line(start, end) else {
+        return;
+    };
+
+    if start.depth > camera.far_plane_distance() && end.depth > camera.far_plane_distance() {
+        return;
+    }
+
+    backend.line([Pos2::new(start.x, start.y), Pos2::new(end.x, end.y)], stroke(start.depth, end.depth));
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::recorder::Recorder;
+
+    fn corner(x: f32, depth: f32) -> ProjectedPoint {
+        ProjectedPoint { x, y: 0.0, depth }
+    }
+
+    #[test]
+    /// # test_faces_drawn_furthest_first
+    /// Nearer faces are drawn over further ones, and faces reaching behind the camera are left out.
+    fn test_faces_drawn_furthest_first() {
+        let near = [corner(0.0, 2.0), corner(1.0, 2.0), corner(2.0, 2.0)];
+        let far = [corner(5.0, 20.0), corner(6.0, 20.0), corner(7.0, 20.0)];
+        let behind = [corner(8.0, 2.0), corner(9.0, -1.0), corner(10.0, 2.0)];
+
+        let faces: Vec<ScreenFace> = [&near, &far, &behind]
+            .into_iter()
+            .filter_map(|corners| ScreenFace::new(&corners.iter().collect::<Vec<_>>()))
+            .collect();
+        assert_eq!(faces.len(), 2);
+
+        let mut output = Recorder::default();
+        draw_faces(&mut output, faces, RenderMode::HiddenLine, |_| Stroke::new(1.0, Color32::GREEN));
+
+        let drawn: Vec<f32> = output.polygons.iter().map(|(points, _, _)| points[0].x).collect();
+        assert_eq!(drawn, vec![5.0, 0.0]);
+        assert!(output.polygons.iter().all(|(_, fill, _)| *fill == Color32::BLACK));
+    }
+
+    #[test]
+    /// # test_render_mode_names
+    fn test_render_mode_names() {
+        for mode in RenderMode::ALL {
+            assert_eq!(RenderMode::from_name(mode.name()), Some(mode));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use crate::types::geometry::Vector3D;
+
+// Events the levels publish on their message bus.
+
+/// An entity was blown up and removed from the level.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDestroyed {
+    pub id: String,
+    pub object_type: String,
+    pub position: Vector3D,
+}
+
+/// Something to tell the player, shown on the HUD for a few seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HudMessage {
+    pub text: String,
+}
+
+/// Every enemy in the level has been destroyed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelComplete;
+
+/// One of the level's triggers has gone off. Its actions' events follow straight after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerFired {
+    pub id: String,
+}
+
+/// A trigger wants one of the level's waves brought in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnWave {
+    pub wave: String,
+}
+
+/// Something wants an entity brought in, on the ground. `model` is an OBJ under `assets/`, without
+/// one it's a copy of an entity of the same type from the level or its waves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnEntity {
+    pub object_type: String,
+    pub model: Option<String>,
+    pub x: f32,
+    pub z: f32,
+}
+
+/// Something a script printed, or went wrong with one, for the console.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptOutput {
+    pub text: String,
+    pub is_error: bool,
+}
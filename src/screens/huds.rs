@@ -1,8 +1,19 @@
-use eframe::egui;
-use eframe::egui::{Align2, Color32, FontId, Painter, Pos2};
+use std::cell::RefCell;
+use std::f32::consts::{PI, TAU};
+use std::rc::Rc;
+use eframe::egui::{Align2, Color32, Pos2, Rect, Stroke, Vec2};
+use crate::objects::entity::Entity;
 use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
 //TODO: Hud stuff...
+use crate::screens::events::{EntityDestroyed, HudMessage};
 use crate::screens::traits::{HudRenderer};
+use crate::types::geometry::Vector3D;
+use crate::utils::message_bus::{MessageBus, Subscription};
+
+/// Seconds a message stays on the HUD.
+const MESSAGE_TIME: f32 = 3.0;
 
 pub struct TitleHud;
 
@@ -14,52 +25,463 @@ impl TitleHud {
 
 /* The intent here is to call the render function to get the hud to draw itself on the screen */
 impl HudRenderer for TitleHud {
-    fn render(&self, camera: &Camera, painter: &Painter) {
-        // Draw text at a specific position
-        painter.text(
-            Pos2::new(10.0, 10.0),
-            Align2::LEFT_TOP,
-            format!("Pos: {:?}", &camera.position),
-            FontId::new(16.0, egui::FontFamily::Proportional),
-            Color32::WHITE,
-        );
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let mut style = TextStyle::new(12.0);
+        style.align = Align2::CENTER_BOTTOM;
 
-        painter.text(
-            Pos2::new(10.0, 30.0),
-            Align2::LEFT_TOP,
-            format!("Pitch:{} Roll:{} Yaw:{}", &camera.rotation.pitch.to_degrees(), &camera.rotation.roll.to_degrees(), &camera.rotation.yaw.to_degrees()),
-            FontId::new(16.0, egui::FontFamily::Proportional),
-            Color32::WHITE,
-        );
-
-        painter.text(
-            Pos2::new(10.0, 50.0),
-            Align2::LEFT_TOP,
-            format!("Rot x:{} y:{} z:{}", &camera.rotation_vector.x, &camera.rotation_vector.y, &camera.rotation_vector.z),
-            FontId::new(16.0, egui::FontFamily::Proportional),
-            Color32::WHITE,
+        backend.text(
+            "UP/DOWN SELECT    ENTER CHOOSE    ESC BACK",
+            Pos2::new(camera.viewport.width / 2.0, camera.viewport.height - 16.0),
+            &style,
+            Stroke::new(1.0, Color32::DARK_GREEN),
         );
     }
 }
 
 
-pub struct GameHud;
+pub struct GameHud {
+    pub radar: Radar,
+    /// Messages from the level's bus, with the seconds each has left on screen.
+    messages: Rc<RefCell<Vec<(String, f32)>>>,
+    subscriptions: Vec<Subscription>,
+}
 
 impl GameHud {
     pub fn new() -> Self{
-        Self
+        Self {
+            radar: Radar::new(50.0, 80.0, Align2::RIGHT_TOP),
+            messages: Rc::new(RefCell::new(Vec::new())),
+            subscriptions: Vec::new(),
+        }
     }
 }
 
 impl HudRenderer for GameHud {
-    fn render(&self, camera: &Camera, painter: &Painter) {
+    fn update(&mut self, camera: &Camera, entities: &[Entity], delta_time: f32) {
+        self.radar.update(camera, entities, delta_time);
+
+        let mut messages = self.messages.borrow_mut();
+        for (_, time_left) in messages.iter_mut() {
+            *time_left -= delta_time;
+        }
+        messages.retain(|(_, time_left)| *time_left > 0.0);
+    }
+
+    fn connect(&mut self, bus: &mut MessageBus) {
+        let messages = self.messages.clone();
+        self.subscriptions.push(bus.subscribe(move |message: &HudMessage| {
+            messages.borrow_mut().push((message.text.clone(), MESSAGE_TIME));
+        }));
+
+        let messages = self.messages.clone();
+        self.subscriptions.push(bus.subscribe(move |destroyed: &EntityDestroyed| {
+            let name = destroyed.object_type.replace('_', " ").to_uppercase();
+            messages.borrow_mut().push((format!("{} DESTROYED", name), MESSAGE_TIME));
+        }));
+    }
+
+    fn disconnect(&mut self, bus: &mut MessageBus) {
+        for subscription in self.subscriptions.drain(..) {
+            bus.unsubscribe(subscription);
+        }
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
         // Draw text at a specific position
-        painter.text(
+        backend.text(
+            &format!("POS {:.1} {:.1} {:.1}", camera.position.x, camera.position.y, camera.position.z),
             Pos2::new(10.0, 10.0),
-            Align2::LEFT_TOP,
-            format!("Pos: {:?}", &camera.position),
-            FontId::new(24.0, egui::FontFamily::Proportional),
-            Color32::WHITE,
+            &TextStyle::new(16.0),
+            Stroke::new(1.5, Color32::GREEN),
         );
+
+        self.radar.render(camera, backend);
+
+        let mut style = TextStyle::new(16.0);
+        style.align = Align2::CENTER_TOP;
+
+        for (index, (text, _)) in self.messages.borrow().iter().enumerate() {
+            let position = Pos2::new(camera.viewport.width / 2.0, 50.0 + index as f32 * 24.0);
+            backend.text(text, position, &style, Stroke::new(1.5, Color32::GREEN));
+        }
+    }
+}
+
+/// # VersusHud
+/// What's for both players in versus, over the whole window: messages about the rounds from the
+/// screen's bus. Each player's own HUD is drawn by the screen in their half.
+pub struct VersusHud {
+    /// Messages with the seconds each has left on screen.
+    messages: Rc<RefCell<Vec<(String, f32)>>>,
+    subscriptions: Vec<Subscription>,
+}
+
+impl VersusHud {
+    pub fn new() -> Self {
+        Self { messages: Rc::new(RefCell::new(Vec::new())), subscriptions: Vec::new() }
+    }
+}
+
+impl HudRenderer for VersusHud {
+    fn update(&mut self, _camera: &Camera, _entities: &[Entity], delta_time: f32) {
+        let mut messages = self.messages.borrow_mut();
+        for (_, time_left) in messages.iter_mut() {
+            *time_left -= delta_time;
+        }
+        messages.retain(|(_, time_left)| *time_left > 0.0);
+    }
+
+    fn connect(&mut self, bus: &mut MessageBus) {
+        let messages = self.messages.clone();
+        self.subscriptions.push(bus.subscribe(move |message: &HudMessage| {
+            messages.borrow_mut().push((message.text.clone(), MESSAGE_TIME));
+        }));
+    }
+
+    fn disconnect(&mut self, bus: &mut MessageBus) {
+        for subscription in self.subscriptions.drain(..) {
+            bus.unsubscribe(subscription);
+        }
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let mut style = TextStyle::new(24.0);
+        style.align = Align2::CENTER_TOP;
+
+        // Across the line between the players' views, so both can read them
+        for (index, (text, _)) in self.messages.borrow().iter().enumerate() {
+            let position = Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * 0.25 + index as f32 * 36.0);
+            backend.text(text, position, &style, Stroke::new(2.0, Color32::GREEN));
+        }
+    }
+}
+
+/// # PlayerHud
+/// One player's own HUD in versus, in their half of the window: who they are, how much armour
+/// their tank has left, whether the gun's loaded and a radar showing where the other player is.
+pub struct PlayerHud {
+    pub name: String,
+    pub radar: Radar,
+    /// Hits the tank can take before it's destroyed, out of `max_armour`.
+    pub armour: u32,
+    pub max_armour: u32,
+    pub loaded: bool,
+}
+
+impl PlayerHud {
+    pub fn new(name: &str, max_armour: u32) -> Self {
+        let mut radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+        radar.tracked_object_type = "player".to_string();
+
+        Self { name: name.to_string(), radar, armour: max_armour, max_armour, loaded: true }
+    }
+}
+
+impl HudRenderer for PlayerHud {
+    /// `entities` are what the radar shows, which should be the other player's tank.
+    fn update(&mut self, camera: &Camera, entities: &[Entity], delta_time: f32) {
+        self.radar.update(camera, entities, delta_time);
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        backend.text(&self.name, Pos2::new(10.0, 10.0), &TextStyle::new(16.0), Stroke::new(1.5, Color32::GREEN));
+
+        // A box for each hit the tank can take, empty once it's been taken
+        for index in 0..self.max_armour {
+            let corner = Pos2::new(10.0 + index as f32 * 22.0, 36.0);
+            let fill = if index < self.armour { Color32::GREEN } else { Color32::TRANSPARENT };
+            backend.polygon(
+                &[corner, corner + Vec2::new(16.0, 0.0), corner + Vec2::new(16.0, 10.0), corner + Vec2::new(0.0, 10.0)],
+                fill,
+                Stroke::new(1.0, Color32::GREEN),
+            );
+        }
+
+        let mut style = TextStyle::new(12.0);
+        style.align = Align2::LEFT_BOTTOM;
+        let (text, colour) = if self.loaded { ("LOADED", Color32::GREEN) } else { ("LOADING", Color32::DARK_GREEN) };
+        backend.text(text, Pos2::new(10.0, camera.viewport.height - 16.0), &style, Stroke::new(1.0, colour));
+
+        self.radar.render(camera, backend);
+    }
+}
+
+/// # Radar
+/// A Battlezone style radar scope. Entities of the tracked type are plotted relative to the camera
+/// position and heading, with the camera's forward direction always pointing up the scope.
+///
+/// Blips are only refreshed when the sweep line passes over them and then fade away, so a moving
+/// target leaves its old blip behind until the sweep comes round again.
+pub struct Radar {
+    /// World units from the camera to the edge of the scope.
+    pub range: f32,
+    /// Radius of the scope on screen in pixels.
+    pub radius: f32,
+    /// Where on the viewport the scope sits.
+    pub anchor: Align2,
+    /// Gap in pixels between the scope and the edge of the viewport.
+    pub margin: f32,
+    /// The entity `object_type` that shows up on the scope.
+    pub tracked_object_type: String,
+    /// Radians per second the sweep line turns.
+    pub sweep_speed: f32,
+    /// Seconds a blip takes to fade out after the sweep has passed over it.
+    pub blip_fade_time: f32,
+    pub sweep_angle: f32,
+    pub blips: Vec<Blip>,
+}
+
+/// A contact on the radar. `offset` is relative to the centre of the scope in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blip {
+    pub offset: Vec2,
+    pub intensity: f32,
+}
+
+impl Radar {
+    pub fn new(range: f32, radius: f32, anchor: Align2) -> Self {
+        Self {
+            range,
+            radius,
+            anchor,
+            margin: 10.0,
+            tracked_object_type: "enemy_tank".to_string(),
+            sweep_speed: PI,
+            blip_fade_time: 2.0,
+            sweep_angle: 0.0,
+            blips: Vec::new(),
+        }
+    }
+
+    /// # bearing_and_distance
+    /// Returns the bearing (radians, 0 is straight ahead, positive is clockwise to the right) and
+    /// the ground distance from the camera to the given world position.
+    ///
+    /// Height is ignored, the radar is a top down view.
+    pub fn bearing_and_distance(camera: &Camera, position: &Vector3D) -> (f32, f32) {
+        let (right, forward) = Self::local_axes(camera);
+
+        let dx = position.x - camera.position.x;
+        let dz = position.z - camera.position.z;
+
+        let local_x = dx * right.0 + dz * right.1;
+        let local_z = dx * forward.0 + dz * forward.1;
+
+        (f32::atan2(local_x, local_z), (local_x * local_x + local_z * local_z).sqrt())
+    }
+
+    /// # blip_offset
+    /// Pass in a world position and get back where it sits on the scope relative to the scope
+    /// centre in pixels, or `None` if it's out of range.
+    pub fn blip_offset(&self, camera: &Camera, position: &Vector3D) -> Option<Vec2> {
+        let (bearing, distance) = Self::bearing_and_distance(camera, position);
+
+        if distance > self.range {
+            return None;
+        }
+
+        let scaled = distance / self.range * self.radius;
+
+        Some(Vec2::new(bearing.sin() * scaled, -bearing.cos() * scaled))
+    }
+
+    /// Screen position of the centre of the scope for the current viewport.
+    pub fn centre(&self, camera: &Camera) -> Pos2 {
+        let viewport = Rect::from_min_size(Pos2::ZERO, Vec2::new(camera.viewport.width, camera.viewport.height))
+            .shrink(self.margin);
+
+        self.anchor.align_size_within_rect(Vec2::splat(self.radius * 2.0), viewport).center()
+    }
+
+    /// The camera's right and forward directions flattened onto the ground (XZ) plane, matching
+    /// the local axes used by `Camera::to_2d` so the scope agrees with what's on screen.
+    fn local_axes(camera: &Camera) -> ((f32, f32), (f32, f32)) {
+        let forward = Vector3D { x: camera.rotation_vector.x, y: 0.0, z: camera.rotation_vector.z };
+
+        let length = (forward.x * forward.x + forward.z * forward.z).sqrt();
+
+        // Looking straight up or down, so fall back to the yaw to get a heading.
+        let (fx, fz) = if length > f32::EPSILON {
+            (forward.x / length, forward.z / length)
+        } else {
+            (-camera.rotation.yaw.sin(), camera.rotation.yaw.cos())
+        };
+
+        ((-fz, fx), (fx, fz))
+    }
+}
+
+impl HudRenderer for Radar {
+    fn update(&mut self, camera: &Camera, entities: &[Entity], delta_time: f32) {
+        let swept = (self.sweep_speed * delta_time).min(TAU);
+
+        for blip in &mut self.blips {
+            blip.intensity -= delta_time / self.blip_fade_time;
+        }
+        self.blips.retain(|blip| blip.intensity > 0.0);
+
+        for entity in entities.iter().filter(|e| e.object_type == self.tracked_object_type) {
+            let (bearing, _) = Self::bearing_and_distance(camera, &entity.mesh.position);
+
+            // Has the sweep passed over this bearing during this frame?
+            if (bearing - self.sweep_angle).rem_euclid(TAU) >= swept {
+                continue;
+            }
+
+            if let Some(offset) = self.blip_offset(camera, &entity.mesh.position) {
+                self.blips.push(Blip { offset, intensity: 1.0 });
+            }
+        }
+
+        self.sweep_angle = (self.sweep_angle + swept).rem_euclid(TAU);
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let centre = self.centre(camera);
+        let colour = Color32::GREEN;
+
+        // Scope outline and range rings
+        backend.circle(centre, self.radius, Color32::TRANSPARENT, Stroke::new(2.0, colour));
+        for ring in 1..3 {
+            backend.circle(centre, self.radius * ring as f32 / 3.0, Color32::TRANSPARENT, Stroke::new(1.0, colour.gamma_multiply(0.4)));
+        }
+
+        // Field of view tick marks either side of straight ahead
+        let half_fov = camera.view_angle().to_radians() / 2.0;
+        for angle in [-half_fov, half_fov] {
+            let tip = centre + Vec2::new(angle.sin(), -angle.cos()) * self.radius;
+            backend.line([centre, tip], Stroke::new(1.0, colour.gamma_multiply(0.3)));
+        }
+
+        // Sweep line with a short fading trail behind it
+        for step in 0..8 {
+            let angle = self.sweep_angle - step as f32 * 0.05;
+            let tip = centre + Vec2::new(angle.sin(), -angle.cos()) * self.radius;
+            let fade = 1.0 - step as f32 / 8.0;
+            backend.line([centre, tip], Stroke::new(2.0, colour.gamma_multiply(fade)));
+        }
+
+        for blip in &self.blips {
+            backend.circle(centre + blip.offset, 3.0, Color32::RED.gamma_multiply(blip.intensity), Stroke::NONE);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::mesh::Mesh;
+    use crate::types::geometry::{EulerAngles, Point3D};
+
+    fn camera_looking_down_z() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0)
     }
-}
\ No newline at end of file
+
+    fn tank_at(x: f32, z: f32) -> Entity {
+        Entity::new(
+            "Tank".to_string(),
+            "enemy_tank".to_string(),
+            Mesh {
+                verts: vec![],
+                faces: vec![],
+                position: Vector3D { x, y: 0.0, z },
+                rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+            },
+        )
+    }
+
+    fn assert_offset_approx_eq(actual: Vec2, expected: Vec2) {
+        assert!((actual - expected).length() < 1e-3, "Offsets not equal: {:?} {:?}", actual, expected);
+    }
+
+    #[test]
+    /// # test_blip_offset_straight_ahead
+    /// Something straight ahead at half range should be half way up the scope.
+    fn test_blip_offset_straight_ahead() {
+        let radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+        let offset = radar.blip_offset(&camera_looking_down_z(), &Vector3D { x: 0.0, y: 0.0, z: 50.0 });
+
+        assert_offset_approx_eq(offset.unwrap(), Vec2::new(0.0, -25.0));
+    }
+
+    #[test]
+    /// # test_blip_offset_to_the_right
+    /// Looking down +Z the camera's right is -X, which matches the projection in `Camera::to_2d`.
+    fn test_blip_offset_to_the_right() {
+        let radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+        let offset = radar.blip_offset(&camera_looking_down_z(), &Vector3D { x: -100.0, y: 0.0, z: 0.0 });
+
+        assert_offset_approx_eq(offset.unwrap(), Vec2::new(50.0, 0.0));
+    }
+
+    #[test]
+    /// # test_blip_offset_follows_camera
+    /// Moving and turning the camera should keep blips relative to the camera, not the world.
+    fn test_blip_offset_follows_camera() {
+        let radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+        let mut camera = camera_looking_down_z();
+        camera.position = Point3D { x: 10.0, y: 5.0, z: 10.0 };
+        camera.rotation_vector = Vector3D { x: -1.0, y: 0.0, z: 0.0 };
+
+        // Behind the camera
+        let offset = radar.blip_offset(&camera, &Vector3D { x: 30.0, y: 0.0, z: 10.0 });
+
+        assert_offset_approx_eq(offset.unwrap(), Vec2::new(0.0, 10.0));
+    }
+
+    #[test]
+    /// # test_blip_offset_out_of_range
+    fn test_blip_offset_out_of_range() {
+        let radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+
+        assert_eq!(radar.blip_offset(&camera_looking_down_z(), &Vector3D { x: 0.0, y: 0.0, z: 101.0 }), None);
+    }
+
+    #[test]
+    /// # test_game_hud_shows_bus_messages
+    /// The HUD hears about kills and messages while connected, and each fades after a while.
+    fn test_game_hud_shows_bus_messages() {
+        let camera = camera_looking_down_z();
+        let mut bus = MessageBus::new();
+        let mut hud = GameHud::new();
+        hud.connect(&mut bus);
+
+        bus.publish(HudMessage { text: "RECORDING".to_string() });
+        bus.queue(EntityDestroyed { id: "Tank".to_string(), object_type: "enemy_tank".to_string(), position: Vector3D { x: 0.0, y: 0.0, z: 0.0 } });
+        bus.flush();
+
+        let texts: Vec<String> = hud.messages.borrow().iter().map(|(text, _)| text.clone()).collect();
+        assert_eq!(texts, vec!["RECORDING", "ENEMY TANK DESTROYED"]);
+
+        hud.update(&camera, &[], MESSAGE_TIME);
+        assert!(hud.messages.borrow().is_empty());
+
+        hud.disconnect(&mut bus);
+        bus.publish(HudMessage { text: "GONE".to_string() });
+        assert!(hud.messages.borrow().is_empty());
+        assert_eq!(bus.subscriber_count::<HudMessage>(), 0);
+    }
+
+    #[test]
+    /// # test_sweep_reveals_and_fades_blips
+    /// A blip only appears once the sweep passes over it and then fades away.
+    fn test_sweep_reveals_and_fades_blips() {
+        let camera = camera_looking_down_z();
+        let mut radar = Radar::new(100.0, 50.0, Align2::RIGHT_TOP);
+        radar.sweep_speed = PI / 2.0;
+
+        // Directly to the right, a quarter turn from the start of the sweep
+        let entities = vec![tank_at(-20.0, 0.0)];
+
+        radar.update(&camera, &entities, 0.5);
+        assert!(radar.blips.is_empty());
+
+        radar.update(&camera, &entities, 0.75);
+        assert_eq!(radar.blips.len(), 1);
+        assert_offset_approx_eq(radar.blips[0].offset, Vec2::new(10.0, 0.0));
+
+        radar.update(&camera, &entities, radar.blip_fade_time);
+        assert!(radar.blips.is_empty());
+    }
+}
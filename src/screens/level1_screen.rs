@@ -1,38 +1,761 @@
 //TODO: Code for the Level1 screen
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::ops::Range;
+use std::rc::Rc;
 use std::str::from_utf8;
-use eframe::egui::{Align2, Color32, FontId, Painter, Pos2, Stroke};
+use std::time::{SystemTime, UNIX_EPOCH};
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
 
-use crate::screens::traits::ScreenRenderer;
+use crate::screens::events::{EntityDestroyed, HudMessage, LevelComplete, ScriptOutput, SpawnEntity, SpawnWave};
+use crate::screens::traits::{ScreenRenderer, ScreenTransition};
+use crate::screens::triggers::{Trigger, Triggers};
 use crate::types::geometry::*;
 
 use serde::{Deserialize, Serialize};
 
-use rust_embed::RustEmbed;
+use crate::animation::cutscene::{Cutscene, CutscenePlayer};
+use crate::animation::track::TransformAnimation;
+use crate::console::commands::{number, Command, CommandHandler};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::input::recording::{Recording, Replay};
+use crate::{log_error, log_info, profile_count, profile_scope};
+use crate::objects::entity::{select_objects, AnimatedPart, Entity, EntityAnimation};
+use crate::objects::lod::{simplify_cached, Lod, AUTO_LODS, AUTO_LOD_MIN_VERTS};
 use crate::objects::mesh::Mesh;
+use crate::objects::particles::{EmitterSettings, ParticleSystem};
+use crate::objects::terrain::{Terrain, TerrainSettings};
+use crate::objects::vehicle::{Vehicle, VehicleSettings};
 use crate::player::camera::Camera;
+use crate::player::controllers::{CameraControllers, CameraMode, Target};
+use crate::rendering::background::Background;
+use crate::rendering::debug_draw::{debug_camera, DebugCategory, DebugDraw};
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::depth_cue::DepthCue;
+use crate::rendering::stroke_font::TextStyle;
+use crate::rendering::wireframe::{draw_faces, RenderMode, ScreenFace};
+use crate::scripting::runtime::ScriptRuntime;
+use crate::utils::assets::Asset;
+use crate::utils::message_bus::MessageBus;
+use crate::utils::profiling::{Counter, Timing};
 
-#[derive(RustEmbed)]
-#[folder = "assets/"]
-struct Asset;
+/// World units per second the player moves.
+const MOVE_SPEED: f32 = 6.0;
+
+/// Degrees per second the level's meshes spin, from the level clock so drawing a frame twice
+/// draws it the same.
+const SPIN_SPEED: f32 = 150.0;
+
+/// Seed for the explosions' randomness, fixed so replays come out the same.
+const PARTICLE_SEED: u32 = 1982;
+
+/// Where recordings are saved when recording stops, relative to the working directory.
+const RECORDINGS_FOLDER: &str = "recordings";
+
+/// Where the `dump` command writes the scene, relative to the working directory.
+const DUMPS_FOLDER: &str = "dumps";
+
+/// The levels' map files under `assets/`.
+pub const LEVEL1_MAP: &str = "maps/level1.json";
+pub const LEVEL2_MAP: &str = "maps/level2.json";
+
+/// The maps recordings can be made in and replayed from.
+const MAPS: [&str; 2] = [LEVEL1_MAP, LEVEL2_MAP];
 
 
 pub struct Level1Screen{
-    pub meshes: Vec<Mesh>,
+    pub entities: Vec<Entity>,
+    /// When set the level is driven by a recording rather than the player.
+    replay: Option<Replay>,
+    /// Whether the recording is the title screen's demo, which any key stops, rather than a
+    /// `--replay`, which only Back stops.
+    attract: bool,
+    /// The player's input while they're recording.
+    recording: Option<Recording>,
+    depth_cue: DepthCue,
+    terrain: Terrain,
+    background: Option<Background>,
+    /// How each type of object blows up.
+    explosions: HashMap<String, EmitterSettings>,
+    particles: ParticleSystem,
+    /// Keeps the camera this high above the ground when set, otherwise it flies freely.
+    camera_height: Option<f32>,
+    /// Seconds the level has been running, which the entities' animations follow.
+    time: f32,
+    /// The intro cutscene while it plays, before the player gets control.
+    intro: Option<CutscenePlayer>,
+    cameras: CameraControllers,
+    /// The entity the orbit and chase cameras follow.
+    follow: Option<usize>,
+    /// The vehicle the player drives, in levels that have one, otherwise they're on foot.
+    player: Option<Vehicle>,
+    /// What happens in the level, queued during `simulate` and delivered at the end of it.
+    bus: MessageBus,
+    /// Set once `LevelComplete` has been delivered.
+    complete: Rc<Cell<bool>>,
+    /// The level's mission, turned into events on the bus as the player plays.
+    triggers: Triggers,
+    /// Entities waiting off stage until a trigger brings them in, by wave.
+    waves: HashMap<String, Vec<Entity>>,
+    /// Waves and entities asked for on the bus, brought in once it's flushed.
+    spawns: Rc<RefCell<Vec<Spawn>>>,
+    /// How many entities scripts have spawned, to give each its own id.
+    spawned: usize,
+    scripts: ScriptRuntime,
+    /// The map file the level was loaded from, for reloading it.
+    map: &'static str,
+    /// Set by the `reload` command, the level starts again on the next update.
+    reload: bool,
+    render_mode: RenderMode,
+    /// Which debug lines are drawn over the scene.
+    debug: Vec<DebugCategory>,
+}
+
+/// Something to bring into the level.
+enum Spawn {
+    Wave(String),
+    Entity(SpawnEntity),
 }
 
 impl Level1Screen {
-    pub fn new() -> Self {
+    /// # with_map
+    /// Plays the level from a map file under `assets/`. Every level plays the same way, it's only
+    /// what's in the map that changes. The depth cue comes from the game's settings.
+    pub fn with_map(map: &'static str, depth_cue: DepthCue) -> Self {
         /*TODO: We don't want to re-load the meshes every time
             if we're re-creating the screen every render! Need to load the meshes once */ 
 
-        let meshes = load_assets().expect("Failed to load meshes");
+        let level = load_assets(map).expect("Failed to load level");
+        log_info!("Loaded {} with {} entities", map, level.entities.len());
+
+        Self::from_assets(map, level, depth_cue)
+    }
+
+    /// The level from what's been loaded from `map`, for screens that take some of it for
+    /// themselves first.
+    pub fn from_assets(map: &'static str, level: LevelAssets, depth_cue: DepthCue) -> Self {
+        let mut bus = MessageBus::new();
+        let complete = Rc::new(Cell::new(false));
+        let level_complete = complete.clone();
+        bus.subscribe(move |_: &LevelComplete| level_complete.set(true));
+
+        let spawns = Rc::new(RefCell::new(Vec::new()));
+        let spawn_wave = spawns.clone();
+        bus.subscribe(move |spawn: &SpawnWave| spawn_wave.borrow_mut().push(Spawn::Wave(spawn.wave.clone())));
+        let spawn_entity = spawns.clone();
+        bus.subscribe(move |spawn: &SpawnEntity| spawn_entity.borrow_mut().push(Spawn::Entity(spawn.clone())));
+
+        let scripts = ScriptRuntime::new(&level.scripts, &mut bus);
+
+        Self {
+            entities: level.entities,
+            replay: None,
+            attract: false,
+            recording: None,
+            depth_cue,
+            terrain: level.terrain,
+            background: level.background,
+            explosions: level.explosions,
+            particles: ParticleSystem::new(PARTICLE_SEED),
+            camera_height: level.camera_height,
+            time: 0.0,
+            intro: level.intro.map(CutscenePlayer::new),
+            cameras: CameraControllers::default(),
+            follow: None,
+            player: level.player,
+            bus,
+            complete,
+            triggers: level.triggers,
+            waves: level.waves,
+            spawns,
+            spawned: 0,
+            scripts,
+            map,
+            reload: false,
+            render_mode: RenderMode::default(),
+            debug: Vec::new(),
+        }
+    }
+
+    /// # with_recording
+    /// The level from the start with the player's input being recorded from `camera`. There's no
+    /// intro, so the recording starts in play the same way a replay does.
+    pub fn with_recording(map: &'static str, camera: &Camera, depth_cue: DepthCue) -> Self {
+        let mut screen = Self { recording: Some(Recording::new(map, camera)), intro: None, ..Self::with_map(map, depth_cue) };
+        screen.bus.queue(HudMessage { text: "RECORDING".to_string() });
+
+        screen
+    }
+
+    /// The recording's level driven by the recording, e.g. from `--replay`. The camera should be
+    /// put at the replay's `camera_start` first. There's no intro, the recording starts in play.
+    pub fn with_replay(replay: Replay, depth_cue: DepthCue) -> Result<Self, String> {
+        let map = MAPS
+            .into_iter()
+            .find(|map| *map == replay.map())
+            .ok_or(format!("Can't replay a recording made in {}", replay.map()))?;
+
+        Ok(Self { replay: Some(replay), intro: None, ..Self::with_map(map, depth_cue) })
+    }
+
+    /// Driven by the title screen's attract demo, which any key or click stops.
+    pub fn with_attract(replay: Replay, depth_cue: DepthCue) -> Result<Self, String> {
+        Ok(Self { attract: true, ..Self::with_replay(replay, depth_cue)? })
+    }
+
+    /// # simulate
+    /// Advances the level by one frame. Everything that changes the game state has to happen in
+    /// here and depend only on the actions and delta time, so replaying a recording comes out
+    /// exactly the same as when it was recorded.
+    pub fn simulate(&mut self, camera: &mut Camera, actions: &ActionState, delta_time: f32) -> Option<ScreenTransition> {
+        if actions.pressed(Action::Back) {
+            return Some(ScreenTransition::Title);
+        }
+
+        self.time += delta_time;
+        for entity in &mut self.entities {
+            entity.animate(self.time);
+        }
+
+        if actions.pressed(Action::SwitchCamera) {
+            self.switch_camera(camera);
+        }
+
+        // Only the player's own views aim, orbit and free fly are for looking around the level
+        let aiming = matches!(self.cameras.mode, CameraMode::FirstPerson | CameraMode::Chase);
+        if aiming && actions.pressed(Action::Fire) && let Some(index) = self.target(camera) {
+            self.destroy(index);
+        }
+
+        // The orbit camera uses the driving controls, so the vehicle stays put while it's on
+        if let Some(vehicle) = &mut self.player && matches!(self.cameras.mode, CameraMode::FirstPerson | CameraMode::Chase) {
+            let terrain = &self.terrain;
+            vehicle.update(actions, delta_time, |x, z| terrain.height_at(x, z), &self.entities);
+        }
+
+        let follow = match &self.player {
+            Some(vehicle) => Some(Target::of(&vehicle.entity.mesh)),
+            None => self.follow.and_then(|index| self.entities.get(index)).map(|entity| Target::of(&entity.mesh)),
+        };
+
+        if !self.cameras.update(camera, actions, follow.as_ref(), delta_time) {
+            match &self.player {
+                Some(vehicle) => vehicle.place_camera(camera),
+                None => self.move_first_person(camera, actions, delta_time),
+            }
+        }
+
+        let terrain = &self.terrain;
+        self.particles.update(delta_time, |x, z| terrain.height_at(x, z));
+
+        let player = match &self.player {
+            Some(vehicle) => vehicle.entity.mesh.position.clone(),
+            None => Vector3D::from(&camera.position),
+        };
+        self.triggers.update(self.time, &player, &self.entities, &mut self.bus);
+        self.scripts.update(self.time, delta_time, &mut self.entities, &mut self.bus);
+
+        self.bus.flush();
+
+        for spawn in self.spawns.take() {
+            match spawn {
+                Spawn::Wave(wave) => self.entities.extend(self.waves.remove(&wave).unwrap_or_default()),
+                Spawn::Entity(spawn) => self.spawn(spawn),
+            }
+        }
+
+        None
+    }
+
+    /// # advance
+    /// Moves the level on a frame without anyone playing it, the entities' animations and
+    /// anything blowing up, for screens that drive their own players about in it. It doesn't
+    /// run the map's triggers or scripts, so those screens should turn such maps away.
+    pub fn advance(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        for entity in &mut self.entities {
+            entity.animate(self.time);
+        }
+
+        let terrain = &self.terrain;
+        self.particles.update(delta_time, |x, z| terrain.height_at(x, z));
+    }
+
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.terrain.height_at(x, z)
+    }
+
+    /// Blows the entity up if its type has an explosion, leaving what to do with it to the caller.
+    pub fn explode(&mut self, entity: &Entity) {
+        if let Some(explosion) = self.explosions.get(&entity.object_type) {
+            self.particles.explode(explosion, &entity.render_mesh());
+        }
+    }
+
+    /// Brings in an entity that was asked for on the bus, on the ground. A bad model or type goes
+    /// to the console rather than stopping the game.
+    fn spawn(&mut self, spawn: SpawnEntity) {
+        match self.spawn_entity(spawn) {
+            Ok(entity) => self.entities.push(entity),
+            Err(error) => self.bus.queue(ScriptOutput { text: error, is_error: true }),
+        }
+    }
+
+    fn spawn_entity(&mut self, spawn: SpawnEntity) -> Result<Entity, String> {
+        self.spawned += 1;
+        let id = format!("Spawned{}", self.spawned);
+        let position = Vector3D { x: spawn.x, y: self.terrain.height_at(spawn.x, spawn.z), z: spawn.z };
+
+        let Some(model) = spawn.model else {
+            let mut entity = self.entities
+                .iter()
+                .chain(self.waves.values().flatten())
+                .find(|entity| entity.object_type == spawn.object_type)
+                .cloned()
+                .ok_or(format!("Can't spawn {}, there isn't one to copy", spawn.object_type))?;
+
+            // Where it's put is its new home, whatever it was animated about before
+            if let Some(animation) = &mut entity.animation {
+                animation.move_home(position.clone());
+            }
+            entity.mesh.position = position;
+
+            return Ok(Entity { id, ..entity });
+        };
+
+        if Asset::get(&model).is_none() {
+            return Err(format!("Can't spawn {}, no model {}", spawn.object_type, model));
+        }
+
+        let entity = JsonEntity {
+            id,
+            object_type: spawn.object_type,
+            model,
+            position,
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+            lods: Vec::new(),
+            on_ground: false,
+            animation: None,
+            parts: Vec::new(),
+            script: None,
+        };
+
+        load_entity(entity, &self.terrain)
+    }
+
+    /// The player looking and walking about.
+    fn move_first_person(&self, camera: &mut Camera, actions: &ActionState, delta_time: f32) {
+        let look_x = actions.value(Action::LookHorizontal);
+        let look_y = actions.value(Action::LookVertical);
+        if actions.is_down(Action::Look) && (look_x != 0.0 || look_y != 0.0) {
+            // Adjust camera yaw/pitch
+            camera.rotate(look_x, look_y);
+        }
+
+        let forward = actions.axis(Action::MoveForward, Action::MoveBackward);
+        if forward != 0.0 {
+            // Move camera forward (or back)
+            camera.move_forward(MOVE_SPEED * forward * delta_time);
+        }
+
+        let strafe = actions.axis(Action::StrafeLeft, Action::StrafeRight);
+        if strafe != 0.0 {
+            camera.move_strafe(MOVE_SPEED * strafe * delta_time);
+        }
+
+        self.ground_camera(camera);
+    }
+
+    /// # switch_camera
+    /// Changes to the next camera mode. The orbit and chase cameras follow the player's vehicle,
+    /// or on foot whatever the player was looking at when they left first person, or the nearest
+    /// entity if nothing.
+    fn switch_camera(&mut self, camera: &mut Camera) {
+        if self.cameras.mode == CameraMode::FirstPerson && self.player.is_none() {
+            self.follow = self.target(camera).or_else(|| self.nearest(camera));
+        }
+
+        self.cameras.switch(camera);
+    }
+
+    fn nearest(&self, camera: &Camera) -> Option<usize> {
+        let distance = |entity: &Entity| Vector3D::from(&camera.position).subtract(&entity.mesh.position).length();
+
+        self.entities
+            .iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+            .map(|(index, _)| index)
+    }
+
+    /// Puts the camera at the level's camera height above the ground, if it has one.
+    pub fn ground_camera(&self, camera: &mut Camera) {
+        if let Some(height) = self.camera_height {
+            camera.position.y = self.terrain.height_at(camera.position.x, camera.position.z) + height;
+        }
+    }
+
+    /// # target
+    /// The nearest entity straight ahead of the camera, going by its bounding sphere.
+    fn target(&self, camera: &Camera) -> Option<usize> {
+        let look = camera.rotation_vector.normalise();
+
+        self.entities
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entity)| {
+                let to_entity = Vector3D::from(&camera.position).subtract(&entity.mesh.position);
+                let along = to_entity.dot_product(&look);
+                let miss_squared = to_entity.dot_product(&to_entity) - along * along;
+                let radius = entity.lod.bounding_radius();
+
+                (along > 0.0 && miss_squared <= radius * radius).then_some((index, along))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(index, _)| index)
+    }
+
+    /// Removes an entity from the level, blowing it up if its type has an explosion.
+    fn destroy(&mut self, index: usize) {
+        let entity = self.entities.remove(index);
+
+        self.bus.queue(EntityDestroyed {
+            id: entity.id.clone(),
+            object_type: entity.object_type.clone(),
+            position: entity.mesh.position.clone(),
+        });
+
+        // The cameras lose what they were following, or it's moved down one
+        self.follow = match self.follow {
+            Some(follow) if follow == index => None,
+            Some(follow) if follow > index => Some(follow - 1),
+            follow => follow,
+        };
+
+        self.explode(&entity);
+    }
+
+    /// Every type of object there is in the level or its waves, for spawning more.
+    fn object_types(&self) -> BTreeSet<String> {
+        self.entities
+            .iter()
+            .chain(self.waves.values().flatten())
+            .map(|entity| entity.object_type.clone())
+            .collect()
+    }
+
+    /// # dump
+    /// Writes where everything is to a JSON file under `dumps/`, returning its path.
+    fn dump(&self) -> Result<String, String> {
+        let dump = SceneDump {
+            map: self.map,
+            time: self.time,
+            player: self.player.as_ref().map(|vehicle| EntityDump::of(&vehicle.entity)),
+            entities: self.entities.iter().map(EntityDump::of).collect(),
+        };
+
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+        let path = format!("{}/{}.json", DUMPS_FOLDER, seconds);
+        let json = serde_json::to_string_pretty(&dump).map_err(|error| error.to_string())?;
+
+        fs::create_dir_all(DUMPS_FOLDER)
+            .and_then(|_| fs::write(&path, json))
+            .map_err(|error| format!("Failed to write {}: {}", path, error))?;
+
+        Ok(path)
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+            let path = format!("{}/{}.rec", RECORDINGS_FOLDER, seconds);
+
+            if let Err(error) = recording.save(&path) {
+                log_error!("Failed to save recording {}: {}", path, error);
+                self.bus.publish(HudMessage { text: "RECORDING NOT SAVED".to_string() });
+            } else {
+                self.bus.publish(HudMessage { text: "RECORDING SAVED".to_string() });
+            }
+        }
+    }
+
+    /// # render_scene
+    /// Draws the level from the camera: the ground, its entities with `vehicles` among them, and
+    /// anything blowing up. What isn't part of the world, such as the REC sign, is left to the
+    /// caller.
+    pub fn render_scene<'a>(&'a self, camera: &Camera, vehicles: impl IntoIterator<Item = &'a Entity>, backend: &mut dyn RenderBackend) {
+        profile_scope!(Timing::Render);
+        profile_count!(Counter::Views, 1);
+
+        // Looking at the game camera from behind to see its frustum, the scene's drawn from there
+        let mut debug = DebugDraw::new(&self.debug);
+        let viewer = debug.is_enabled(DebugCategory::Frustum).then(|| debug_camera(camera));
+        let (game_camera, camera) = (camera, viewer.as_ref().unwrap_or(camera));
+
+        // TODO: Remove temporary spin
+        let spin = (self.time * SPIN_SPEED).to_radians();
+
+        //TODO: Calculate object positions
+        
+        self.terrain.render(camera, &self.depth_cue, backend);
+        
+        //TODO: Render game objects
+
+        let viewport_width = camera.viewport.width;
+        let viewport_height = camera.viewport.height;
+
+        // Filled faces have to be sorted across every mesh, so they're drawn once they're all in
+        let mut faces = Vec::new();
+
+        // Only the level's own entities spin, the vehicles are driven
+        let entities = self.entities.iter().map(|entity| (entity, spin)).chain(vehicles.into_iter().map(|vehicle| (vehicle, 0.0)));
+
+        for (entity, spin) in entities {
+
+            //TODO: Render 3D Points to 2D via camera
+
+            //TODO: Need to apply transforms before projecting
+
+            profile_count!(Counter::MeshesDrawn, 1);
+
+            let mut mut_mesh = entity.render_mesh();
 
-        Self { meshes, }
+            mut_mesh.rotation.pitch += spin;
+            mut_mesh.rotation.roll += spin;
+            mut_mesh.rotation.yaw += spin;
+
+            let transformed_verts = mut_mesh.get_transformed_verts();
+            let points_2d = camera.project(&transformed_verts);
+
+            let position = &entity.mesh.position;
+            debug.local_axes(&mut_mesh);
+            debug.normals(&transformed_verts, &mut_mesh.faces);
+            debug.bounds(&Point3D { x: position.x, y: position.y, z: position.z }, entity.lod.bounding_radius(), &transformed_verts);
+
+            for face in &mut_mesh.faces {
+                let mut verts = Vec::new();
+
+                for vert_index in &face.vert_indices {
+                    verts.push(points_2d.get(*vert_index - 1).unwrap());
+                }
+
+                let num_verts = verts.len();
+
+                profile_count!(Counter::Faces, 1);
+                profile_count!(Counter::Edges, num_verts);
+
+                let mut face_outside_viewport = true;
+
+                // Ignore faces outside view (doesn't really work well)...
+                for i in 0..num_verts {
+                    let first_vert = verts.get(i).unwrap();
+                    let second_vert = if i == num_verts - 1 { verts.get(0).unwrap() } else {verts.get(i + 1).unwrap()} ;
+
+                    // Projected points are already in the camera's viewport, whatever part of the window that's in
+                    let start: Pos2 = [first_vert.x, first_vert.y].into();
+                    let end: Pos2 = [second_vert.x, second_vert.y].into();
+
+                    if  (start.x >= 0.0 && start.x <= viewport_width) || (start.y >= 0.0 && start.y <= viewport_height) ||
+                        (end.x >= 0.0 && end.x <= viewport_width) || (end.y >= 0.0 && end.y <= viewport_height) {
+                        face_outside_viewport = false;
+                    }
+                }
+
+                if face_outside_viewport{
+                  //  continue;
+                }
+
+                if self.render_mode != RenderMode::Wireframe {
+                    faces.extend(ScreenFace::new(&verts));
+                    continue;
+                }
+
+                // Render faces
+                for i in 0..num_verts {
+                    let first_vert = verts.get(i).unwrap();
+                    let second_vert = if i == num_verts - 1 { verts.get(0).unwrap() } else {verts.get(i + 1).unwrap()} ;
+
+                    let start: Pos2 = [first_vert.x, first_vert.y].into();
+                    let end: Pos2 = [second_vert.x, second_vert.y].into();
+
+                    // Fainter and thinner with distance
+                    let stroke = self.depth_cue.edge_stroke(camera, Stroke::new(2.0, Color32::GREEN), first_vert.depth, second_vert.depth);
+
+                    backend.line([start, end], stroke);
+                }
+            }
+        }
+
+        draw_faces(backend, faces, self.render_mode, |depth| self.depth_cue.edge_stroke(camera, Stroke::new(2.0, Color32::GREEN), depth, depth));
+
+        self.particles.render(camera, &self.depth_cue, backend);
+
+        debug.world_axes();
+        debug.frustum(game_camera);
+        debug.render(camera, backend);
+    }
+}
+
+
+impl CommandHandler for Level1Screen {
+    fn commands(&self) -> Vec<Command> {
+        vec![
+            Command { name: "spawn", usage: "<object_type> <x> <z>", help: "Spawns a copy of one of the level's objects" },
+            Command { name: "render", usage: "[mode]", help: "Draws meshes as wireframe, filled or hidden-line" },
+            Command { name: "reload", usage: "", help: "Starts the level again from its map file" },
+            Command { name: "dump", usage: "", help: "Writes the scene to a JSON file" },
+            Command { name: "debug", usage: "[category] [on|off]", help: "Shows or switches debug lines" },
+        ]
+    }
+
+    fn complete(&self, command: &str, index: usize) -> Vec<String> {
+        match (command, index) {
+            ("spawn", 0) => self.object_types().into_iter().collect(),
+            ("render", 0) => RenderMode::ALL.iter().map(|mode| mode.name().to_string()).collect(),
+            ("debug", 0) => DebugCategory::ALL.iter().map(|category| category.name().to_string()).collect(),
+            ("debug", 1) => vec!["on".to_string(), "off".to_string()],
+            _ => Vec::new(),
+        }
+    }
+
+    fn run(&mut self, command: &str, args: &[&str]) -> Result<String, String> {
+        match command {
+            "spawn" => {
+                let object_type = args.first().ok_or("Missing object_type")?;
+                let x = number(args, 1, "x")?;
+                let z = number(args, 2, "z")?;
+
+                let entity = self.spawn_entity(SpawnEntity { object_type: object_type.to_string(), model: None, x, z })?;
+                let id = entity.id.clone();
+                self.entities.push(entity);
+
+                Ok(format!("Spawned {}", id))
+            }
+            "render" => {
+                if let Some(name) = args.first() {
+                    self.render_mode = RenderMode::from_name(name).ok_or(format!("No render mode {}", name))?;
+                }
+
+                Ok(format!("Rendering {}", self.render_mode.name()))
+            }
+            "reload" => {
+                self.reload = true;
+
+                Ok(format!("Reloading {}", self.map))
+            }
+            "dump" => self.dump().map(|path| format!("Dumped scene to {}", path)),
+            "debug" => {
+                if let Some(name) = args.first() {
+                    let category = DebugCategory::from_name(name).ok_or(format!("No debug category {}", name))?;
+                    let on = match args.get(1) {
+                        Some(&"on") => true,
+                        Some(&"off") => false,
+                        Some(other) => return Err(format!("Expected on or off, not {}", other)),
+                        None => !self.debug.contains(&category),
+                    };
+
+                    self.debug.retain(|other| *other != category);
+                    if on {
+                        self.debug.push(category);
+                    }
+                }
+
+                let states = DebugCategory::ALL
+                    .iter()
+                    .map(|category| format!("{} {}", category.name(), if self.debug.contains(category) { "on" } else { "off" }));
+
+                Ok(states.collect::<Vec<_>>().join(", "))
+            }
+            _ => Err(format!("Unknown command {}", command)),
+        }
+    }
+}
+
+/// What `dump` writes.
+#[derive(Serialize)]
+struct SceneDump<'a> {
+    map: &'a str,
+    time: f32,
+    player: Option<EntityDump<'a>>,
+    entities: Vec<EntityDump<'a>>,
+}
+
+#[derive(Serialize)]
+struct EntityDump<'a> {
+    id: &'a str,
+    object_type: &'a str,
+    position: &'a Vector3D,
+    rotation: &'a EulerAngles,
+    verts: usize,
+    faces: usize,
+}
+
+impl<'a> EntityDump<'a> {
+    fn of(entity: &'a Entity) -> Self {
+        Self {
+            id: &entity.id,
+            object_type: &entity.object_type,
+            position: &entity.mesh.position,
+            rotation: &entity.mesh.rotation,
+            verts: entity.mesh.verts.len(),
+            faces: entity.mesh.faces.len(),
+        }
     }
 }
 
 
-// Intermediate struct to match the JSON structure
+/// # LevelAssets
+/// Everything loaded from a level's JSON.
+pub struct LevelAssets {
+    pub entities: Vec<Entity>,
+    pub terrain: Terrain,
+    pub background: Option<Background>,
+    pub explosions: HashMap<String, EmitterSettings>,
+    pub camera_height: Option<f32>,
+    pub intro: Option<Cutscene>,
+    pub player: Option<Vehicle>,
+    /// Each player's vehicle in versus, where they start.
+    pub players: Vec<Vehicle>,
+    pub triggers: Triggers,
+    pub waves: HashMap<String, Vec<Entity>>,
+    pub scripts: Vec<String>,
+}
+
+// Intermediate structs to match the JSON structure
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonLevel {
+    #[serde(default)]
+    camera_height: Option<f32>,
+    #[serde(default)]
+    terrain: TerrainSettings,
+    /// A background file under `assets/`.
+    #[serde(default)]
+    background: Option<String>,
+    /// Effect files under `assets/` by object type, for when one is destroyed.
+    #[serde(default)]
+    explosions: HashMap<String, String>,
+    /// A cutscene file under `assets/` to play when the level starts.
+    #[serde(default)]
+    intro: Option<String>,
+    /// The vehicle the player drives. Without one they're on foot.
+    #[serde(default)]
+    player: Option<JsonPlayer>,
+    /// Where each player starts in versus, which doesn't use `player`.
+    #[serde(default)]
+    players: Vec<JsonPlayer>,
+    entities: Vec<JsonEntity>,
+    /// Entities that aren't there at the start, by name, for triggers to spawn.
+    #[serde(default)]
+    waves: HashMap<String, Vec<JsonEntity>>,
+    #[serde(default)]
+    triggers: Vec<Trigger>,
+    /// Level script files under `assets/`, run every tick.
+    #[serde(default)]
+    scripts: Vec<String>,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct JsonEntity {
     id: String,
@@ -40,34 +763,191 @@ struct JsonEntity {
     model: String,
     position: Vector3D,
     rotation: EulerAngles,
+    /// Coarser versions of the model, from the most to the least detailed. Big models get some
+    /// generated if there aren't any.
+    #[serde(default)]
+    lods: Vec<JsonLod>,
+    /// Whether `position.y` is a height above the terrain rather than above 0.
+    #[serde(default)]
+    on_ground: bool,
+    /// Moves the entity relative to `position` and `rotation`.
+    #[serde(default)]
+    animation: Option<TransformAnimation>,
+    #[serde(default)]
+    parts: Vec<JsonPart>,
+    /// A script file under `assets/` that moves the entity.
+    #[serde(default)]
+    script: Option<String>,
+}
+
+/// The player's vehicle and where it starts, on the ground.
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonPlayer {
+    /// A vehicle file under `assets/`.
+    vehicle: String,
+    x: f32,
+    z: f32,
+    /// Degrees the hull faces, 0 is +z.
+    #[serde(default)]
+    yaw: f32,
 }
 
-pub fn load_assets() -> Result<Vec<Mesh>, Box<dyn std::error::Error>>{
-    //TODO Load level definition
+/// Objects from an entity's OBJ that move together, such as a turret and its barrel.
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonPart {
+    objects: Vec<String>,
+    /// Where the part turns about, in the model's own space.
+    pivot: Point3D,
+    animation: TransformAnimation,
+}
 
-    let map_json = Asset::get("maps/level1.json").expect("Failed to load /maps.level1.json");
+/// A level of detail for an entity, either its own OBJ or generated from the entity's model.
+#[derive(Debug, Deserialize, Serialize)]
+struct JsonLod {
+    #[serde(default)]
+    model: Option<String>,
+    /// Fraction of the model's vertices to keep when generating it.
+    #[serde(default)]
+    ratio: Option<f32>,
+    /// Used when the radius of the model's bounding sphere on screen is less than this many pixels.
+    below_radius: f32,
+}
 
-    let obj_content = from_utf8(&map_json.data).expect("Invalid UTF-8 in maps/level1.json");
+/// The levels of detail for `mesh`, loaded from `model`. Generated levels are cached by `model`.
+fn load_lod(model: &str, mesh: &Mesh, lods: &[JsonLod]) -> Result<Lod, String> {
+    if lods.is_empty() {
+        return Ok(if mesh.verts.len() > AUTO_LOD_MIN_VERTS { Lod::generated(model, mesh, &AUTO_LODS) } else { Lod::single(mesh) });
+    }
+
+    let coarser = lods
+        .iter()
+        .map(|lod| {
+            let lod_mesh = match (&lod.model, lod.ratio) {
+                (Some(model), _) => Mesh { verts: load_model_verts(model), faces: load_model_faces(model), ..mesh.clone() },
+                (None, Some(ratio)) => simplify_cached(model, mesh, (mesh.verts.len() as f32 * ratio).round() as usize),
+                (None, None) => return Err("A LOD needs either a model or a ratio".to_string()),
+            };
+
+            Ok((lod_mesh, lod.below_radius))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(Lod::new(mesh, coarser))
+}
+
+pub fn load_assets(map: &str) -> Result<LevelAssets, Box<dyn std::error::Error>>{
+    let map_json = Asset::get(map).ok_or(format!("Failed to load {}", map))?;
+
+    let obj_content = from_utf8(&map_json.data)?;
 
     //TODO: Load verts from obj
-    // Deserialize JSON into Vec<JsonEntity>
-    let entities: Vec<JsonEntity> = serde_json::from_str(&obj_content)?;
+    // Deserialize JSON into JsonLevel
+    let level: JsonLevel = serde_json::from_str(&obj_content)?;
+
+    let terrain = Terrain::new(&level.terrain)?;
+    let background = level.background.as_deref().map(Background::load_asset).transpose()?;
+    let intro = level.intro.as_deref().map(Cutscene::load_asset).transpose()?;
+    let explosions = level.explosions
+        .iter()
+        .map(|(object_type, effect)| Ok((object_type.clone(), EmitterSettings::load_asset(effect)?)))
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
 
-    // Convert JsonEntity to Mesh (verts will be empty for now)
-    let meshes: Vec<Mesh> = entities
+    let entities: Vec<Entity> = level.entities
         .into_iter()
-        .map(|entity| Mesh {
-            verts: load_model_verts(&entity.model), // Load verts from model
-            faces: load_model_faces(&entity.model),
-            position: entity.position,
-            rotation: entity.rotation,
+        .map(|entity| load_entity(entity, &terrain))
+        .collect::<Result<_, String>>()?;
+
+    let triggers = Triggers::new(level.triggers)?;
+    if let Some(missing) = triggers.waves().find(|wave| !level.waves.contains_key(*wave)) {
+        return Err(format!("No wave {} in {}", missing, map).into());
+    }
+
+    let waves = level.waves
+        .into_iter()
+        .map(|(wave, entities)| Ok((wave, entities.into_iter().map(|entity| load_entity(entity, &terrain)).collect::<Result<_, String>>()?)))
+        .collect::<Result<_, String>>()?;
+
+    let player = level.player.map(|player| load_vehicle("Player", &player, &terrain)).transpose()?;
+    let players = level.players
+        .iter()
+        .enumerate()
+        .map(|(index, player)| load_vehicle(&format!("Player{}", index + 1), player, &terrain))
+        .collect::<Result<_, _>>()?;
+
+    Ok(LevelAssets { entities, terrain, background, explosions, camera_height: level.camera_height, intro, player, players, triggers, waves, scripts: level.scripts })
+}
+
+/// Converts a JsonEntity to an Entity, loading the mesh from the model file.
+fn load_entity(mut entity: JsonEntity, terrain: &Terrain) -> Result<Entity, String> {
+    if entity.on_ground {
+        entity.position.y += terrain.height_at(entity.position.x, entity.position.z);
+    }
+
+    let mesh = Mesh {
+        verts: load_model_verts(&entity.model), // Load verts from model
+        faces: load_model_faces(&entity.model),
+        position: entity.position,
+        rotation: entity.rotation,
+    };
+    let lod = load_lod(&entity.model, &mesh, &entity.lods)?;
+
+    let objects = load_model_parts(&entity.model)?;
+    let parts = entity.parts
+        .into_iter()
+        .map(|part| {
+            let verts = select_objects(&objects, &part.objects, &entity.model)?;
+
+            Ok(AnimatedPart::new(&lod, &verts, part.pivot, part.animation))
         })
-        .collect();
+        .collect::<Result<_, String>>()?;
+
+    let animation = entity.animation.map(|animation| EntityAnimation::new(&mesh, animation));
+
+    Ok(Entity { lod, animation, parts, script: entity.script, ..Entity::new(entity.id, entity.object_type, mesh) })
+}
 
-    Ok(meshes)
+fn load_vehicle(id: &str, player: &JsonPlayer, terrain: &Terrain) -> Result<Vehicle, Box<dyn std::error::Error>> {
+    let settings = VehicleSettings::load_asset(&player.vehicle)?;
+
+    let mesh = Mesh {
+        verts: settings.face_forward(&load_model_verts(&settings.model)),
+        faces: load_model_faces(&settings.model),
+        position: Vector3D { x: player.x, y: terrain.height_at(player.x, player.z), z: player.z },
+        rotation: EulerAngles { pitch: 0.0, yaw: player.yaw.to_radians(), roll: 0.0 },
+    };
+    // The vehicle's verts are turned to face forward, so they're cached under its settings
+    let lod = load_lod(&player.vehicle, &mesh, &[])?;
+    let entity = Entity { lod, ..Entity::new(id.to_string(), "player".to_string(), mesh) };
+
+    Ok(Vehicle::new(settings.clone(), entity, &load_model_parts(&settings.model)?)?)
 }
 
-fn load_model_verts(model_name: &String) -> Vec<Point3D> {
+/// # load_model_parts
+/// The objects (`o` lines) in an OBJ file, each with the range of vertex indices it has.
+pub fn load_model_parts(model_name: &String) -> Result<Vec<(String, Range<usize>)>, String> {
+    let model_obj = Asset::get(model_name).ok_or(format!("Failed to load {}", model_name))?;
+
+    let obj_content = from_utf8(&model_obj.data).map_err(|_| format!("Invalid UTF-8 in {}", model_name))?;
+
+    let mut parts: Vec<(String, Range<usize>)> = Vec::new();
+    let mut vert_count = 0;
+
+    for line in obj_content.lines() {
+        if let Some(name) = line.strip_prefix("o ") {
+            parts.push((name.trim().to_string(), vert_count..vert_count));
+        } else if line.starts_with("v ") {
+            vert_count += 1;
+
+            if let Some((_, verts)) = parts.last_mut() {
+                verts.end = vert_count;
+            }
+        }
+    }
+
+    Ok(parts)
+}
+
+pub fn load_model_verts(model_name: &String) -> Vec<Point3D> {
     // Load model from assets
     let cube_obj = Asset::get(model_name).expect(&format!("Failed to load {}", model_name));
 
@@ -92,7 +972,7 @@ fn load_model_verts(model_name: &String) -> Vec<Point3D> {
     verts
 }
 
-fn load_model_faces(model_name: &String) -> Vec<Face> {
+pub fn load_model_faces(model_name: &String) -> Vec<Face> {
     // Load model from assets
     let cube_obj = Asset::get(model_name).expect(&format!("Failed to load {}", model_name));
 
@@ -125,89 +1005,585 @@ fn load_model_faces(model_name: &String) -> Vec<Face> {
     faces
 }
 
-// TODO: Remove temporary frame counter
-static mut rotation: f32 = 0.0;
-
 impl ScreenRenderer for Level1Screen {
-    fn render(&self, camera: &Camera, painter: &Painter) {
-        // TODO: Remove temporary counter
-        unsafe {
-            rotation += 2.5;
+    fn update(&mut self, camera: &mut Camera, actions: &ActionState, delta_time: f32) -> Option<ScreenTransition> {
+        if self.reload {
+            self.stop_recording();
+
+            return Some(ScreenTransition::Map(self.map));
         }
 
-        //TODO: Calculate object positions
-        
-        //TODO: Render map
-        
-        //TODO: Render game objects
+        // Only changes what's drawn, so it's fine outside `simulate`
+        for entity in &mut self.entities {
+            entity.update_lod(camera);
+        }
+
+        if let Some(vehicle) = &mut self.player {
+            vehicle.entity.update_lod(camera);
+        }
+
+        if let Some(intro) = &mut self.intro {
+            let skip = actions.pressed(Action::Fire) || actions.pressed(Action::Back);
+
+            if !intro.update(camera, delta_time, skip) {
+                self.intro = None;
+                self.ground_camera(camera);
+            }
+
+            return None;
+        }
+
+        if let Some(replay) = &mut self.replay {
+            // Any key or click during the demo goes back to the title screen, a replay needs Back
+            let stop = if self.attract { actions.captured.is_some() } else { actions.pressed(Action::Back) };
+            if stop {
+                return Some(ScreenTransition::Title);
+            }
+
+            return match replay.next_frame().cloned() {
+                Some(frame) => self.simulate(camera, &frame.actions, frame.delta_time),
+                None => Some(ScreenTransition::Title),
+            };
+        }
+
+        // Recording starts the level over, so a replay doesn't need everything that's happened
+        if actions.pressed(Action::ToggleRecording) {
+            if self.recording.is_some() {
+                self.stop_recording();
+            } else {
+                return Some(ScreenTransition::Record(self.map));
+            }
+        }
+
+        if let Some(recording) = &mut self.recording {
+            recording.push(delta_time, actions);
+        }
+
+        let transition = self.simulate(camera, actions, delta_time);
+
+        // Don't lose the recording if the player leaves the level before stopping it
+        if transition.is_some() {
+            self.stop_recording();
+        }
+
+        transition
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        // The player's own vehicle is only in view from outside it
+        let player = self.player.as_ref().filter(|_| self.cameras.mode != CameraMode::FirstPerson).map(|vehicle| &vehicle.entity);
+
+        self.render_scene(camera, player, backend);
 
-        let viewport_width = camera.viewport.width;
         let viewport_height = camera.viewport.height;
         let viewport_width_div_2 = &camera.viewport.width / 2.0;
-        let viewport_height_div_2 = &camera.viewport.height / 2.0;
-        
-        for mesh in &self.meshes {
 
-            //TODO: Render 3D Points to 2D via camera
+        if self.recording.is_some() {
+            let mut style = TextStyle::new(24.0);
+            style.align = Align2::CENTER_TOP;
 
-            //TODO: Need to apply transforms before projecting
+            backend.text("REC", Pos2::new(viewport_width_div_2, 10.0), &style, Stroke::new(2.0, Color32::RED));
+        }
+
+        if self.complete.get() {
+            let mut style = TextStyle::new(40.0);
+            style.align = Align2::CENTER_CENTER;
+
+            backend.text("LEVEL COMPLETE", Pos2::new(viewport_width_div_2, viewport_height * 0.3), &style, Stroke::new(3.0, Color32::GREEN));
+        }
+
+        if self.cameras.mode != CameraMode::FirstPerson {
+            let mut style = TextStyle::new(16.0);
+            style.align = Align2::LEFT_BOTTOM;
+
+            backend.text(self.cameras.mode.label(), Pos2::new(20.0, viewport_height - 20.0), &style, Stroke::new(1.5, Color32::GREEN));
+        }
+
+        if self.replay.is_some() {
+            let mut style = TextStyle::new(24.0);
+            style.align = Align2::CENTER_BOTTOM;
+
+            let label = if self.attract { "DEMO - PRESS ANY KEY" } else { "REPLAY" };
+            backend.text(label, Pos2::new(viewport_width_div_2, viewport_height - 20.0), &style, Stroke::new(2.0, Color32::GREEN));
+        }
+    }
+
+    fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    fn background(&self) -> Option<&Background> {
+        self.background.as_ref()
+    }
+
+    fn bus(&mut self) -> Option<&mut MessageBus> {
+        Some(&mut self.bus)
+    }
+
+    fn uses_layout(&self) -> bool {
+        true
+    }
+
+    fn commands(&mut self) -> Option<&mut dyn CommandHandler> {
+        Some(self)
+    }
+}
+
+#[cfg(test)]
+impl Level1Screen {
+    /// Level 1 with the default depth cue, so tests don't depend on a local settings file.
+    pub fn new() -> Self {
+        Self::with_map(LEVEL1_MAP, DepthCue::default())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_camera() -> Camera {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        camera.update_viewport_size(Rectangle { width: 1024.0, height: 768.0 });
+
+        camera
+    }
+
+    /// Replays a recording headlessly, the same way the game does, and returns the final camera
+    /// and level.
+    fn replay(recording: Recording) -> (Camera, Level1Screen) {
+        let mut camera = start_camera();
+        let replay = Replay::new(recording);
+        replay.camera_start().apply(&mut camera);
+
+        let mut screen = Level1Screen::with_replay(replay, DepthCue::default()).unwrap();
+        let no_input = ActionState::default();
+
+        while screen.update(&mut camera, &no_input, 1.0 / 60.0).is_none() {}
+
+        (camera, screen)
+    }
+
+    #[test]
+    /// # test_simulate_moves_at_move_speed
+    fn test_simulate_moves_at_move_speed() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::new();
+
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveForward, 1.0, false);
+
+        for _ in 0..30 {
+            screen.simulate(&mut camera, &actions, 1.0 / 30.0);
+        }
+
+        assert!((camera.position.z - MOVE_SPEED).abs() < 1e-4, "z: {}", camera.position.z);
+    }
 
-            let mut mut_mesh = mesh.clone();
+    #[test]
+    /// # test_fire_destroys_target
+    /// Shooting the nearest tank in line removes it and leaves an explosion in its place.
+    fn test_fire_destroys_target() {
+        let mut camera = start_camera();
+        camera.position.z = -40.0;
 
-            unsafe {
-                mut_mesh.rotation.pitch += rotation.to_radians();
-                mut_mesh.rotation.roll += rotation.to_radians();
-                mut_mesh.rotation.yaw += rotation.to_radians();
+        let mut screen = Level1Screen::new();
+        let count = screen.entities.len();
+
+        let mut actions = ActionState::default();
+        actions.set(Action::Fire, 1.0, true);
+        screen.simulate(&mut camera, &actions, 1.0 / 60.0);
+
+        assert_eq!(screen.entities.len(), count - 1);
+        assert!(screen.entities.iter().all(|entity| entity.mesh.position.z > -16.0), "the nearest goes first");
+        assert!(screen.particles.count() > 0);
+
+        // Off to the side there's nothing to hit
+        camera.position.x = 50.0;
+        screen.simulate(&mut camera, &actions, 1.0 / 60.0);
+        assert_eq!(screen.entities.len(), count - 1);
+    }
+
+    #[test]
+    /// # test_no_fire_from_orbit_or_free_fly
+    fn test_no_fire_from_orbit_or_free_fly() {
+        let mut screen = Level1Screen::new();
+        let count = screen.entities.len();
+
+        let mut actions = ActionState::default();
+        actions.set(Action::Fire, 1.0, true);
+
+        for mode in [CameraMode::Orbit, CameraMode::FreeFly] {
+            let mut camera = start_camera();
+            camera.position.z = -40.0;
+            screen.cameras.mode = mode;
+            screen.simulate(&mut camera, &actions, 1.0 / 60.0);
+
+            assert_eq!(screen.entities.len(), count);
+        }
+    }
+
+    #[test]
+    /// # test_intro_plays_before_control
+    /// The intro has the camera until it's skipped, then the player starts from the start camera.
+    fn test_intro_plays_before_control() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::new();
+        assert!(screen.intro.is_some());
+
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveForward, 1.0, false);
+
+        for _ in 0..30 {
+            assert!(screen.update(&mut camera, &actions, 1.0 / 30.0).is_none());
+        }
+        assert!(camera.position.y > 10.0, "{:?}", camera.position);
+
+        let mut skip = ActionState::default();
+        skip.set(Action::Fire, 1.0, true);
+        screen.update(&mut camera, &skip, 1.0 / 30.0);
+
+        assert!(screen.intro.is_none());
+        assert_eq!((camera.position.x, camera.position.z), (0.0, 0.0));
+        assert_eq!(screen.time, 0.0, "the level doesn't start until the intro's done");
+    }
+
+    #[test]
+    /// # test_platform_moves
+    /// The moving platform rises and comes back down on the level's clock.
+    fn test_platform_moves() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::new();
+        let platform = |screen: &Level1Screen| screen.entities.iter().find(|entity| entity.id == "Platform1").unwrap().mesh.position.y;
+
+        let ground = platform(&screen);
+
+        for _ in 0..90 {
+            screen.simulate(&mut camera, &ActionState::default(), 1.0 / 30.0);
+        }
+        assert!((platform(&screen) - ground - 4.0).abs() < 1e-3, "up after 3s: {}", platform(&screen));
+
+        for _ in 0..150 {
+            screen.simulate(&mut camera, &ActionState::default(), 1.0 / 30.0);
+        }
+        assert!((platform(&screen) - ground).abs() < 1e-3, "back down after 8s: {}", platform(&screen));
+    }
+
+    #[test]
+    /// # test_switch_camera_follows_target
+    /// Switching to the orbit camera circles what the player was looking at, and switching all
+    /// the way round puts the player back where they were.
+    fn test_switch_camera_follows_target() {
+        let mut camera = start_camera();
+        camera.position.z = -40.0;
+
+        let mut screen = Level1Screen::new();
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        let first_person = camera.position.clone();
+
+        let mut switch = ActionState::default();
+        switch.set(Action::SwitchCamera, 1.0, true);
+        screen.simulate(&mut camera, &switch, 1.0 / 60.0);
+
+        assert_eq!(screen.cameras.mode, CameraMode::Orbit);
+        let followed = screen.entities[screen.follow.unwrap()].mesh.position.clone();
+        assert_eq!(followed.z, -16.0, "the tank in front");
+
+        let distance = Vector3D::from(&camera.position).subtract(&followed).length();
+        assert!((distance - screen.cameras.orbit.distance).abs() < 2.0, "orbiting at {}", distance);
+
+        for _ in 0..3 {
+            screen.simulate(&mut camera, &switch, 1.0 / 60.0);
+        }
+
+        assert_eq!(screen.cameras.mode, CameraMode::FirstPerson);
+        assert_eq!(camera.position, first_person);
+    }
+
+    #[test]
+    /// # test_level2_drives_tank
+    /// Level 2 puts the player in a tank, with the camera in its cockpit as it drives.
+    fn test_level2_drives_tank() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::with_map(LEVEL2_MAP, DepthCue::default());
+
+        let mut throttle = ActionState::default();
+        throttle.set(Action::MoveForward, 1.0, false);
+
+        for _ in 0..60 {
+            screen.simulate(&mut camera, &throttle, 1.0 / 30.0);
+        }
+
+        let tank = &screen.player.as_ref().unwrap().entity.mesh.position;
+        assert!(tank.x < 20.0 && tank.z.abs() < 1e-3, "driven towards -x: {:?}", tank);
+        assert_eq!(tank.y, screen.terrain.height_at(tank.x, tank.z));
+
+        assert!((camera.position.x - tank.x).abs() < 1.0 && camera.position.y > tank.y + 5.0, "in the cockpit: {:?}", camera.position);
+        assert!(camera.rotation_vector.x < -0.99, "looking ahead: {:?}", camera.rotation_vector);
+    }
+
+    #[test]
+    /// # test_destroying_every_enemy_completes_level
+    /// Each kill is published on the bus at the end of the frame. Level 1's mission is only
+    /// complete once the player has set off the ambush at the platform and beaten that too.
+    fn test_destroying_every_enemy_completes_level() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::new();
+
+        let destroyed = Rc::new(Cell::new(0));
+        let counter = destroyed.clone();
+        screen.bus().unwrap().subscribe(move |event: &EntityDestroyed| {
+            assert_eq!(event.object_type, "enemy_tank");
+            counter.set(counter.get() + 1);
+        });
+
+        let destroy_enemies = |screen: &mut Level1Screen| {
+            while let Some(index) = screen.entities.iter().position(|entity| entity.object_type == "enemy_tank") {
+                screen.destroy(index);
             }
+        };
 
-            let points_2d = camera.to_2d(&mut_mesh.get_transformed_verts());
+        let enemies = screen.entities.iter().filter(|entity| entity.object_type == "enemy_tank").count();
+        destroy_enemies(&mut screen);
 
-            for face in &mut_mesh.faces {
-                let mut verts = Vec::new();
+        assert_eq!(destroyed.get(), 0, "nothing's delivered until the end of the frame");
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        assert_eq!(destroyed.get(), enemies);
+        assert!(!screen.complete.get(), "the ambush is still to come");
 
-                for vert_index in &face.vert_indices {
-                    verts.push(points_2d.get(*vert_index - 1).unwrap());
-                }
+        camera.position = Point3D { x: 20.0, y: 1.5, z: 18.0 };
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        let ambush = screen.entities.iter().filter(|entity| entity.object_type == "enemy_tank").count();
+        assert_eq!(ambush, 2);
 
-                let num_verts = verts.len();
+        destroy_enemies(&mut screen);
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        assert_eq!(destroyed.get(), enemies + ambush);
+        assert!(screen.complete.get());
+    }
 
-                let mut face_outside_viewport = true;
+    #[test]
+    /// # test_level_scripts_run
+    /// Level 1's script cheers the third kill, and the ambush's patrol script drives its tank
+    /// about, all without errors.
+    fn test_level_scripts_run() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::new();
 
-                // Ignore faces outside view (doesn't really work well)...
-                for i in 0..num_verts {
-                    let first_vert = verts.get(i).unwrap();
-                    let second_vert = if i == num_verts - 1 { verts.get(0).unwrap() } else {verts.get(i + 1).unwrap()} ;
+        let said = Rc::new(RefCell::new(Vec::new()));
+        let log = said.clone();
+        screen.bus().unwrap().subscribe(move |message: &HudMessage| log.borrow_mut().push(message.text.clone()));
+        let errors = Rc::new(RefCell::new(Vec::new()));
+        let log = errors.clone();
+        screen.bus().unwrap().subscribe(move |output: &ScriptOutput| log.borrow_mut().push(output.text.clone()));
+
+        for _ in 0..3 {
+            let index = screen.entities.iter().position(|entity| entity.object_type == "enemy_tank").unwrap();
+            screen.destroy(index);
+        }
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        assert_eq!(*said.borrow(), vec!["THREE DOWN"]);
 
-                    let start: Pos2 = [first_vert.x + viewport_width_div_2, first_vert.y + viewport_height_div_2].into();
-                    let end: Pos2 = [second_vert.x + viewport_width_div_2, second_vert.y + viewport_height_div_2].into();
+        camera.position = Point3D { x: 20.0, y: 1.5, z: 18.0 };
+        screen.simulate(&mut camera, &ActionState::default(), 1.0 / 60.0);
+        let start = screen.entities.iter().find(|entity| entity.id == "AmbushTank1").unwrap().mesh.position.clone();
 
-                    if  (start.x >= 0.0 && start.x <= viewport_width) || (start.y >= 0.0 && start.y <= viewport_height) ||
-                        (end.x >= 0.0 && end.x <= viewport_width) || (end.y >= 0.0 && end.y <= viewport_height) {
-                        face_outside_viewport = false;
-                    }
-                }
+        for _ in 0..30 {
+            screen.simulate(&mut camera, &ActionState::default(), 1.0 / 30.0);
+        }
 
-                if face_outside_viewport{
-                  //  continue;
-                }
+        let patrol = &screen.entities.iter().find(|entity| entity.id == "AmbushTank1").unwrap().mesh;
+        assert!(Vector3D::from(&Point3D { x: start.x, y: start.y, z: start.z }).subtract(&patrol.position).length() > 2.0, "{:?}", patrol.position);
+        assert!(said.borrow().contains(&"AMBUSH".to_string()));
+        assert!(errors.borrow().is_empty(), "{:?}", errors.borrow());
+    }
 
-                // Render faces
-                for i in 0..num_verts {
-                    let first_vert = verts.get(i).unwrap();
-                    let second_vert = if i == num_verts - 1 { verts.get(0).unwrap() } else {verts.get(i + 1).unwrap()} ;
+    #[test]
+    /// # test_level_commands
+    /// Spawning copies one of the level's objects, render modes and debug lines switch, and
+    /// reloading starts the same level again.
+    fn test_level_commands() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::with_map(LEVEL2_MAP, DepthCue::default());
+        let count = screen.entities.len();
 
-                    let start: Pos2 = [first_vert.x, first_vert.y].into();
-                    let end: Pos2 = [second_vert.x, second_vert.y].into();
+        assert!(screen.complete("spawn", 0).contains(&"enemy_tank".to_string()));
+        assert!(screen.run("spawn", &["enemy_tank", "5", "6"]).is_ok());
+        assert!(screen.run("spawn", &["ufo", "5", "6"]).is_err());
+        assert_eq!(screen.entities.len(), count + 1);
+        let spawned = &screen.entities[count].mesh.position;
+        assert_eq!((spawned.x, spawned.z), (5.0, 6.0));
 
-                    painter.line_segment(
-                        [start, end],
-                        Stroke::new(2.0, Color32::GREEN),
-                    );
-                }
+        assert!(screen.run("render", &["filled"]).is_ok());
+        assert_eq!(screen.render_mode, RenderMode::Filled);
+        assert!(screen.run("render", &["solid"]).is_err());
+
+        assert!(screen.run("debug", &["normals"]).unwrap().contains("normals on"));
+        assert!(screen.run("debug", &["normals", "off"]).unwrap().contains("normals off"));
+        assert!(screen.run("debug", &["vertices"]).is_err());
+
+        assert!(screen.run("reload", &[]).is_ok());
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), 1.0 / 60.0), Some(ScreenTransition::Map(LEVEL2_MAP)));
+    }
+
+    #[test]
+    /// # test_load_model_parts_missing_model
+    fn test_load_model_parts_missing_model() {
+        assert!(load_model_parts(&"npcs/TankBody.obj".to_string()).is_ok());
+        assert_eq!(load_model_parts(&"missing.obj".to_string()), Err("Failed to load missing.obj".to_string()));
+    }
+
+    #[test]
+    /// # test_reload_keeps_the_map
+    /// Reloading starts the level again from its own map, whichever map that is.
+    fn test_reload_keeps_the_map() {
+        let mut camera = start_camera();
+        let mut screen = Level1Screen::with_map(crate::screens::versus_screen::VERSUS_MAP, DepthCue::default());
+
+        assert!(screen.run("reload", &[]).is_ok());
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), 1.0 / 60.0), Some(ScreenTransition::Map(crate::screens::versus_screen::VERSUS_MAP)));
+    }
+
+    #[test]
+    /// # test_render_counts
+    /// A render is one view, drawing every mesh in the level.
+    fn test_render_counts() {
+        let camera = start_camera();
+        let screen = Level1Screen::new();
+
+        crate::utils::profiling::take_frame();
+        screen.render(&camera, &mut crate::rendering::recorder::Recorder::default());
+        let stats = crate::utils::profiling::take_frame();
+
+        assert_eq!(stats.count(Counter::Views), 1);
+        assert_eq!(stats.count(Counter::MeshesDrawn), screen.entities.len());
+    }
+
+    #[test]
+    /// # test_render_is_pure
+    /// Drawing the same frame twice, e.g. to export it, has to draw the same thing.
+    fn test_render_is_pure() {
+        let camera = start_camera();
+        let mut screen = Level1Screen::new();
+        screen.intro = None;
+        screen.update(&mut camera.clone(), &ActionState::default(), 0.5);
+
+        let mut first = crate::rendering::svg::SvgFrame::new(1024.0, 768.0, Color32::BLACK);
+        let mut second = crate::rendering::svg::SvgFrame::new(1024.0, 768.0, Color32::BLACK);
+        screen.render(&camera, &mut first);
+        screen.render(&camera, &mut second);
+
+        assert_eq!(first.to_string(), second.to_string());
+    }
+
+    #[test]
+    /// # test_vehicles_dont_spin
+    /// The level's entities spin with the clock but the vehicles passed in are drawn as they are.
+    fn test_vehicles_dont_spin() {
+        let camera = start_camera();
+        let mut screen = Level1Screen::new();
+        let mut tank = screen.entities.remove(1);
+        tank.mesh.position = Vector3D { x: 0.0, y: 0.0, z: 10.0 };
+        screen.entities.clear();
+
+        let draw = |screen: &Level1Screen, vehicles: &[&Entity]| {
+            let mut frame = crate::rendering::svg::SvgFrame::new(1024.0, 768.0, Color32::BLACK);
+            screen.render_scene(&camera, vehicles.iter().copied(), &mut frame);
+            frame.to_string()
+        };
+
+        let before = draw(&screen, &[&tank]);
+        screen.time += 0.5;
+        assert_eq!(draw(&screen, &[&tank]), before);
+
+        screen.entities.push(tank);
+        let before = draw(&screen, &[]);
+        screen.time += 0.5;
+        assert_ne!(draw(&screen, &[]), before);
+    }
+
+    #[test]
+    /// # test_replay_is_deterministic
+    fn test_replay_is_deterministic() {
+        let (first, _) = replay(Recording::load_asset("demos/attract.rec").unwrap());
+        let (second, _) = replay(Recording::load_asset("demos/attract.rec").unwrap());
+
+        assert_eq!(first.position, second.position);
+        assert_eq!(first.rotation_vector, second.rotation_vector);
+    }
+
+    #[test]
+    /// # test_replay_matches_recorded_session
+    /// Recording starts the level over, and replaying what was recorded ends up exactly where the
+    /// player did.
+    fn test_replay_matches_recorded_session() {
+        let mut camera = start_camera();
+        let mut record = ActionState::default();
+        record.set(Action::ToggleRecording, 1.0, true);
+
+        let mut level = Level1Screen::with_map(LEVEL2_MAP, DepthCue::default());
+        level.time = 12.0;
+        assert_eq!(level.update(&mut camera, &record, 1.0 / 60.0), Some(ScreenTransition::Record(LEVEL2_MAP)));
+
+        let mut screen = Level1Screen::with_recording(LEVEL2_MAP, &camera, DepthCue::default());
+        let mut forward = ActionState::default();
+        forward.set(Action::MoveForward, 1.0, false);
+        let mut strafe = ActionState::default();
+        strafe.set(Action::StrafeLeft, 1.0, false);
+        let mut fire = ActionState::default();
+        fire.set(Action::Fire, 1.0, true);
+
+        let session = [(&forward, 40), (&strafe, 25), (&fire, 1), (&forward, 30), (&fire, 1)];
+        for (actions, frames) in session {
+            for _ in 0..frames {
+                assert_eq!(screen.update(&mut camera, actions, 1.0 / 60.0), None);
             }
         }
+
+        let (replayed_camera, replayed) = replay(screen.recording.take().unwrap());
+
+        assert_eq!(replayed.map, LEVEL2_MAP);
+        assert_eq!(replayed.time, screen.time);
+        assert_eq!(replayed_camera.position, camera.position);
+        assert_eq!(replayed_camera.rotation_vector, camera.rotation_vector);
+        assert_eq!(replayed.player.map(|vehicle| vehicle.entity.mesh.position), screen.player.map(|vehicle| vehicle.entity.mesh.position));
+        assert_eq!(replayed.entities.len(), screen.entities.len());
+        assert_eq!(replayed.particles.count(), screen.particles.count());
     }
 
+    #[test]
+    /// # test_replay_stops_only_on_back
+    /// Any key stops the attract demo, but a `--replay` keeps going until Back.
+    fn test_replay_stops_only_on_back() {
+        let mut camera = start_camera();
+        let mut key = ActionState::default();
+        key.captured = Some(crate::input::bindings::Binding::Key(eframe::egui::Key::W));
+        let mut back = ActionState::default();
+        back.set(Action::Back, 1.0, true);
 
-}
+        let mut attract = Level1Screen::with_attract(Replay::new(Recording::load_asset("demos/attract.rec").unwrap()), DepthCue::default()).unwrap();
+        assert_eq!(attract.update(&mut camera, &key, 1.0 / 60.0), Some(ScreenTransition::Title));
 
+        let mut replay = Level1Screen::with_replay(Replay::new(Recording::load_asset("demos/attract.rec").unwrap()), DepthCue::default()).unwrap();
+        assert_eq!(replay.update(&mut camera, &key, 1.0 / 60.0), None);
+        assert_eq!(replay.update(&mut camera, &back, 1.0 / 60.0), Some(ScreenTransition::Title));
+    }
+
+    #[test]
+    /// # test_attract_demo_final_state
+    /// Regression test for the camera and movement code. If this changes on purpose, update the
+    /// expected position from a fresh run.
+    fn test_attract_demo_final_state() {
+        let (camera, _) = replay(Recording::load_asset("demos/attract.rec").unwrap());
+
+        let expected_position = Point3D { x: -11.850667, y: 1.5, z: 4.775123 };
+        let expected_rotation = Vector3D { x: -0.5869752, y: -0.052335765, z: -0.807902 };
+
+        let error = Vector3D::from(&camera.position).subtract(&Vector3D::from(&expected_position));
+
+        assert!(error.dot_product(&error).sqrt() < 1e-3, "position: {:?}", camera.position);
+        assert!(camera.rotation_vector.angle_to_other_vector(&expected_rotation) < 1e-3, "rotation: {:?}", camera.rotation_vector);
+    }
+}
@@ -0,0 +1,45 @@
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::TextStyle;
+use crate::screens::level1_screen::{LEVEL1_MAP, LEVEL2_MAP};
+use crate::screens::menu::Menu;
+use crate::screens::traits::{ScreenRenderer, ScreenTransition};
+
+pub struct LevelSelectScreen {
+    menu: Menu<ScreenTransition>,
+}
+
+impl LevelSelectScreen {
+    pub fn new() -> Self {
+        let menu = Menu::new(vec![
+            ("LEVEL 1", ScreenTransition::Map(LEVEL1_MAP)),
+            ("LEVEL 2", ScreenTransition::Map(LEVEL2_MAP)),
+            ("VERSUS", ScreenTransition::Versus),
+            ("BACK", ScreenTransition::Title),
+        ]);
+
+        Self { menu }
+    }
+}
+
+impl ScreenRenderer for LevelSelectScreen {
+    fn update(&mut self, camera: &mut Camera, actions: &ActionState, _delta_time: f32) -> Option<ScreenTransition> {
+        if actions.pressed(Action::Back) {
+            return Some(ScreenTransition::Title);
+        }
+
+        self.menu.update(actions, Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * 0.4))
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let mut style = TextStyle::new(40.0);
+        style.align = Align2::CENTER_TOP;
+
+        backend.text("SELECT LEVEL", Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * 0.15), &style, Stroke::new(3.0, Color32::GREEN));
+
+        self.menu.render(backend, Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * 0.4));
+    }
+}
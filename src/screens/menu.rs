@@ -0,0 +1,168 @@
+use eframe::egui::{Align2, Color32, Pos2, Rect, Stroke};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::{text_size, TextStyle};
+
+const ITEM_SIZE: f32 = 24.0;
+/// Distance between items as a multiple of the item size.
+const ITEM_SPACING: f32 = 44.0 / 24.0;
+
+/// # Menu
+/// A vertical list of stroke font items that can be driven with the menu actions (up, down and
+/// select) or the mouse (hover to select, click to pick).
+///
+/// Each item carries an action of type `T` that is handed back when the item is picked.
+pub struct Menu<T: Copy> {
+    pub items: Vec<(String, T)>,
+    pub selected: usize,
+    /// Height of the text, the items are spaced out to match.
+    pub item_size: f32,
+}
+
+impl<T: Copy> Menu<T> {
+    pub fn new(items: Vec<(&str, T)>) -> Self {
+        Self {
+            items: items.into_iter().map(|(label, action)| (label.to_string(), action)).collect(),
+            selected: 0,
+            item_size: ITEM_SIZE,
+        }
+    }
+
+    /// Smaller text for menus with a lot of items.
+    pub fn with_item_size(self, item_size: f32) -> Self {
+        Self { item_size, ..self }
+    }
+
+    /// # fit
+    /// Sizes the items so the whole menu is no taller than `height`, but no bigger than
+    /// `max_item_size`, for menus long enough to run off short windows.
+    pub fn fit(&mut self, height: f32, max_item_size: f32) {
+        let rows = self.items.len().saturating_sub(1) as f32 * ITEM_SPACING + 1.0;
+
+        self.item_size = (height / rows).min(max_item_size);
+    }
+
+    fn item_spacing(&self) -> f32 {
+        self.item_size * ITEM_SPACING
+    }
+
+    /// # item_rect
+    /// Screen rectangle for the item at `index` when the menu is centred on `top_centre`.
+    pub fn item_rect(&self, index: usize, top_centre: Pos2) -> Rect {
+        let size = text_size(&self.items[index].0, &TextStyle::new(self.item_size));
+        let centre = Pos2::new(top_centre.x, top_centre.y + index as f32 * self.item_spacing() + self.item_size / 2.0);
+
+        // Pad the hit box a little so it's not fiddly to click between the strokes.
+        Rect::from_center_size(centre, size).expand(6.0)
+    }
+
+    /// # update
+    /// Moves the selection in response to input and returns the action for the picked item, if one
+    /// was picked this frame.
+    pub fn update(&mut self, actions: &ActionState, top_centre: Pos2) -> Option<T> {
+        if self.items.is_empty() {
+            return None;
+        }
+
+        if actions.pressed(Action::MenuDown) {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+
+        if actions.pressed(Action::MenuUp) {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+
+        if actions.pressed(Action::MenuSelect) {
+            return Some(self.items[self.selected].1);
+        }
+
+        let hovered = actions.pointer
+            .and_then(|pointer| (0..self.items.len()).find(|&i| self.item_rect(i, top_centre).contains(pointer)));
+
+        if let Some(index) = hovered {
+            if actions.pointer_moved {
+                self.selected = index;
+            }
+
+            if actions.clicked {
+                self.selected = index;
+                return Some(self.items[index].1);
+            }
+        }
+
+        None
+    }
+
+    pub fn render(&self, backend: &mut dyn RenderBackend, top_centre: Pos2) {
+        let mut style = TextStyle::new(self.item_size);
+        style.align = Align2::CENTER_TOP;
+
+        for (index, (label, _)) in self.items.iter().enumerate() {
+            let position = Pos2::new(top_centre.x, top_centre.y + index as f32 * self.item_spacing());
+
+            let stroke = if index == self.selected {
+                Stroke::new(2.5, Color32::GREEN)
+            } else {
+                Stroke::new(1.5, Color32::DARK_GREEN)
+            };
+
+            backend.text(label, position, &style, stroke);
+
+            if index == self.selected {
+                let rect = self.item_rect(index, top_centre);
+                let mut marker = TextStyle::new(self.item_size);
+                marker.align = Align2::RIGHT_TOP;
+                backend.text(">", Pos2::new(rect.left() - 8.0, position.y), &marker, stroke);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Item { Start, Quit }
+
+    fn pressed(action: Action) -> ActionState {
+        let mut actions = ActionState::default();
+        actions.set(action, 1.0, true);
+        actions
+    }
+
+    #[test]
+    /// # test_menu_wraps_selection
+    fn test_menu_wraps_selection() {
+        let mut menu = Menu::new(vec![("START", Item::Start), ("QUIT", Item::Quit)]);
+
+        menu.update(&pressed(Action::MenuUp), Pos2::ZERO);
+        assert_eq!(menu.selected, 1);
+
+        menu.update(&pressed(Action::MenuDown), Pos2::ZERO);
+        assert_eq!(menu.selected, 0);
+    }
+
+    #[test]
+    /// # test_menu_enter_picks_selected
+    fn test_menu_enter_picks_selected() {
+        let mut menu = Menu::new(vec![("START", Item::Start), ("QUIT", Item::Quit)]);
+        menu.selected = 1;
+
+        assert_eq!(menu.update(&pressed(Action::MenuSelect), Pos2::ZERO), Some(Item::Quit));
+    }
+
+    #[test]
+    /// # test_menu_click_picks_hovered
+    fn test_menu_click_picks_hovered() {
+        let mut menu = Menu::new(vec![("START", Item::Start), ("QUIT", Item::Quit)]);
+
+        let mut actions = ActionState::default();
+        actions.pointer = Some(menu.item_rect(1, Pos2::ZERO).center());
+        actions.clicked = true;
+
+        assert_eq!(menu.update(&actions, Pos2::ZERO), Some(Item::Quit));
+    }
+}
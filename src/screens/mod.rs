@@ -1,4 +1,10 @@
 pub mod huds;
 pub mod traits;
+pub mod menu;
 pub mod title_screen;
+pub mod level_select_screen;
+pub mod options_screen;
 pub mod level1_screen;
+pub mod versus_screen;
+pub mod events;
+pub mod triggers;
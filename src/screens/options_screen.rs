@@ -0,0 +1,209 @@
+use eframe::egui::{Align2, Color32, Key, Pos2, Stroke};
+use crate::input::actions::ActionState;
+use crate::input::bindings::{Action, Binding, InputBindings, BINDINGS_FILE};
+use crate::player::camera::Camera;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::crt::CrtEffect;
+use crate::rendering::stroke_font::TextStyle;
+use crate::screens::menu::Menu;
+use crate::screens::traits::{ScreenRenderer, ScreenTransition};
+use crate::log_error;
+use crate::utils::settings::{Settings, SETTINGS_FILE};
+
+/// Height of the menu's text when there's room for it.
+const ITEM_SIZE: f32 = 16.0;
+
+/// Where the menu starts, as a fraction of the window's height, below the title.
+const MENU_TOP: f32 = 0.2;
+
+/// Room kept under the menu for the line saying the options weren't saved.
+const MENU_BOTTOM_MARGIN: f32 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OptionsItem {
+    Rebind(Action),
+    ToggleCrt(CrtEffect),
+    ResetDefaults,
+    Back,
+}
+
+/// # OptionsScreen
+/// Lets the player rebind their controls and switch the CRT effects on and off. Picking an action
+/// waits for the next key or mouse button and adds it to the action (up to two per action, the
+/// oldest is replaced). Backspace clears the selected action. Changes are saved straight away,
+/// and the game takes them from the screen when it's left so they apply even if saving failed.
+///
+/// Reset defaults only resets the bindings.
+pub struct OptionsScreen {
+    menu: Menu<OptionsItem>,
+    bindings: InputBindings,
+    settings: Settings,
+    /// The action waiting for a key or button to be pressed.
+    capturing: Option<Action>,
+    /// Why the last change couldn't be saved.
+    error: Option<String>,
+}
+
+impl OptionsScreen {
+    pub fn new() -> Self {
+        let mut items: Vec<(&str, OptionsItem)> = Action::REBINDABLE
+            .iter()
+            .map(|action| ("", OptionsItem::Rebind(*action)))
+            .collect();
+        items.extend(CrtEffect::ALL.iter().map(|effect| ("", OptionsItem::ToggleCrt(*effect))));
+        items.push(("RESET DEFAULTS", OptionsItem::ResetDefaults));
+        items.push(("BACK", OptionsItem::Back));
+
+        let mut screen = Self {
+            menu: Menu::new(items).with_item_size(ITEM_SIZE),
+            bindings: InputBindings::load_or_default(BINDINGS_FILE),
+            settings: Settings::load_or_default(SETTINGS_FILE),
+            capturing: None,
+            error: None,
+        };
+        screen.update_labels();
+
+        screen
+    }
+
+    fn update_labels(&mut self) {
+        for (label, item) in &mut self.menu.items {
+            match item {
+                OptionsItem::Rebind(action) => {
+                    let inputs: Vec<String> = self.bindings.bindings(*action).iter().map(|b| b.input.label()).collect();
+                    let inputs = if self.capturing == Some(*action) { "PRESS A KEY".to_string() } else { inputs.join(", ") };
+
+                    *label = format!("{}: {}", action.label(), inputs);
+                }
+                OptionsItem::ToggleCrt(effect) => {
+                    let state = if self.settings.crt.enabled(*effect) { "ON" } else { "OFF" };
+
+                    *label = format!("{}: {}", effect.label(), state);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Saves the changes. Failing to isn't fatal, they still apply until the game is closed, but
+    /// the player's told.
+    fn save(&mut self) {
+        let saved = self.bindings.save(BINDINGS_FILE).and_then(|_| self.settings.save(SETTINGS_FILE));
+
+        self.error = saved.err().map(|error| {
+            log_error!("Failed to save options: {}", error);
+            format!("NOT SAVED: {}", error).to_uppercase()
+        });
+        self.update_labels();
+    }
+
+    fn menu_position(camera: &Camera) -> Pos2 {
+        Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * MENU_TOP)
+    }
+
+    /// There are too many items to fit a short window at full size, so they shrink to fit.
+    fn fit_menu(&mut self, camera: &Camera) {
+        let height = camera.viewport.height * (1.0 - MENU_TOP) - MENU_BOTTOM_MARGIN;
+
+        self.menu.fit(height, ITEM_SIZE);
+    }
+}
+
+impl ScreenRenderer for OptionsScreen {
+    fn update(&mut self, camera: &mut Camera, actions: &ActionState, _delta_time: f32) -> Option<ScreenTransition> {
+        self.fit_menu(camera);
+
+        if let Some(action) = self.capturing {
+            if let Some(binding) = actions.captured {
+                // Escape backs out without changing anything
+                if binding != Binding::Key(Key::Escape) {
+                    self.bindings.bind(action, binding);
+                }
+
+                self.capturing = None;
+                self.save();
+            }
+
+            return None;
+        }
+
+        if actions.pressed(Action::Back) {
+            return Some(ScreenTransition::Title);
+        }
+
+        if let (Some(Binding::Key(Key::Backspace)), OptionsItem::Rebind(action)) = (actions.captured, self.menu.items[self.menu.selected].1) {
+            self.bindings.clear(action);
+            self.save();
+        }
+
+        match self.menu.update(actions, Self::menu_position(camera)) {
+            Some(OptionsItem::Rebind(action)) => {
+                self.capturing = Some(action);
+                self.update_labels();
+                None
+            }
+            Some(OptionsItem::ToggleCrt(effect)) => {
+                self.settings.crt.toggle(effect);
+                self.save();
+                None
+            }
+            Some(OptionsItem::ResetDefaults) => {
+                self.bindings = InputBindings::default();
+                self.save();
+                None
+            }
+            Some(OptionsItem::Back) => Some(ScreenTransition::Title),
+            None => None,
+        }
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let mut style = TextStyle::new(40.0);
+        style.align = Align2::CENTER_TOP;
+
+        backend.text("OPTIONS", Pos2::new(camera.viewport.width / 2.0, camera.viewport.height * 0.06), &style, Stroke::new(3.0, Color32::GREEN));
+
+        self.menu.render(backend, Self::menu_position(camera));
+
+        if let Some(error) = &self.error {
+            let mut style = TextStyle::new(12.0);
+            style.align = Align2::CENTER_BOTTOM;
+
+            backend.text(error, Pos2::new(camera.viewport.width / 2.0, camera.viewport.height - 40.0), &style, Stroke::new(1.0, Color32::RED));
+        }
+    }
+
+    fn edited(&self) -> Option<(&InputBindings, &Settings)> {
+        Some((&self.bindings, &self.settings))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::geometry::{Point3D, Rectangle};
+
+    #[test]
+    /// # test_menu_fits_the_window
+    /// Every item, down to BACK, is on screen and clear of the line saying the options weren't
+    /// saved.
+    fn test_menu_fits_the_window() {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        camera.update_viewport_size(Rectangle { width: 1024.0, height: 768.0 });
+
+        let mut screen = OptionsScreen::new();
+        screen.update(&mut camera, &ActionState::default(), 1.0 / 60.0);
+
+        let top_centre = OptionsScreen::menu_position(&camera);
+        let error_line_top = camera.viewport.height - 40.0 - 12.0;
+
+        for index in 0..screen.menu.items.len() {
+            let rect = screen.menu.item_rect(index, top_centre);
+
+            assert!(rect.top() >= 0.0 && rect.bottom() <= error_line_top, "{} at {:?}", screen.menu.items[index].0, rect);
+        }
+
+        assert_eq!(screen.menu.items.last().unwrap().1, OptionsItem::Back);
+    }
+}
@@ -1,23 +1,143 @@
-//TODO: Code for the title screen
-use eframe::egui;
-use eframe::egui::{Align2, Color32, FontId, Painter, Pos2};
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::objects::mesh::Mesh;
 use crate::player::camera::Camera;
-use crate::screens::traits::ScreenRenderer;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::stroke_font::{draw_text_3d, TextStyle, WorldTextPlacement};
+use crate::rendering::wireframe::draw_wireframe;
+use crate::screens::level1_screen::{load_model_faces, load_model_verts, LEVEL1_MAP};
+use crate::screens::menu::Menu;
+use crate::screens::traits::{ScreenRenderer, ScreenTransition};
+use crate::types::geometry::*;
 
-pub struct TitleScreen;
+/// Seconds without any input before the attract mode demo kicks in.
+const ATTRACT_MODE_DELAY: f32 = 20.0;
+
+/// Radians per second the title model turns.
+const MODEL_SPIN_SPEED: f32 = 0.6;
+
+pub struct TitleScreen {
+    camera: Camera,
+    model: Mesh,
+    menu: Menu<ScreenTransition>,
+    idle_time: f32,
+}
 
 impl TitleScreen {
     pub fn new() -> Self {
-        Self
+        // The title screen has its own fixed camera looking at the model so it doesn't matter
+        // where the player was left.
+        let camera = Camera::new(
+            Point3D { x: 0.0, y: 5.0, z: -25.0 },
+            EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+            Vector3D { x: 0.0, y: 0.0, z: 1.0 },
+            90.0,
+            Rectangle { width: 1024.0, height: 768.0 },
+            1000.0,
+        );
+
+        let model_name = "npcs/TankBody.obj".to_string();
+
+        let model = Mesh {
+            verts: load_model_verts(&model_name),
+            faces: load_model_faces(&model_name),
+            position: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        };
+
+        let menu = Menu::new(vec![
+            ("START", ScreenTransition::Map(LEVEL1_MAP)),
+            ("LEVEL SELECT", ScreenTransition::LevelSelect),
+            ("OPTIONS", ScreenTransition::Options),
+            ("QUIT", ScreenTransition::Quit),
+        ]);
+
+        Self { camera, model, menu, idle_time: 0.0 }
+    }
+
+    fn menu_position(&self) -> Pos2 {
+        Pos2::new(self.camera.viewport.width / 2.0, self.camera.viewport.height * 0.68)
     }
 }
 
 impl ScreenRenderer for TitleScreen {
-    fn render(&self, camera: &Camera, painter: &Painter) {
-        //TODO: 
+    fn update(&mut self, camera: &mut Camera, actions: &ActionState, delta_time: f32) -> Option<ScreenTransition> {
+        self.camera.update_viewport_size(camera.viewport.clone());
+
+        self.model.rotation.yaw += MODEL_SPIN_SPEED * delta_time;
+
+        self.idle_time = if actions.any_activity() { 0.0 } else { self.idle_time + delta_time };
+
+        if self.idle_time > ATTRACT_MODE_DELAY {
+            return Some(ScreenTransition::AttractDemo);
+        }
+
+        if actions.pressed(Action::Back) {
+            return Some(ScreenTransition::Quit);
+        }
+
+        let menu_position = self.menu_position();
+        self.menu.update(actions, menu_position)
+    }
+
+    fn render(&self, _camera: &Camera, backend: &mut dyn RenderBackend) {
+        draw_wireframe(backend, &self.camera, &self.model, Stroke::new(1.0, Color32::GREEN));
+
+        let mut style = TextStyle::new(3.0);
+        style.align = Align2::CENTER_BOTTOM;
+        style.skew = 0.2;
+
+        let placement = WorldTextPlacement {
+            position: Point3D { x: 0.0, y: 9.0, z: 0.0 },
+            right: Vector3D { x: -1.0, y: 0.0, z: 0.0 },
+            up: Vector3D { x: 0.0, y: 1.0, z: 0.0 },
+        };
+
+        draw_text_3d(backend, &self.camera, "VECTAR", &placement, &style, Stroke::new(3.0, Color32::GREEN));
+
+        self.menu.render(backend, self.menu_position());
     }
 }
 
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pressed(action: Action) -> ActionState {
+        let mut actions = ActionState::default();
+        actions.set(action, 1.0, true);
+        actions
+    }
 
+    #[test]
+    /// # test_attract_demo_after_idling
+    fn test_attract_demo_after_idling() {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        let mut screen = TitleScreen::new();
 
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), ATTRACT_MODE_DELAY - 1.0), None);
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), 2.0), Some(ScreenTransition::AttractDemo));
+    }
+
+    #[test]
+    /// # test_input_resets_idle_time
+    fn test_input_resets_idle_time() {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        let mut screen = TitleScreen::new();
+
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), ATTRACT_MODE_DELAY - 1.0), None);
+        assert_eq!(screen.update(&mut camera, &pressed(Action::MenuDown), 0.5), None);
+        assert_eq!(screen.update(&mut camera, &ActionState::default(), 2.0), None);
+    }
+
+    #[test]
+    /// # test_back_quits
+    fn test_back_quits() {
+        let mut camera = Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 1000.0);
+        let mut screen = TitleScreen::new();
+
+        assert_eq!(screen.update(&mut camera, &pressed(Action::Back), 1.0 / 60.0), Some(ScreenTransition::Quit));
+    }
+}
@@ -1,10 +1,99 @@
-use eframe::egui::Painter;
+use crate::input::actions::ActionState;
+use crate::input::bindings::InputBindings;
+use crate::objects::entity::Entity;
 use crate::player::camera::Camera;
+use crate::console::commands::CommandHandler;
+use crate::rendering::background::Background;
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::viewport::View;
+use crate::types::geometry::Rectangle;
+use crate::utils::message_bus::MessageBus;
+use crate::utils::settings::Settings;
 
 pub trait HudRenderer {
-    fn render(&self, camera: &Camera, painter: &Painter);
+    /// Advance any animated parts of the HUD. Called once per frame before `render`.
+    fn update(&mut self, _camera: &Camera, _entities: &[Entity], _delta_time: f32) {}
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend);
+
+    /// Subscribes to the events the HUD shows from the screen's message bus.
+    fn connect(&mut self, _bus: &mut MessageBus) {}
+
+    /// Undoes `connect`, before the screen goes away.
+    fn disconnect(&mut self, _bus: &mut MessageBus) {}
 }
 
 pub trait ScreenRenderer {
-    fn render(&self, camera: &Camera, painter: &Painter);
-}
\ No newline at end of file
+    /// Handle input and advance the screen by `delta_time` seconds. Called once per frame before
+    /// `render`. Return a transition to have the game switch to another screen.
+    fn update(&mut self, _camera: &mut Camera, _actions: &ActionState, _delta_time: f32) -> Option<ScreenTransition> {
+        None
+    }
+
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend);
+
+    /// The entities currently in the scene, for systems (radar etc.) that need to know about them.
+    fn entities(&self) -> &[Entity] {
+        &[]
+    }
+
+    /// Far away scenery drawn with the game camera before the screen itself.
+    fn background(&self) -> Option<&Background> {
+        None
+    }
+
+    /// Where the screen publishes what happens on it, if it does.
+    fn bus(&mut self) -> Option<&mut MessageBus> {
+        None
+    }
+
+    /// Whether the game's view layout (split screen, rear view etc.) applies to the screen. Only
+    /// worth it for screens that are a 3D scene to look around.
+    fn uses_layout(&self) -> bool {
+        false
+    }
+
+    /// # views
+    /// The screen's own views of `window`, for screens that look at the scene through cameras of
+    /// their own, such as split screen. The game draws these instead of its layout, then its HUD
+    /// over the whole window.
+    fn views(&self, _window: &Rectangle) -> Option<Vec<View>> {
+        None
+    }
+
+    /// Draws what's only for the screen's own view at `index`, such as that player's HUD, over
+    /// the view.
+    fn render_view_hud(&self, _index: usize, _camera: &Camera, _backend: &mut dyn RenderBackend) {}
+
+    /// Draws what goes over the whole window once the screen's own views have been drawn, such
+    /// as the score between split screen halves.
+    fn render_over_views(&self, _camera: &Camera, _backend: &mut dyn RenderBackend) {}
+
+    /// The bindings and settings as the screen has left them, for screens that change them. The
+    /// game takes these rather than reading the files again, which may not have been saved.
+    fn edited(&self) -> Option<(&InputBindings, &Settings)> {
+        None
+    }
+
+    /// The screen's own console commands, if it has any.
+    fn commands(&mut self) -> Option<&mut dyn CommandHandler> {
+        None
+    }
+}
+
+/// Where a screen wants the game to go next.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScreenTransition {
+    Title,
+    /// A level from its map file under `assets/`, e.g. to start the current one again.
+    Map(&'static str),
+    /// A level started over with the player's input being recorded, so the recording replays
+    /// from the same state.
+    Record(&'static str),
+    /// Two players against each other on one keyboard.
+    Versus,
+    LevelSelect,
+    Options,
+    AttractDemo,
+    Quit,
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+use crate::objects::entity::Entity;
+use crate::screens::events::{HudMessage, LevelComplete, SpawnWave, TriggerFired};
+use crate::types::geometry::Vector3D;
+use crate::utils::message_bus::MessageBus;
+
+/// # Condition
+/// What sets a trigger off, checked every tick.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// Something comes within `radius` of (`x`, `z`) across the ground. That's the player, or any
+    /// entity of `object_type` if it's set.
+    Enter {
+        x: f32,
+        z: f32,
+        radius: f32,
+        #[serde(default)]
+        object_type: Option<String>,
+    },
+    /// None of the entities with an object type starting with `prefix` are left.
+    AllDestroyed {
+        #[serde(default = "default_prefix")]
+        prefix: String,
+    },
+    /// `seconds` into the level.
+    After { seconds: f32 },
+}
+
+fn default_prefix() -> String {
+    "enemy".to_string()
+}
+
+/// # TriggerAction
+/// What a trigger does, as events published on the level's bus.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TriggerAction {
+    /// Brings in one of the level's waves of entities.
+    Spawn { wave: String },
+    /// Shows some text on the HUD.
+    Message { text: String },
+    LevelComplete,
+}
+
+/// # Trigger
+/// Does `then` the first time `when` holds, once every trigger in `requires` has gone off.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Trigger {
+    pub id: String,
+    pub when: Condition,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    pub then: Vec<TriggerAction>,
+}
+
+/// # Triggers
+/// A level's triggers and which of them have gone off. Each goes off once at most.
+#[derive(Debug, Clone)]
+pub struct Triggers {
+    triggers: Vec<Trigger>,
+    fired: Vec<bool>,
+}
+
+impl Triggers {
+    /// Fails if a trigger requires one that doesn't exist, so a typo can't leave it never firing.
+    pub fn new(triggers: Vec<Trigger>) -> Result<Self, String> {
+        for trigger in &triggers {
+            if let Some(missing) = trigger.requires.iter().find(|id| !triggers.iter().any(|other| &other.id == *id)) {
+                return Err(format!("Trigger {} requires unknown trigger {}", trigger.id, missing));
+            }
+        }
+
+        let fired = vec![false; triggers.len()];
+
+        Ok(Self { triggers, fired })
+    }
+
+    /// Whether there are no triggers at all.
+    pub fn is_empty(&self) -> bool {
+        self.triggers.is_empty()
+    }
+
+    /// Every wave the triggers spawn.
+    pub fn waves(&self) -> impl Iterator<Item = &str> {
+        self.triggers
+            .iter()
+            .flat_map(|trigger| &trigger.then)
+            .filter_map(|action| match action {
+                TriggerAction::Spawn { wave } => Some(wave.as_str()),
+                _ => None,
+            })
+    }
+
+    /// # update
+    /// Checks the triggers against the level `time` seconds in, with the player at `player`, and
+    /// queues a `TriggerFired` then the actions' events on the bus for each that goes off.
+    /// Triggers only see the ones that went off before them in the list on the same tick.
+    pub fn update(&mut self, time: f32, player: &Vector3D, entities: &[Entity], bus: &mut MessageBus) {
+        for index in 0..self.triggers.len() {
+            let trigger = &self.triggers[index];
+
+            if self.fired[index] || !self.requirements_met(trigger) || !Self::holds(&trigger.when, time, player, entities) {
+                continue;
+            }
+
+            self.fired[index] = true;
+            bus.queue(TriggerFired { id: trigger.id.clone() });
+
+            for action in &trigger.then {
+                match action {
+                    TriggerAction::Spawn { wave } => bus.queue(SpawnWave { wave: wave.clone() }),
+                    TriggerAction::Message { text } => bus.queue(HudMessage { text: text.clone() }),
+                    TriggerAction::LevelComplete => bus.queue(LevelComplete),
+                }
+            }
+        }
+    }
+
+    fn requirements_met(&self, trigger: &Trigger) -> bool {
+        trigger.requires
+            .iter()
+            .all(|id| self.triggers.iter().zip(&self.fired).any(|(other, fired)| &other.id == id && *fired))
+    }
+
+    fn holds(condition: &Condition, time: f32, player: &Vector3D, entities: &[Entity]) -> bool {
+        match condition {
+            Condition::Enter { x, z, radius, object_type } => {
+                let inside = |position: &Vector3D| (position.x - x).hypot(position.z - z) <= *radius;
+
+                match object_type {
+                    Some(object_type) => entities
+                        .iter()
+                        .any(|entity| &entity.object_type == object_type && inside(&entity.mesh.position)),
+                    None => inside(player),
+                }
+            }
+            Condition::AllDestroyed { prefix } => !entities.iter().any(|entity| entity.object_type.starts_with(prefix.as_str())),
+            Condition::After { seconds } => time >= *seconds,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::objects::mesh::Mesh;
+    use crate::types::geometry::EulerAngles;
+
+    fn enemy_at(x: f32, z: f32) -> Entity {
+        Entity::new(
+            "Tank".to_string(),
+            "enemy_tank".to_string(),
+            Mesh {
+                verts: vec![],
+                faces: vec![],
+                position: Vector3D { x, y: 0.0, z },
+                rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+            },
+        )
+    }
+
+    fn mission() -> Triggers {
+        let triggers: Vec<Trigger> = serde_json::from_str(r#"[
+            { "id": "ambush", "when": { "type": "enter", "x": 10.0, "z": 0.0, "radius": 2.0 },
+              "then": [{ "type": "spawn", "wave": "ambush" }, { "type": "message", "text": "AMBUSH" }] },
+            { "id": "won", "requires": ["ambush"], "when": { "type": "all_destroyed" }, "then": [{ "type": "level_complete" }] },
+            { "id": "hurry", "when": { "type": "after", "seconds": 30.0 }, "then": [{ "type": "message", "text": "HURRY UP" }] }
+        ]"#).unwrap();
+
+        Triggers::new(triggers).unwrap()
+    }
+
+    /// Subscribes to what the triggers publish, written down in order.
+    fn listen(bus: &mut MessageBus) -> Rc<RefCell<Vec<String>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+
+        let log = events.clone();
+        bus.subscribe(move |fired: &TriggerFired| log.borrow_mut().push(fired.id.clone()));
+        let log = events.clone();
+        bus.subscribe(move |spawn: &SpawnWave| log.borrow_mut().push(format!("spawn {}", spawn.wave)));
+        let log = events.clone();
+        bus.subscribe(move |message: &HudMessage| log.borrow_mut().push(message.text.clone()));
+        let log = events.clone();
+        bus.subscribe(move |_: &LevelComplete| log.borrow_mut().push("complete".to_string()));
+
+        events
+    }
+
+    #[test]
+    /// # test_triggers_fire_once_in_order
+    /// Entering the region spawns the wave, the level is only complete once the wave is in and
+    /// beaten, and nothing goes off twice.
+    fn test_triggers_fire_once_in_order() {
+        let mut triggers = mission();
+        let mut bus = MessageBus::new();
+        let events = listen(&mut bus);
+        let outside = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+        let inside = Vector3D { x: 9.0, y: 5.0, z: 1.0 };
+
+        // No enemies, but the ambush hasn't happened yet
+        triggers.update(1.0, &outside, &[], &mut bus);
+        bus.flush();
+        assert!(events.borrow().is_empty());
+
+        triggers.update(2.0, &inside, &[enemy_at(0.0, 0.0)], &mut bus);
+        triggers.update(3.0, &inside, &[enemy_at(0.0, 0.0)], &mut bus);
+        bus.flush();
+        assert_eq!(*events.borrow(), vec!["ambush", "spawn ambush", "AMBUSH"]);
+
+        triggers.update(31.0, &inside, &[], &mut bus);
+        triggers.update(32.0, &inside, &[], &mut bus);
+        bus.flush();
+        assert_eq!(events.borrow()[3..], ["won", "complete", "hurry", "HURRY UP"]);
+    }
+
+    #[test]
+    /// # test_enter_by_object_type
+    fn test_enter_by_object_type() {
+        let trigger = Trigger {
+            id: "arrived".to_string(),
+            when: Condition::Enter { x: 0.0, z: 0.0, radius: 5.0, object_type: Some("enemy_tank".to_string()) },
+            requires: vec![],
+            then: vec![TriggerAction::LevelComplete],
+        };
+        let mut triggers = Triggers::new(vec![trigger]).unwrap();
+        let mut bus = MessageBus::new();
+        let events = listen(&mut bus);
+        let player = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+
+        triggers.update(0.0, &player, &[enemy_at(10.0, 0.0)], &mut bus);
+        bus.flush();
+        assert!(events.borrow().is_empty(), "the player being there doesn't count");
+
+        triggers.update(1.0, &player, &[enemy_at(4.0, 0.0)], &mut bus);
+        bus.flush();
+        assert_eq!(*events.borrow(), vec!["arrived", "complete"]);
+    }
+
+    #[test]
+    /// # test_unknown_requirement
+    fn test_unknown_requirement() {
+        let trigger = Trigger {
+            id: "won".to_string(),
+            when: Condition::After { seconds: 0.0 },
+            requires: vec!["typo".to_string()],
+            then: vec![],
+        };
+
+        assert!(Triggers::new(vec![trigger]).is_err());
+    }
+}
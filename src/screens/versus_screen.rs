@@ -0,0 +1,517 @@
+use std::mem;
+use eframe::egui::{Align2, Color32, Pos2, Stroke};
+use crate::input::actions::ActionState;
+use crate::input::bindings::Action;
+use crate::objects::entity::Entity;
+use crate::objects::vehicle::Vehicle;
+use crate::player::camera::Camera;
+use crate::player::controllers::{ChaseController, Target};
+use crate::rendering::backend::RenderBackend;
+use crate::rendering::depth_cue::DepthCue;
+use crate::rendering::stroke_font::TextStyle;
+use crate::rendering::background::Background;
+use crate::rendering::viewport::View;
+use crate::screens::events::{EntityDestroyed, HudMessage};
+use crate::screens::huds::PlayerHud;
+use crate::screens::level1_screen::{load_assets, Level1Screen, LevelAssets};
+use crate::screens::traits::{HudRenderer, ScreenRenderer, ScreenTransition};
+use crate::types::geometry::{Rectangle, Vector3D};
+use crate::utils::message_bus::MessageBus;
+
+/// The versus map under `assets/`, which has a starting place for each player.
+pub const VERSUS_MAP: &str = "maps/versus.json";
+
+/// Rounds a player has to win to win the match.
+pub const ROUNDS_TO_WIN: u32 = 3;
+
+/// Hits a tank takes before it's destroyed.
+pub const ARMOUR: u32 = 3;
+
+/// Seconds after firing before the gun's loaded again.
+const RELOAD_TIME: f32 = 1.0;
+
+/// How far a shot goes, in world units.
+const SHOT_RANGE: f32 = 150.0;
+
+/// Seconds between a round being won and the next starting.
+const ROUND_OVER_TIME: f32 = 3.0;
+
+/// # Player
+/// One of the players: their tank, the camera chasing it and their own HUD.
+struct Player {
+    vehicle: Vehicle,
+    /// The tank as it is at the start of every round.
+    start: Vehicle,
+    camera: Camera,
+    chase: ChaseController,
+    hud: PlayerHud,
+    armour: u32,
+    /// Seconds until the gun's loaded.
+    reload: f32,
+    /// Rounds won this match.
+    score: u32,
+}
+
+impl Player {
+    fn new(vehicle: Vehicle, camera: &Camera, name: &str) -> Self {
+        Self {
+            start: vehicle.clone(),
+            vehicle,
+            camera: camera.clone(),
+            chase: ChaseController::default(),
+            hud: PlayerHud::new(name, ARMOUR),
+            armour: ARMOUR,
+            reload: 0.0,
+            score: 0,
+        }
+    }
+
+    /// Back where they started for a new round, keeping their score.
+    fn reset(&mut self) {
+        self.vehicle = self.start.clone();
+        self.chase.release();
+        self.armour = ARMOUR;
+        self.reload = 0.0;
+    }
+
+    fn destroyed(&self) -> bool {
+        self.armour == 0
+    }
+}
+
+/// Where the match is up to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Round {
+    Playing,
+    /// Won or drawn, with the seconds left until the next round starts.
+    Over(f32),
+    /// Someone's won enough rounds. It stays like this until either player fires to play again.
+    MatchOver,
+}
+
+/// # VersusScreen
+/// Two players sharing the keyboard, each driving a tank round the same map in their own half of
+/// the window. A tank's destroyed after `ARMOUR` hits, winning the round for the other player,
+/// and the first to win `ROUNDS_TO_WIN` rounds wins the match.
+///
+/// The players' keys come from their own bindings, see `ActionState::player`.
+pub struct VersusScreen {
+    /// The map, which carries on as usual around the players.
+    level: Level1Screen,
+    players: [Player; 2],
+    round: Round,
+    /// Which round it is, from 1.
+    round_number: u32,
+    /// Where the rounds' messages go, for the HUD.
+    bus: MessageBus,
+}
+
+impl VersusScreen {
+    /// A match on the versus map, the players' cameras starting as copies of the game's.
+    pub fn new(camera: &Camera, depth_cue: DepthCue) -> Self {
+        let mut assets = load_assets(VERSUS_MAP).expect("Failed to load versus map");
+        unscripted(&assets).expect("The versus map can't have triggers or scripts");
+        let [one, two]: [Vehicle; 2] = mem::take(&mut assets.players).try_into().expect("The versus map needs two players");
+
+        let mut bus = MessageBus::new();
+        bus.queue(HudMessage { text: "ROUND 1".to_string() });
+
+        Self {
+            level: Level1Screen::from_assets(VERSUS_MAP, assets, depth_cue),
+            players: [Player::new(one, camera, "PLAYER 1"), Player::new(two, camera, "PLAYER 2")],
+            round: Round::Playing,
+            round_number: 1,
+            bus,
+        }
+    }
+
+    /// # play
+    /// Drives both tanks for a frame, then fires the shots. Everyone's moved before anything's
+    /// hit, so player 1 doesn't get to shoot first.
+    fn play(&mut self, actions: &ActionState, delta_time: f32) {
+        let mut shots = Vec::new();
+
+        for index in 0..2 {
+            let actions = actions.player(index);
+            let (player, other) = pair(&mut self.players, index);
+            let level = &self.level;
+
+            player.vehicle.update(&actions, delta_time, |x, z| level.height_at(x, z), level.entities.iter().chain([&other.vehicle.entity]));
+            player.reload = (player.reload - delta_time).max(0.0);
+
+            if actions.pressed(Action::Fire) && player.reload == 0.0 {
+                player.reload = RELOAD_TIME;
+                shots.push(index);
+            }
+        }
+
+        for index in shots {
+            self.fire(index);
+        }
+
+        if self.players.iter().any(Player::destroyed) {
+            self.end_round();
+        }
+    }
+
+    /// # fire
+    /// A shot from the player's tank straight ahead across the ground. The turret stays facing
+    /// forward in versus, there's no mouse each to aim it with. It hits the other tank if nothing
+    /// in the map is in the way first.
+    fn fire(&mut self, index: usize) {
+        let (player, other) = pair(&mut self.players, index);
+        let (from, direction) = (&player.vehicle.entity.mesh.position, player.vehicle.forward());
+
+        let blocked = self.level.entities
+            .iter()
+            .filter_map(|entity| hit_distance(from, &direction, entity))
+            .fold(SHOT_RANGE, f32::min);
+
+        if other.destroyed() || !hit_distance(from, &direction, &other.vehicle.entity).is_some_and(|distance| distance < blocked) {
+            return;
+        }
+
+        other.armour -= 1;
+        self.bus.queue(HudMessage { text: format!("{} HIT", other.hud.name) });
+
+        if other.destroyed() {
+            let entity = &other.vehicle.entity;
+
+            self.bus.queue(EntityDestroyed { id: entity.id.clone(), object_type: entity.object_type.clone(), position: entity.mesh.position.clone() });
+            self.level.explode(entity);
+        }
+    }
+
+    /// Scores the round for whoever's still standing, if anyone is, and decides whether that's
+    /// the match.
+    fn end_round(&mut self) {
+        let winner = match self.players.each_ref().map(Player::destroyed) {
+            [false, true] => Some(0),
+            [true, false] => Some(1),
+            _ => None,
+        };
+
+        let Some(winner) = winner else {
+            self.bus.queue(HudMessage { text: format!("ROUND {} DRAWN", self.round_number) });
+            self.round = Round::Over(ROUND_OVER_TIME);
+            return;
+        };
+
+        let player = &mut self.players[winner];
+        player.score += 1;
+        self.bus.queue(HudMessage { text: format!("{} WINS ROUND {}", player.hud.name, self.round_number) });
+
+        self.round = if player.score == ROUNDS_TO_WIN {
+            self.bus.queue(HudMessage { text: format!("{} WINS THE MATCH", player.hud.name) });
+            Round::MatchOver
+        } else {
+            Round::Over(ROUND_OVER_TIME)
+        };
+    }
+
+    fn next_round(&mut self) {
+        for player in &mut self.players {
+            player.reset();
+        }
+
+        self.round_number += 1;
+        self.round = Round::Playing;
+        self.bus.queue(HudMessage { text: format!("ROUND {}", self.round_number) });
+    }
+
+    /// Starts the match again from nothing.
+    fn rematch(&mut self) {
+        for player in &mut self.players {
+            player.score = 0;
+        }
+
+        self.round_number = 0;
+        self.next_round();
+    }
+}
+
+impl ScreenRenderer for VersusScreen {
+    fn update(&mut self, camera: &mut Camera, actions: &ActionState, delta_time: f32) -> Option<ScreenTransition> {
+        if actions.pressed(Action::Back) {
+            return Some(ScreenTransition::Title);
+        }
+
+        let views = self.views(&camera.viewport).unwrap_or_default();
+        for (player, view) in self.players.iter_mut().zip(views) {
+            player.camera.update_viewport_size(view.camera.viewport);
+        }
+
+        match self.round {
+            Round::Playing => self.play(actions, delta_time),
+            Round::Over(time_left) if time_left > delta_time => self.round = Round::Over(time_left - delta_time),
+            Round::Over(_) => self.next_round(),
+            Round::MatchOver => {
+                if (0..2).any(|index| actions.player(index).pressed(Action::Fire)) {
+                    self.rematch();
+                }
+            }
+        }
+
+        self.level.advance(delta_time);
+
+        for index in 0..2 {
+            let (player, other) = pair(&mut self.players, index);
+
+            player.chase.update(&mut player.camera, &Target::of(&player.vehicle.entity.mesh), delta_time);
+            player.hud.armour = player.armour;
+            player.hud.loaded = player.reload == 0.0;
+            player.hud.update(&player.camera, std::slice::from_ref(&other.vehicle.entity), delta_time);
+        }
+
+        // Only changes what's drawn, each entity in as much detail as the nearer player needs
+        let cameras = self.players.each_ref().map(|player| player.camera.clone());
+        for entity in self.level.entities.iter_mut().chain(self.players.iter_mut().map(|player| &mut player.vehicle.entity)) {
+            entity.update_lod(nearer(&cameras, &entity.mesh.position));
+        }
+
+        self.bus.flush();
+
+        None
+    }
+
+    /// The map and both tanks, unless they've been destroyed, from one of the players' cameras.
+    fn render(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let tanks = self.players.iter().filter(|player| !player.destroyed()).map(|player| &player.vehicle.entity);
+
+        self.level.render_scene(camera, tanks, backend);
+    }
+
+    /// Each player's view in their half of the window.
+    fn views(&self, window: &Rectangle) -> Option<Vec<View>> {
+        Some(View::side_by_side(self.players.iter().map(|player| player.camera.clone()).collect(), window))
+    }
+
+    fn render_view_hud(&self, index: usize, camera: &Camera, backend: &mut dyn RenderBackend) {
+        if let Some(player) = self.players.get(index) {
+            player.hud.render(camera, backend);
+        }
+    }
+
+    /// The score over the line between the players' views.
+    fn render_over_views(&self, camera: &Camera, backend: &mut dyn RenderBackend) {
+        let (width, height) = (camera.viewport.width, camera.viewport.height);
+        backend.line([Pos2::new(width / 2.0, 0.0), Pos2::new(width / 2.0, height)], Stroke::new(2.0, Color32::DARK_GREEN));
+
+        let mut style = TextStyle::new(24.0);
+        style.align = Align2::CENTER_TOP;
+        let score = format!("{} - {}", self.players[0].score, self.players[1].score);
+        backend.text(&score, Pos2::new(width / 2.0, 10.0), &style, Stroke::new(2.0, Color32::GREEN));
+
+        if self.round == Round::MatchOver {
+            let mut style = TextStyle::new(16.0);
+            style.align = Align2::CENTER_BOTTOM;
+
+            backend.text("FIRE TO PLAY AGAIN    ESC TO QUIT", Pos2::new(width / 2.0, height - 20.0), &style, Stroke::new(1.5, Color32::GREEN));
+        }
+    }
+
+    fn entities(&self) -> &[Entity] {
+        &self.level.entities
+    }
+
+    fn background(&self) -> Option<&Background> {
+        self.level.background()
+    }
+
+    fn bus(&mut self) -> Option<&mut MessageBus> {
+        Some(&mut self.bus)
+    }
+}
+
+/// # unscripted
+/// Fails if the map has triggers, waves or scripts, which versus never runs as it only
+/// `advance`s the level, so a map with them would just sit there.
+fn unscripted(assets: &LevelAssets) -> Result<(), String> {
+    if !assets.triggers.is_empty() || !assets.waves.is_empty() {
+        return Err("the map has triggers or waves".to_string());
+    }
+
+    if !assets.scripts.is_empty() || assets.entities.iter().any(|entity| entity.script.is_some()) {
+        return Err("the map has scripts".to_string());
+    }
+
+    Ok(())
+}
+
+/// The player at `index` and the other one.
+fn pair(players: &mut [Player; 2], index: usize) -> (&mut Player, &mut Player) {
+    let [one, two] = players;
+
+    if index == 0 { (one, two) } else { (two, one) }
+}
+
+/// The camera nearer to `position`.
+fn nearer<'a>(cameras: &'a [Camera; 2], position: &Vector3D) -> &'a Camera {
+    let distance = |camera: &Camera| Vector3D::from(&camera.position).subtract(position).length();
+
+    if distance(&cameras[1]) < distance(&cameras[0]) { &cameras[1] } else { &cameras[0] }
+}
+
+/// How far along a shot across the ground it hits the entity, going by the entity's radius.
+/// `direction` should be along the ground and a unit long.
+fn hit_distance(from: &Vector3D, direction: &Vector3D, entity: &Entity) -> Option<f32> {
+    let (x, z) = (entity.mesh.position.x - from.x, entity.mesh.position.z - from.z);
+    let along = x * direction.x + z * direction.z;
+    let miss = (x * direction.z - z * direction.x).abs();
+
+    (along > 0.0 && miss <= entity.radius).then_some(along)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::geometry::Point3D;
+    use eframe::egui::{Rect, Vec2};
+
+    fn camera() -> Camera {
+        Camera::for_tests(Point3D { x: 0.0, y: 0.0, z: 0.0 }, 200.0)
+    }
+
+    /// Each player doing one action, for a frame.
+    fn players(actions: [Option<Action>; 2]) -> ActionState {
+        let players = actions.map(|action| {
+            let mut state = ActionState::default();
+            if let Some(action) = action {
+                state.set(action, 1.0, true);
+            }
+            state
+        });
+
+        let mut state = ActionState::default();
+        state.players = players.to_vec();
+        state
+    }
+
+    #[test]
+    /// # test_players_drive_their_own_tanks
+    /// Each player's keys only drive their own tank, and their camera follows it in their half of
+    /// the window.
+    fn test_players_drive_their_own_tanks() {
+        let mut camera = camera();
+        let mut screen = VersusScreen::new(&camera, DepthCue::default());
+        let start = screen.players.each_ref().map(|player| player.vehicle.entity.mesh.position.clone());
+
+        for _ in 0..30 {
+            screen.update(&mut camera, &players([None, Some(Action::MoveForward)]), 1.0 / 30.0);
+        }
+
+        assert_eq!(screen.players[0].vehicle.entity.mesh.position, start[0]);
+        assert!(start[1].subtract(&screen.players[1].vehicle.entity.mesh.position).length() > 1.0);
+
+        let player = &screen.players[1];
+        let tank = &player.vehicle.entity.mesh.position;
+        assert_eq!((player.camera.viewport.width, player.camera.viewport.height), (400.0, 600.0));
+        assert!(player.camera.depth(&Point3D { x: tank.x, y: tank.y, z: tank.z }) > 0.0);
+    }
+
+    #[test]
+    /// # test_views_split_the_window
+    /// Versus draws through a view from each player's camera, side by side over the window.
+    fn test_views_split_the_window() {
+        let camera = camera();
+        let screen = VersusScreen::new(&camera, DepthCue::default());
+
+        let views = screen.views(&camera.viewport).expect("versus should supply its own views");
+
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].rect, Rect::from_min_size(Pos2::new(0.0, 0.0), Vec2::new(400.0, 600.0)));
+        assert_eq!(views[1].rect, Rect::from_min_size(Pos2::new(400.0, 0.0), Vec2::new(400.0, 600.0)));
+        assert_eq!(views[1].camera.position, screen.players[1].camera.position);
+    }
+
+    #[test]
+    /// # test_scripted_maps_are_turned_away
+    /// The versus map loads without triggers or scripts, and a map with them is turned away.
+    fn test_scripted_maps_are_turned_away() {
+        assert_eq!(unscripted(&load_assets(VERSUS_MAP).unwrap()), Ok(()));
+        assert!(unscripted(&load_assets(crate::screens::level1_screen::LEVEL1_MAP).unwrap()).is_err());
+    }
+
+    #[test]
+    /// # test_rounds_win_the_match
+    /// Player 1 shooting player 2 `ARMOUR` times wins a round, the tanks start again for the
+    /// next, and winning `ROUNDS_TO_WIN` wins the match until they play again.
+    fn test_rounds_win_the_match() {
+        let mut camera = camera();
+        let mut screen = VersusScreen::new(&camera, DepthCue::default());
+        let start = screen.players[1].vehicle.entity.mesh.position.clone();
+
+        for round in 1..=ROUNDS_TO_WIN {
+            assert_eq!((screen.round, screen.round_number), (Round::Playing, round));
+
+            for _ in 0..ARMOUR {
+                screen.update(&mut camera, &players([Some(Action::Fire), None]), RELOAD_TIME);
+            }
+
+            assert!(screen.players[1].destroyed());
+            assert_eq!([screen.players[0].score, screen.players[1].score], [round, 0]);
+
+            screen.update(&mut camera, &players([None, None]), ROUND_OVER_TIME);
+        }
+
+        assert_eq!(screen.round, Round::MatchOver);
+        assert_eq!(screen.players[0].armour, ARMOUR, "player 2 never hit back");
+
+        screen.update(&mut camera, &players([None, Some(Action::Fire)]), 0.1);
+        assert_eq!((screen.round, screen.round_number), (Round::Playing, 1));
+        assert_eq!([screen.players[0].score, screen.players[1].score], [0, 0]);
+        assert_eq!(screen.players[1].armour, ARMOUR);
+        assert_eq!(screen.players[1].vehicle.entity.mesh.position, start);
+    }
+
+    #[test]
+    /// # test_lod_follows_the_nearer_player
+    /// The map's entities and the tanks change detail with how far they are from the players.
+    fn test_lod_follows_the_nearer_player() {
+        let mut camera = camera();
+        let mut screen = VersusScreen::new(&camera, DepthCue::default());
+        let mut tank = screen.players[0].vehicle.entity.clone();
+        assert!(tank.lod.levels().len() > 1);
+        tank.mesh.position = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+        screen.level.entities.push(tank);
+
+        // Both tanks far back along the same heading, with a tank in the map way off ahead of them
+        let heading = screen.players[0].vehicle.entity.mesh.rotation;
+        for (player, z) in screen.players.iter_mut().zip([-10.0, 10.0]) {
+            player.vehicle.entity.mesh.position = Vector3D { x: -1000.0, y: 0.0, z };
+            player.vehicle.entity.mesh.rotation = heading;
+        }
+
+        for _ in 0..30 {
+            screen.update(&mut camera, &players([None, None]), 1.0 / 30.0);
+        }
+
+        assert!(screen.level.entities.last().unwrap().lod.current_index() > 0);
+        assert!(screen.players.iter().all(|player| player.vehicle.entity.lod.current_index() == 0));
+    }
+
+    #[test]
+    /// # test_shots_are_blocked
+    /// A shot hits what it's aimed at, unless something's in the way, and not what's behind it.
+    fn test_shots_are_blocked() {
+        let mut screen = VersusScreen::new(&camera(), DepthCue::default());
+        let from = Vector3D { x: 0.0, y: 0.0, z: 0.0 };
+        let ahead = Vector3D { x: 0.0, y: 0.0, z: 1.0 };
+        let mut entity = screen.players[0].vehicle.entity.clone();
+
+        entity.mesh.position = Vector3D { x: entity.radius * 0.5, y: 0.0, z: 20.0 };
+        assert_eq!(hit_distance(&from, &ahead, &entity), Some(20.0));
+
+        entity.mesh.position.z = -20.0;
+        assert_eq!(hit_distance(&from, &ahead, &entity), None);
+
+        // A block in the map between the tanks stops player 1's shots
+        let mut block = screen.level.entities[0].clone();
+        block.mesh.position = screen.players[0].vehicle.entity.mesh.position.clone() + screen.players[0].vehicle.forward().set_length(20.0);
+        screen.level.entities.push(block);
+
+        screen.fire(0);
+        assert_eq!(screen.players[1].armour, ARMOUR);
+    }
+}
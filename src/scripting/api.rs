@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use rhai::{Array, Dynamic, Engine};
+use crate::objects::entity::Entity;
+use crate::screens::events::{HudMessage, LevelComplete, ScriptOutput, SpawnEntity, SpawnWave};
+use crate::types::geometry::*;
+use crate::utils::message_bus::MessageBus;
+
+/// Most operations a script can run in one call before it's stopped, so a script stuck in a loop
+/// can't hang the game.
+const MAX_OPERATIONS: u64 = 200_000;
+
+/// An event a script has asked for, published once the script has finished.
+type QueuedEvent = Box<dyn FnOnce(&mut MessageBus)>;
+
+/// # ScriptEntity
+/// An entity as scripts see it, which one it is and where. Angles are in radians.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptEntity {
+    pub id: String,
+    pub object_type: String,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub pitch: f64,
+    pub yaw: f64,
+    pub roll: f64,
+}
+
+impl ScriptEntity {
+    pub fn of(entity: &Entity) -> Self {
+        let (position, rotation) = (&entity.mesh.position, &entity.mesh.rotation);
+
+        Self {
+            id: entity.id.clone(),
+            object_type: entity.object_type.clone(),
+            x: position.x as f64,
+            y: position.y as f64,
+            z: position.z as f64,
+            pitch: rotation.pitch as f64,
+            yaw: rotation.yaw as f64,
+            roll: rotation.roll as f64,
+        }
+    }
+
+    /// Puts the entity where the script moved this to.
+    pub fn apply(&self, entity: &mut Entity) {
+        entity.mesh.position = Vector3D { x: self.x as f32, y: self.y as f32, z: self.z as f32 };
+        entity.mesh.rotation = EulerAngles { pitch: self.pitch as f32, yaw: self.yaw as f32, roll: self.roll as f32 };
+    }
+}
+
+/// # ScriptWorld
+/// What's shared between the functions scripts call and whoever runs them. The runtime fills in
+/// `time` and `entities` before calling into scripts, then applies `moved` and publishes
+/// `events` once they're done.
+#[derive(Default)]
+pub struct ScriptWorld {
+    /// Seconds into the level.
+    pub time: f64,
+    pub entities: Vec<ScriptEntity>,
+    /// Entities scripts have moved with `set_transform`.
+    pub moved: Vec<ScriptEntity>,
+    pub events: Vec<QueuedEvent>,
+}
+
+impl ScriptWorld {
+    fn queue(&mut self, event: impl FnOnce(&mut MessageBus) + 'static) {
+        self.events.push(Box::new(event));
+    }
+}
+
+/// # engine
+/// A Rhai engine with the game's functions for scripts:
+///
+/// - `time()`, seconds into the level.
+/// - `entities()` and `entity(id)`, copies of the level's entities, `()` if there's no such id.
+/// - `set_transform(entity)`, moves the entity with that id to where the copy is.
+/// - `spawn(object_type, model, x, z)` and `spawn_wave(name)`, bring in more entities.
+/// - `message(text)`, shows text on the HUD.
+/// - `complete_level()`.
+///
+/// Entities have `id` and `object_type`, and `x`, `y`, `z`, `pitch`, `yaw` and `roll` to move them
+/// about. Whatever scripts `print` goes to the console.
+pub fn engine(world: &Rc<RefCell<ScriptWorld>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+
+    engine
+        .register_type_with_name::<ScriptEntity>("Entity")
+        .register_get("id", |entity: &mut ScriptEntity| entity.id.clone())
+        .register_get("object_type", |entity: &mut ScriptEntity| entity.object_type.clone())
+        .register_get_set("x", |entity: &mut ScriptEntity| entity.x, |entity: &mut ScriptEntity, value: f64| entity.x = value)
+        .register_get_set("y", |entity: &mut ScriptEntity| entity.y, |entity: &mut ScriptEntity, value: f64| entity.y = value)
+        .register_get_set("z", |entity: &mut ScriptEntity| entity.z, |entity: &mut ScriptEntity, value: f64| entity.z = value)
+        .register_get_set("pitch", |entity: &mut ScriptEntity| entity.pitch, |entity: &mut ScriptEntity, value: f64| entity.pitch = value)
+        .register_get_set("yaw", |entity: &mut ScriptEntity| entity.yaw, |entity: &mut ScriptEntity, value: f64| entity.yaw = value)
+        .register_get_set("roll", |entity: &mut ScriptEntity| entity.roll, |entity: &mut ScriptEntity, value: f64| entity.roll = value);
+
+    let shared = world.clone();
+    engine.register_fn("time", move || shared.borrow().time);
+
+    let shared = world.clone();
+    engine.register_fn("entities", move || -> Array {
+        shared.borrow().entities.iter().cloned().map(Dynamic::from).collect()
+    });
+
+    let shared = world.clone();
+    engine.register_fn("entity", move |id: &str| -> Dynamic {
+        shared.borrow().entities.iter().find(|entity| entity.id == id).cloned().map_or(Dynamic::UNIT, Dynamic::from)
+    });
+
+    let shared = world.clone();
+    engine.register_fn("set_transform", move |entity: ScriptEntity| shared.borrow_mut().moved.push(entity));
+
+    let shared = world.clone();
+    engine.register_fn("spawn", move |object_type: &str, model: &str, x: f64, z: f64| {
+        let event = SpawnEntity { object_type: object_type.to_string(), model: Some(model.to_string()), x: x as f32, z: z as f32 };
+        shared.borrow_mut().queue(move |bus| bus.queue(event));
+    });
+
+    let shared = world.clone();
+    engine.register_fn("spawn_wave", move |wave: &str| {
+        let event = SpawnWave { wave: wave.to_string() };
+        shared.borrow_mut().queue(move |bus| bus.queue(event));
+    });
+
+    let shared = world.clone();
+    engine.register_fn("message", move |text: &str| {
+        let event = HudMessage { text: text.to_string() };
+        shared.borrow_mut().queue(move |bus| bus.queue(event));
+    });
+
+    let shared = world.clone();
+    engine.register_fn("complete_level", move || shared.borrow_mut().queue(|bus| bus.queue(LevelComplete)));
+
+    let shared = world.clone();
+    engine.on_print(move |text| {
+        let event = ScriptOutput { text: text.to_string(), is_error: false };
+        shared.borrow_mut().queue(move |bus| bus.queue(event));
+    });
+
+    engine
+}
@@ -0,0 +1,382 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::str::from_utf8;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use crate::objects::entity::Entity;
+use crate::scripting::api::{engine, ScriptEntity, ScriptWorld};
+use crate::screens::events::{EntityDestroyed, ScriptOutput};
+use crate::utils::assets::Asset;
+use crate::utils::message_bus::MessageBus;
+
+/// Seconds between checking the scripts' files for changes.
+#[cfg(debug_assertions)]
+const RELOAD_INTERVAL: f32 = 1.0;
+
+/// # Script
+/// A script file and what it compiled to.
+struct Script {
+    file: String,
+    source: String,
+    /// `None` if it didn't compile, or has failed since. It stays off until the file changes so
+    /// the console isn't flooded with the same error every tick.
+    ast: Option<AST>,
+}
+
+impl Script {
+    /// Reads and compiles the file, returning the error if that fails.
+    fn load(engine: &Engine, file: &str) -> (Self, Option<String>) {
+        let Some(source) = read_source(file) else {
+            return (Self { file: file.to_string(), source: String::new(), ast: None }, Some(format!("{}: missing", file)));
+        };
+
+        let mut script = Self { file: file.to_string(), source, ast: None };
+        let error = script.compile(engine);
+
+        (script, error)
+    }
+
+    fn compile(&mut self, engine: &Engine) -> Option<String> {
+        match engine.compile(&self.source) {
+            Ok(ast) => {
+                self.ast = Some(ast);
+                None
+            }
+            Err(error) => Some(format!("{}: {}", self.file, error)),
+        }
+    }
+
+    /// Calls a function in the script with `this` bound, if it has that function. Anything going
+    /// wrong turns the script off and comes back as the error.
+    fn call(&mut self, engine: &Engine, this: &mut Dynamic, name: &str, args: Vec<Dynamic>) -> Result<(), String> {
+        let Some(ast) = &self.ast else {
+            return Ok(());
+        };
+
+        if !ast.iter_functions().any(|function| function.name == name && function.params.len() == args.len()) {
+            return Ok(());
+        }
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+
+        engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, args)
+            .map(|_| ())
+            .map_err(|error| {
+                self.ast = None;
+                format!("{}: {} in {}", self.file, error, name)
+            })
+    }
+}
+
+fn read_source(file: &str) -> Option<String> {
+    Asset::get(file).and_then(|asset| from_utf8(&asset.data).ok().map(str::to_string))
+}
+
+/// # ScriptRuntime
+/// Runs a level's scripts every tick.
+///
+/// Level scripts can have `init()`, called when they're loaded, `update(dt)` every tick and
+/// `on_destroyed(id, object_type)` when an entity is destroyed. `this` is a map they can keep
+/// whatever they like in between calls. Entity scripts have `update(dt)` with `this` as the
+/// entity, which they can move about.
+///
+/// In debug builds scripts are reloaded when their files change. Only debug builds read `assets/`
+/// from disk, release builds have the scripts baked in so they never change. Errors go to the
+/// console as `ScriptOutput`. See `api::engine` for what scripts can do.
+pub struct ScriptRuntime {
+    engine: Engine,
+    world: Rc<RefCell<ScriptWorld>>,
+    /// Every script loaded so far by file, entity scripts being loaded the first time they're used.
+    scripts: HashMap<String, Script>,
+    /// The level scripts' files in order, each with its `this`.
+    level: Vec<(String, Dynamic)>,
+    /// Entities destroyed since the last tick, for `on_destroyed`.
+    destroyed: Rc<RefCell<Vec<EntityDestroyed>>>,
+    #[cfg(debug_assertions)]
+    since_reload_check: f32,
+}
+
+impl ScriptRuntime {
+    /// Loads the level scripts, subscribing to the bus for what they want to hear about and
+    /// queuing any errors on it.
+    pub fn new(level_scripts: &[String], bus: &mut MessageBus) -> Self {
+        let world = Rc::new(RefCell::new(ScriptWorld::default()));
+
+        let destroyed = Rc::new(RefCell::new(Vec::new()));
+        let on_destroyed = destroyed.clone();
+        bus.subscribe(move |event: &EntityDestroyed| on_destroyed.borrow_mut().push(event.clone()));
+
+        let mut runtime = Self {
+            engine: engine(&world),
+            world,
+            scripts: HashMap::new(),
+            level: level_scripts.iter().map(|file| (file.clone(), Dynamic::UNIT)).collect(),
+            destroyed,
+            #[cfg(debug_assertions)]
+            since_reload_check: 0.0,
+        };
+
+        for file in level_scripts {
+            runtime.load(file);
+            runtime.init(file);
+        }
+        runtime.publish(bus);
+
+        runtime
+    }
+
+    /// # update
+    /// Runs the scripts for a tick `time` seconds into the level, moving the entities the scripts
+    /// move and queuing the events they ask for on the bus.
+    pub fn update(&mut self, time: f32, delta_time: f32, entities: &mut [Entity], bus: &mut MessageBus) {
+        #[cfg(debug_assertions)]
+        {
+            self.since_reload_check += delta_time;
+            if self.since_reload_check >= RELOAD_INTERVAL {
+                self.since_reload_check = 0.0;
+                self.reload_changed();
+            }
+        }
+
+        {
+            let mut world = self.world.borrow_mut();
+            world.time = time as f64;
+            world.entities = entities.iter().map(ScriptEntity::of).collect();
+        }
+
+        let destroyed = self.destroyed.take();
+        for index in 0..self.level.len() {
+            let file = self.level[index].0.clone();
+
+            for event in &destroyed {
+                self.call_level(&file, index, "on_destroyed", vec![event.id.clone().into(), event.object_type.clone().into()]);
+            }
+            self.call_level(&file, index, "update", vec![Dynamic::from_float(delta_time as f64)]);
+        }
+
+        for entity in entities.iter_mut() {
+            let Some(file) = entity.script.clone() else {
+                continue;
+            };
+
+            if !self.scripts.contains_key(&file) {
+                self.load(&file);
+            }
+
+            let mut this = Dynamic::from(ScriptEntity::of(entity));
+            let result = self.scripts
+                .get_mut(&file)
+                .map_or(Ok(()), |script| script.call(&self.engine, &mut this, "update", vec![Dynamic::from_float(delta_time as f64)]));
+
+            match result {
+                Ok(()) => {
+                    if let Some(moved) = this.try_cast::<ScriptEntity>() {
+                        moved.apply(entity);
+                    }
+                }
+                Err(error) => self.report(error),
+            }
+        }
+
+        let moved = std::mem::take(&mut self.world.borrow_mut().moved);
+        for transform in moved {
+            if let Some(entity) = entities.iter_mut().find(|entity| entity.id == transform.id) {
+                transform.apply(entity);
+            }
+        }
+
+        self.publish(bus);
+    }
+
+    fn load(&mut self, file: &str) {
+        let (script, error) = Script::load(&self.engine, file);
+        self.scripts.insert(file.to_string(), script);
+
+        if let Some(error) = error {
+            self.report(error);
+        }
+    }
+
+    /// Starts a level script's `this` afresh and calls its `init`.
+    fn init(&mut self, file: &str) {
+        for index in 0..self.level.len() {
+            if self.level[index].0 == file {
+                self.level[index].1 = Dynamic::from_map(Map::new());
+                self.call_level(file, index, "init", vec![]);
+            }
+        }
+    }
+
+    fn call_level(&mut self, file: &str, index: usize, name: &str, args: Vec<Dynamic>) {
+        let (Some(script), Some((_, this))) = (self.scripts.get_mut(file), self.level.get_mut(index)) else {
+            return;
+        };
+
+        if let Err(error) = script.call(&self.engine, this, name, args) {
+            self.report(error);
+        }
+    }
+
+    /// Sends an error to the console, in order with whatever the scripts printed.
+    fn report(&self, error: String) {
+        self.world.borrow_mut().events.push(Box::new(move |bus| bus.queue(ScriptOutput { text: error, is_error: true })));
+    }
+
+    /// Recompiles the scripts whose files have changed, starting level scripts over. `Asset` only
+    /// reads from disk in debug builds, so that's the only place there's anything to reload.
+    #[cfg(debug_assertions)]
+    fn reload_changed(&mut self) {
+        let changed: Vec<String> = self.scripts
+            .values()
+            .filter(|script| read_source(&script.file).is_some_and(|source| source != script.source))
+            .map(|script| script.file.clone())
+            .collect();
+
+        for file in changed {
+            let text = format!("Reloaded {}", file);
+            self.world.borrow_mut().events.push(Box::new(move |bus| bus.queue(ScriptOutput { text, is_error: false })));
+
+            self.load(&file);
+            self.init(&file);
+        }
+    }
+
+    /// Queues the events scripts asked for on the bus.
+    fn publish(&mut self, bus: &mut MessageBus) {
+        let events = std::mem::take(&mut self.world.borrow_mut().events);
+
+        for event in events {
+            event(bus);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::mesh::Mesh;
+    use crate::screens::events::HudMessage;
+    use crate::types::geometry::*;
+
+    fn entity(id: &str, script: Option<&str>) -> Entity {
+        let mesh = Mesh {
+            verts: vec![],
+            faces: vec![],
+            position: Vector3D { x: 0.0, y: 0.0, z: 0.0 },
+            rotation: EulerAngles { pitch: 0.0, yaw: 0.0, roll: 0.0 },
+        };
+
+        Entity { script: script.map(str::to_string), ..Entity::new(id.to_string(), "enemy_tank".to_string(), mesh) }
+    }
+
+    /// Subscribes to what the scripts say, written down in order.
+    fn listen(bus: &mut MessageBus) -> Rc<RefCell<Vec<String>>> {
+        let said = Rc::new(RefCell::new(Vec::new()));
+
+        let log = said.clone();
+        bus.subscribe(move |message: &HudMessage| log.borrow_mut().push(message.text.clone()));
+        let log = said.clone();
+        bus.subscribe(move |output: &ScriptOutput| log.borrow_mut().push(format!("{} {}", if output.is_error { "error" } else { "print" }, output.text)));
+
+        said
+    }
+
+    /// A runtime with one script compiled from `source` rather than loaded from a file.
+    fn runtime_with(file: &str, source: &str, level: bool, bus: &mut MessageBus) -> ScriptRuntime {
+        let mut runtime = ScriptRuntime::new(&[], bus);
+        let mut script = Script { file: file.to_string(), source: source.to_string(), ast: None };
+        assert_eq!(script.compile(&runtime.engine), None);
+
+        runtime.scripts.insert(file.to_string(), script);
+        if level {
+            runtime.level.push((file.to_string(), Dynamic::UNIT));
+            runtime.init(file);
+        }
+
+        runtime
+    }
+
+    #[test]
+    /// # test_entity_script_moves_entity
+    fn test_entity_script_moves_entity() {
+        let mut bus = MessageBus::new();
+        let mut runtime = runtime_with("scripts/test_slide.rhai", "fn update(dt) { this.x += dt * 2.0; this.yaw = 1.5; }", false, &mut bus);
+        let mut entities = vec![entity("Tank", Some("scripts/test_slide.rhai")), entity("Still", None)];
+
+        runtime.update(0.0, 0.5, &mut entities, &mut bus);
+        runtime.update(0.5, 0.5, &mut entities, &mut bus);
+
+        assert_eq!(entities[0].mesh.position.x, 2.0);
+        assert_eq!(entities[0].mesh.rotation.yaw, 1.5);
+        assert_eq!(entities[1].mesh.position.x, 0.0);
+    }
+
+    #[test]
+    /// # test_level_script_keeps_state_and_hears_events
+    /// A level script counts kills in `this`, talks to the HUD and moves other entities.
+    fn test_level_script_keeps_state_and_hears_events() {
+        let mut bus = MessageBus::new();
+        let said = listen(&mut bus);
+        let source = r#"
+            fn init() { this.kills = 0; }
+            fn on_destroyed(id, object_type) {
+                this.kills += 1;
+                message(`${object_type} ${this.kills}`);
+            }
+            fn update(dt) {
+                let tank = entity("Tank");
+                tank.z = time();
+                set_transform(tank);
+            }
+        "#;
+        let mut runtime = runtime_with("scripts/level.rhai", source, true, &mut bus);
+        let mut entities = vec![entity("Tank", None)];
+
+        for kill in 0..2 {
+            bus.publish(EntityDestroyed { id: format!("Kill{}", kill), object_type: "enemy_tank".to_string(), position: Vector3D { x: 0.0, y: 0.0, z: 0.0 } });
+        }
+        runtime.update(3.0, 0.1, &mut entities, &mut bus);
+        bus.flush();
+
+        assert_eq!(*said.borrow(), vec!["enemy_tank 1", "enemy_tank 2"]);
+        assert_eq!(entities[0].mesh.position.z, 3.0);
+    }
+
+    #[test]
+    /// # test_script_errors_go_to_console
+    /// A failing script reports once and then stays quiet rather than stopping the game, and one
+    /// that never ends is cut off.
+    fn test_script_errors_go_to_console() {
+        let mut bus = MessageBus::new();
+        let said = listen(&mut bus);
+        let mut runtime = runtime_with("scripts/broken.rhai", "fn update(dt) { print(\"hi\"); this.missing += 1; }", true, &mut bus);
+        let mut entities = vec![entity("Forever", Some("scripts/forever.rhai"))];
+        let mut forever = Script { file: "scripts/forever.rhai".to_string(), source: "fn update(dt) { loop {} }".to_string(), ast: None };
+        forever.compile(&runtime.engine);
+        runtime.scripts.insert(forever.file.clone(), forever);
+
+        for _ in 0..3 {
+            runtime.update(0.0, 0.1, &mut entities, &mut bus);
+        }
+        bus.flush();
+
+        let said = said.borrow();
+        assert_eq!(said.len(), 3, "{:?}", said);
+        assert_eq!(said[0], "print hi");
+        assert!(said[1].starts_with("error scripts/broken.rhai"), "{}", said[1]);
+        assert!(said[2].starts_with("error scripts/forever.rhai"), "{}", said[2]);
+    }
+
+    #[test]
+    /// # test_missing_script
+    fn test_missing_script() {
+        let mut bus = MessageBus::new();
+        let said = listen(&mut bus);
+        let mut runtime = ScriptRuntime::new(&["scripts/missing.rhai".to_string()], &mut bus);
+        runtime.update(0.0, 0.1, &mut [], &mut bus);
+        bus.flush();
+
+        assert_eq!(*said.borrow(), vec!["error scripts/missing.rhai: missing"]);
+    }
+}
@@ -2,6 +2,7 @@ use std::f32::consts::PI;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::ops::Add;
+use crate::log_trace;
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Face {
@@ -20,6 +21,15 @@ pub struct Point2D {
     pub y: f32,
 }
 
+/// A point projected onto the viewport, along with how far in front of the camera it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectedPoint {
+    pub x: f32,
+    pub y: f32,
+    /// Camera space depth, the distance along the camera's look direction. Negative behind it.
+    pub depth: f32,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Point3D {
     pub x: f32,
@@ -108,6 +118,12 @@ impl Vector3D {
         }
     }
 
+    /// # length
+    /// The length (magnitude) of the vector.
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z).sqrt()
+    }
+
     /// # angle_to_other_vector
     /// Pass a 3D vector and will return the angle in radians between self and the passed in vector.
     ///
@@ -416,8 +432,7 @@ impl From<EulerAngles> for Vector3D {
         let y = sin_pitch;            // +Y is up
         let z = cos_yaw * cos_pitch;  // +Z is forward
 
-        println!("Euler: Pitch:{}, Roll:{}, Yaw:{}", euler.pitch.to_degrees(), euler.roll.to_degrees(), euler.yaw.to_degrees());
-        println!("Vector: {:?}", Vector3D { x, y, z });
+        log_trace!("Euler: pitch {}, roll {}, yaw {} to vector {:?}", euler.pitch.to_degrees(), euler.roll.to_degrees(), euler.yaw.to_degrees(), Vector3D { x, y, z });
 
         Vector3D { x, y, z }
     }
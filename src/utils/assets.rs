@@ -0,0 +1,7 @@
+use rust_embed::RustEmbed;
+
+/// # Asset
+/// Everything under `assets/` baked into the binary.
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+pub struct Asset;
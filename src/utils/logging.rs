@@ -0,0 +1,283 @@
+use std::collections::VecDeque;
+use std::fmt::Arguments;
+use std::sync::Mutex;
+
+/// The environment variable the filters are read from at start up, e.g. `VECTAR_LOG=info,camera=debug`.
+pub const LOG_ENV: &str = "VECTAR_LOG";
+
+/// How many records the log keeps for the overlay, oldest dropped first.
+const MAX_RECORDS: usize = 500;
+
+/// # Level
+/// How much a log record matters, from most to least. A filter at a level lets through that and
+/// everything above it, `Off` lets through nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const ALL: [Level; 6] = [Level::Off, Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Level> {
+        Level::ALL.into_iter().find(|level| level.name() == name)
+    }
+}
+
+/// # Record
+/// Something that was logged, by the module it came from without the crate's name, such as
+/// `player::camera`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub level: Level,
+    pub module: String,
+    pub message: String,
+}
+
+/// # Filters
+/// Which records are kept: a level for each module that has one and `default` for the rest. A
+/// module's filter covers its submodules, and can be named by its last part, so `camera` and
+/// `player::camera` are the same. The longest matching name wins.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filters {
+    pub default: Level,
+    pub modules: Vec<(String, Level)>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Filters {
+    /// Everything at `Info` and above.
+    pub const fn new() -> Self {
+        Self { default: Level::Info, modules: Vec::new() }
+    }
+
+    /// # parse
+    /// Reads filters written as a comma separated list of `module=level` and a bare level for the
+    /// default, e.g. `warn,camera=debug,geometry=trace`. Anything not given is left as it is in
+    /// `self`.
+    pub fn parse(&self, spec: &str) -> Result<Filters, String> {
+        let mut filters = self.clone();
+
+        for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let level = |name: &str| Level::from_name(name).ok_or(format!("No log level {}", name));
+
+            match part.split_once('=') {
+                Some((module, _)) if module.trim().is_empty() => return Err(format!("No module in {}", part)),
+                Some((module, name)) => {
+                    let (module, level) = (module.trim().to_string(), level(name.trim())?);
+                    filters.modules.retain(|(other, _)| *other != module);
+                    filters.modules.push((module, level));
+                }
+                None => filters.default = level(part)?,
+            }
+        }
+
+        Ok(filters)
+    }
+
+    /// The most detailed level kept for records from `module`.
+    pub fn level_for(&self, module: &str) -> Level {
+        self.modules
+            .iter()
+            .filter(|(name, _)| covers(name, module))
+            .max_by_key(|(name, _)| name.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    pub fn enabled(&self, level: Level, module: &str) -> bool {
+        level != Level::Off && level <= self.level_for(module)
+    }
+}
+
+/// Whether the filter for `name` applies to records from `module`, i.e. `name` is a run of whole
+/// parts of its path.
+fn covers(name: &str, module: &str) -> bool {
+    let name: Vec<&str> = name.split("::").collect();
+    let module: Vec<&str> = module.split("::").collect();
+
+    module.windows(name.len()).any(|parts| parts == name.as_slice())
+}
+
+/// # Log
+/// The filters and the latest records that got through them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Log {
+    pub filters: Filters,
+    records: VecDeque<Record>,
+}
+
+impl Log {
+    pub const fn new() -> Self {
+        Self { filters: Filters::new(), records: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, record: Record) {
+        if self.records.len() == MAX_RECORDS {
+            self.records.pop_front();
+        }
+
+        self.records.push_back(record);
+    }
+
+    /// The last `count` records, oldest first.
+    pub fn latest(&self, count: usize) -> Vec<Record> {
+        self.records.iter().skip(self.records.len().saturating_sub(count)).cloned().collect()
+    }
+}
+
+/// The game's log. It's only touched through the functions below and the `log_*!` macros.
+static LOG: Mutex<Log> = Mutex::new(Log::new());
+
+fn with_log<T>(f: impl FnOnce(&mut Log) -> T) -> T {
+    // A panic while logging shouldn't take the log down with it
+    let mut log = LOG.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    f(&mut log)
+}
+
+/// The module path without the crate's name, which every module has in common.
+fn short_module(module: &str) -> &str {
+    module.split_once("::").map_or(module, |(_, rest)| rest)
+}
+
+/// Whether a record from `module` at `level` would be kept, so the macros don't format messages
+/// that would be thrown away.
+pub fn enabled(level: Level, module: &str) -> bool {
+    with_log(|log| log.filters.enabled(level, short_module(module)))
+}
+
+/// # log
+/// Keeps a record for the overlay and writes it to stderr. Use the `log_*!` macros rather than
+/// calling this directly.
+pub fn log(level: Level, module: &str, message: Arguments) {
+    let record = Record { level, module: short_module(module).to_string(), message: message.to_string() };
+
+    eprintln!("[{} {}] {}", record.level.name().to_uppercase(), record.module, record.message);
+    with_log(|log| log.push(record));
+}
+
+pub fn filters() -> Filters {
+    with_log(|log| log.filters.clone())
+}
+
+pub fn set_filters(filters: Filters) {
+    with_log(|log| log.filters = filters);
+}
+
+/// Reads the filters from `VECTAR_LOG`, if it's set.
+pub fn init_from_env() -> Result<(), String> {
+    match std::env::var(LOG_ENV) {
+        Ok(spec) => filters().parse(&spec).map(set_filters),
+        Err(_) => Ok(()),
+    }
+}
+
+/// The last `count` records, oldest first.
+pub fn latest(count: usize) -> Vec<Record> {
+    with_log(|log| log.latest(count))
+}
+
+/// Logs at a level, formatting the message only if it's going to be kept.
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        if $crate::utils::logging::enabled($level, module_path!()) {
+            $crate::utils::logging::log($level, module_path!(), format_args!($($arg)+));
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)+) => { $crate::log_at!($crate::utils::logging::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)+) => { $crate::log_at!($crate::utils::logging::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)+) => { $crate::log_at!($crate::utils::logging::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)+) => { $crate::log_at!($crate::utils::logging::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    ($($arg:tt)+) => { $crate::log_at!($crate::utils::logging::Level::Trace, $($arg)+) };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_filters_by_module
+    /// A module's own filter wins over the default, by its full path or its last part, and covers
+    /// its submodules.
+    fn test_filters_by_module() {
+        let filters = Filters::default().parse("warn,camera=debug,types=trace").unwrap();
+
+        assert_eq!(filters.default, Level::Warn);
+        assert!(filters.enabled(Level::Debug, "player::camera"));
+        assert!(!filters.enabled(Level::Trace, "player::camera"));
+        assert!(filters.enabled(Level::Trace, "types::geometry"));
+        assert!(!filters.enabled(Level::Info, "screens::level1_screen"));
+        assert!(filters.enabled(Level::Error, "screens::level1_screen"));
+        assert!(!filters.enabled(Level::Info, "player::camera_shake"), "only whole parts of the path match");
+
+        let filters = filters.parse("player=off,player::camera=info").unwrap();
+        assert!(!filters.enabled(Level::Error, "player::controllers"));
+        assert!(filters.enabled(Level::Info, "player::camera"), "the longer name wins");
+        assert_eq!(filters.modules.len(), 4);
+    }
+
+    #[test]
+    /// # test_bad_filters
+    fn test_bad_filters() {
+        assert!(Filters::default().parse("loud").is_err());
+        assert!(Filters::default().parse("camera=loud").is_err());
+        assert!(Filters::default().parse("=debug").is_err());
+    }
+
+    #[test]
+    /// # test_log_keeps_latest
+    fn test_log_keeps_latest() {
+        let mut log = Log::new();
+
+        for index in 0..MAX_RECORDS + 5 {
+            log.push(Record { level: Level::Info, module: "tests".to_string(), message: index.to_string() });
+        }
+
+        let latest: Vec<String> = log.latest(2).into_iter().map(|record| record.message).collect();
+        assert_eq!(latest, vec![(MAX_RECORDS + 3).to_string(), (MAX_RECORDS + 4).to_string()]);
+        assert_eq!(log.latest(MAX_RECORDS * 2).len(), MAX_RECORDS);
+    }
+}
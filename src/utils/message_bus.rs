@@ -1,33 +1,172 @@
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+
+type Handler = Box<dyn FnMut(&dyn Any)>;
+
 /// # MessageBus
 /// Implementation of the Observer patterns to allow us to share messages and events throughout the
-/// app.
-pub struct MessageBus{
-    subscribers: Vec<fn(&str, &str)>,
+/// app. Events are any type, and subscribers only hear about the type they subscribed to.
+///
+/// Events can be published straight away, or queued and delivered together when the owner calls
+/// `flush`, such as at the end of a frame's simulation. Either way subscribers hear about events in
+/// the order they were sent, and in the order they subscribed.
+///
+/// There's no global bus, whatever owns one hands it to the systems that need it.
+pub struct MessageBus {
+    subscribers: HashMap<TypeId, Vec<(Subscription, Handler)>>,
+    queued: VecDeque<(TypeId, Box<dyn Any>)>,
+    next_id: u64,
+}
+
+/// A handle to a subscription, for unsubscribing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription {
+    event: TypeId,
+    id: u64,
 }
 
 impl MessageBus {
     pub fn new() -> Self {
         Self {
-            subscribers: Vec::new(),
+            subscribers: HashMap::new(),
+            queued: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Calls `handler` with every event of type `E` from now on.
+    pub fn subscribe<E: Any>(&mut self, mut handler: impl FnMut(&E) + 'static) -> Subscription {
+        let subscription = Subscription { event: TypeId::of::<E>(), id: self.next_id };
+        self.next_id += 1;
+
+        let handler: Handler = Box::new(move |event| {
+            if let Some(event) = event.downcast_ref::<E>() {
+                handler(event);
+            }
+        });
+        self.subscribers.entry(subscription.event).or_default().push((subscription, handler));
+
+        subscription
+    }
+
+    /// Stops a subscription. Returns false if it had already stopped.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        let Some(handlers) = self.subscribers.get_mut(&subscription.event) else {
+            return false;
+        };
+
+        let count = handlers.len();
+        handlers.retain(|(other, _)| *other != subscription);
+
+        handlers.len() < count
+    }
+
+    /// Sends an event to its subscribers straight away.
+    pub fn publish<E: Any>(&mut self, event: E) {
+        self.deliver(TypeId::of::<E>(), &event);
+    }
+
+    /// Holds an event until the next `flush`.
+    pub fn queue<E: Any>(&mut self, event: E) {
+        self.queued.push_back((TypeId::of::<E>(), Box::new(event)));
+    }
+
+    /// Sends every queued event, oldest first.
+    pub fn flush(&mut self) {
+        while let Some((event_type, event)) = self.queued.pop_front() {
+            self.deliver(event_type, event.as_ref());
         }
     }
 
-    pub fn register(&mut self, update: fn(&str, &str)){
-        &mut self.subscribers.push(update);
+    fn deliver(&mut self, event_type: TypeId, event: &dyn Any) {
+        if let Some(handlers) = self.subscribers.get_mut(&event_type) {
+            for (_, handler) in handlers {
+                handler(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl MessageBus {
+    /// How many subscribers there are for events of type `E`.
+    pub fn subscriber_count<E: Any>(&self) -> usize {
+        self.subscribers.get(&TypeId::of::<E>()).map_or(0, Vec::len)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Debug, PartialEq)]
+    struct Spawned(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Destroyed(u32);
+
+    /// Subscribes a handler that writes down every event it gets, labelled.
+    fn log<E: Any + std::fmt::Debug>(bus: &mut MessageBus, log: &Rc<RefCell<Vec<String>>>, label: &'static str) -> Subscription {
+        let log = log.clone();
+        bus.subscribe(move |event: &E| log.borrow_mut().push(format!("{} {:?}", label, event)))
     }
 
-    pub fn unregister(&mut self, update: fn(&str, &str)){
-        //TODO: Remove subscriber...
+    #[test]
+    /// # test_events_go_to_their_type
+    fn test_events_go_to_their_type() {
+        let mut bus = MessageBus::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        log::<Spawned>(&mut bus, &events, "a");
+        log::<Destroyed>(&mut bus, &events, "b");
+
+        bus.publish(Destroyed(1));
+        bus.publish(Spawned(2));
 
+        assert_eq!(*events.borrow(), vec!["b Destroyed(1)", "a Spawned(2)"]);
+        assert_eq!(bus.subscriber_count::<Spawned>(), 1);
+        assert_eq!(bus.subscriber_count::<u32>(), 0);
     }
 
-    pub fn send_message(&self) {
-        //TODO: Sends a message to subscribers...
-        &self.subscribers.iter().for_each(|subscriber| subscriber("Hello", "World"));
+    #[test]
+    /// # test_queued_events_wait_for_flush
+    /// Queued events arrive in the order they were queued, whatever their type, and only once the
+    /// bus is flushed. Events published straight away don't wait for them.
+    fn test_queued_events_wait_for_flush() {
+        let mut bus = MessageBus::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        log::<Spawned>(&mut bus, &events, "first");
+        log::<Destroyed>(&mut bus, &events, "first");
+        log::<Spawned>(&mut bus, &events, "second");
+
+        bus.queue(Spawned(1));
+        bus.queue(Destroyed(2));
+        bus.publish(Spawned(3));
+        assert_eq!(*events.borrow(), vec!["first Spawned(3)", "second Spawned(3)"]);
+
+        bus.flush();
+        assert_eq!(events.borrow()[2..], ["first Spawned(1)", "second Spawned(1)", "first Destroyed(2)"]);
+
+        bus.flush();
+        assert_eq!(events.borrow().len(), 5, "each is only delivered once");
     }
 
-    pub fn get_message(&self)  {
-        //TODO: Receive message from subscribers...
+    #[test]
+    /// # test_unsubscribe
+    fn test_unsubscribe() {
+        let mut bus = MessageBus::new();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let first = log::<Spawned>(&mut bus, &events, "first");
+        log::<Spawned>(&mut bus, &events, "second");
+
+        bus.queue(Spawned(1));
+        assert!(bus.unsubscribe(first));
+        assert!(!bus.unsubscribe(first));
+        bus.flush();
 
+        assert_eq!(*events.borrow(), vec!["second Spawned(1)"]);
+        assert_eq!(bus.subscriber_count::<Spawned>(), 1);
     }
 }
@@ -1 +1,6 @@
-mod message_bus;
\ No newline at end of file
+pub mod message_bus;
+pub mod assets;
+pub mod noise;
+pub mod settings;
+pub mod logging;
+pub mod profiling;
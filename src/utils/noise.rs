@@ -0,0 +1,145 @@
+use crate::types::geometry::Vector3D;
+
+/// # hash
+/// Mixes a seed and a 2D lattice point into a well spread 32 bit value. The same inputs always
+/// give the same value on every machine, so anything generated from it is reproducible.
+pub fn hash(seed: u32, x: i32, y: i32) -> u32 {
+    let mut h = seed
+        .wrapping_mul(0x9E37_79B9)
+        ^ (x as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (y as u32).wrapping_mul(0xC2B2_AE35);
+
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7FEB_352D);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846C_A68B);
+    h ^ (h >> 16)
+}
+
+/// A value from 0.0 to 1.0 for a lattice point.
+pub fn lattice_value(seed: u32, x: i32, y: i32) -> f32 {
+    (hash(seed, x, y) >> 8) as f32 / (1 << 24) as f32
+}
+
+/// # value_noise
+/// Smooth noise from 0.0 to 1.0 with features about one unit across, made by smoothly blending
+/// the values of the surrounding lattice points.
+pub fn value_noise(seed: u32, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (cell_x, cell_y) = (x0 as i32, y0 as i32);
+
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (tx, ty) = (smooth(x - x0), smooth(y - y0));
+
+    let top = lerp(lattice_value(seed, cell_x, cell_y), lattice_value(seed, cell_x + 1, cell_y), tx);
+    let bottom = lerp(lattice_value(seed, cell_x, cell_y + 1), lattice_value(seed, cell_x + 1, cell_y + 1), tx);
+
+    lerp(top, bottom, ty)
+}
+
+/// # fractal_noise
+/// Several octaves of `value_noise`, each twice as detailed and half as strong as the one before,
+/// scaled back to 0.0 to 1.0. More octaves give rougher results.
+pub fn fractal_noise(seed: u32, x: f32, y: f32, octaves: u32) -> f32 {
+    let mut total = 0.0;
+    let mut strength = 1.0;
+    let mut frequency = 1.0;
+    let mut max = 0.0;
+
+    for octave in 0..octaves.max(1) {
+        total += value_noise(seed.wrapping_add(octave), x * frequency, y * frequency) * strength;
+        max += strength;
+        strength *= 0.5;
+        frequency *= 2.0;
+    }
+
+    total / max
+}
+
+/// # Random
+/// A reproducible stream of random numbers from a seed, for anything that has to come out the same
+/// every time, such as in a replay.
+#[derive(Debug, Clone)]
+pub struct Random {
+    seed: u32,
+    index: i32,
+}
+
+impl Random {
+    pub fn new(seed: u32) -> Self {
+        Self { seed, index: 0 }
+    }
+
+    /// The next number, from 0.0 up to 1.0.
+    pub fn value(&mut self) -> f32 {
+        let value = lattice_value(self.seed, self.index, 0);
+        self.index = self.index.wrapping_add(1);
+
+        value
+    }
+
+    /// The next number between `min` and `max`.
+    pub fn range(&mut self, [min, max]: [f32; 2]) -> f32 {
+        min + self.value() * (max - min)
+    }
+
+    /// A direction evenly spread over every way there is to point.
+    pub fn direction(&mut self) -> Vector3D {
+        let y = self.range([-1.0, 1.0]);
+        let angle = self.value() * std::f32::consts::TAU;
+        let across = (1.0 - y * y).sqrt();
+
+        Vector3D { x: angle.cos() * across, y, z: angle.sin() * across }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_noise_is_reproducible
+    fn test_noise_is_reproducible() {
+        assert_eq!(fractal_noise(7, 1.3, -4.2, 4), fractal_noise(7, 1.3, -4.2, 4));
+        assert_ne!(fractal_noise(7, 1.3, -4.2, 4), fractal_noise(8, 1.3, -4.2, 4));
+    }
+
+    #[test]
+    /// # test_noise_range
+    fn test_noise_range() {
+        for i in 0..1000 {
+            let (x, y) = (i as f32 * 0.37 - 100.0, i as f32 * -0.21 + 50.0);
+
+            let value = fractal_noise(3, x, y, 5);
+            assert!((0.0..=1.0).contains(&value), "{} at ({}, {})", value, x, y);
+        }
+    }
+
+    #[test]
+    /// # test_random_is_reproducible
+    fn test_random_is_reproducible() {
+        let (mut first, mut second) = (Random::new(5), Random::new(5));
+
+        for _ in 0..100 {
+            let value = first.range([2.0, 3.0]);
+
+            assert_eq!(value, second.range([2.0, 3.0]));
+            assert!((2.0..=3.0).contains(&value));
+        }
+
+        assert!((first.direction().length() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    /// # test_value_noise_passes_through_lattice
+    /// Noise is continuous, matching the lattice values at whole coordinates.
+    fn test_value_noise_passes_through_lattice() {
+        assert_eq!(value_noise(1, 2.0, 3.0), lattice_value(1, 2, 3));
+        assert!((value_noise(1, 2.0001, 3.0) - value_noise(1, 1.9999, 3.0)).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::time::Duration;
+#[cfg(any(debug_assertions, feature = "profiling"))]
+use std::time::Instant;
+
+/// Whether the timing scopes and counters are compiled in. They always are in debug builds, and
+/// in release builds with the `profiling` feature.
+pub const ENABLED: bool = cfg!(any(debug_assertions, feature = "profiling"));
+
+/// # Timing
+/// Parts of a frame that are timed. They nest, `Render` covers all of drawing the level including
+/// the transforms and projections done for it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    Render,
+    Transform,
+    Projection,
+}
+
+/// # Counter
+/// Things counted over a frame. Split screen and picture in picture draw the level more than once
+/// a frame, the counts cover every view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Counter {
+    /// Times the level was drawn, once for each view of it.
+    Views,
+    MeshesDrawn,
+    Faces,
+    /// Face edges, some of which are shared with other faces and drawn twice.
+    Edges,
+    /// Lines sent to the backend for everything, including text and the HUD.
+    Segments,
+}
+
+/// # FrameStats
+/// What was timed and counted over a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameStats {
+    timings: [Duration; 3],
+    counters: [usize; 5],
+}
+
+impl FrameStats {
+    pub fn time(&self, timing: Timing) -> Duration {
+        self.timings[timing as usize]
+    }
+
+    pub fn count(&self, counter: Counter) -> usize {
+        self.counters[counter as usize]
+    }
+}
+
+#[cfg(test)]
+impl FrameStats {
+    /// The stats with `time` spent on `timing`, for testing what's shown without timing anything.
+    pub fn with_time(mut self, timing: Timing, time: Duration) -> Self {
+        self.timings[timing as usize] = time;
+        self
+    }
+
+    pub fn with_count(mut self, counter: Counter, count: usize) -> Self {
+        self.counters[counter as usize] = count;
+        self
+    }
+}
+
+thread_local! {
+    /// The frame being measured, everything's drawn on the one thread.
+    static CURRENT: RefCell<FrameStats> = RefCell::new(FrameStats::default());
+}
+
+/// The stats for the frame so far, starting the next one from nothing.
+pub fn take_frame() -> FrameStats {
+    CURRENT.with(|current| current.take())
+}
+
+#[cfg(any(debug_assertions, feature = "profiling"))]
+pub fn add_time(timing: Timing, time: Duration) {
+    CURRENT.with(|current| current.borrow_mut().timings[timing as usize] += time);
+}
+
+#[cfg(any(debug_assertions, feature = "profiling"))]
+pub fn add_count(counter: Counter, count: usize) {
+    CURRENT.with(|current| current.borrow_mut().counters[counter as usize] += count);
+}
+
+/// # ScopeTimer
+/// Adds the time from when it's started to when it's dropped to a timing. Use `profile_scope!`
+/// rather than this so it compiles out when profiling's disabled.
+#[cfg(any(debug_assertions, feature = "profiling"))]
+pub struct ScopeTimer {
+    timing: Timing,
+    start: Instant,
+}
+
+#[cfg(any(debug_assertions, feature = "profiling"))]
+impl ScopeTimer {
+    pub fn start(timing: Timing) -> Self {
+        Self { timing, start: Instant::now() }
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "profiling"))]
+impl Drop for ScopeTimer {
+    fn drop(&mut self) {
+        add_time(self.timing, self.start.elapsed());
+    }
+}
+
+/// Times the rest of the enclosing block.
+#[macro_export]
+macro_rules! profile_scope {
+    ($timing:expr) => {
+        #[cfg(any(debug_assertions, feature = "profiling"))]
+        let _scope = $crate::utils::profiling::ScopeTimer::start($timing);
+        // Never called, it only keeps what's passed in from looking unused
+        #[cfg(not(any(debug_assertions, feature = "profiling")))]
+        let _ = || $timing;
+    };
+}
+
+/// Adds to a counter for the frame.
+#[macro_export]
+macro_rules! profile_count {
+    ($counter:expr, $count:expr) => {
+        #[cfg(any(debug_assertions, feature = "profiling"))]
+        $crate::utils::profiling::add_count($counter, $count);
+        #[cfg(not(any(debug_assertions, feature = "profiling")))]
+        let _ = || ($counter, $count);
+    };
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_frame_stats
+    /// Scopes and counters add up over a frame, and taking it starts the next from nothing.
+    fn test_frame_stats() {
+        take_frame();
+
+        for _ in 0..2 {
+            profile_scope!(Timing::Transform);
+            profile_count!(Counter::Faces, 3);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let frame = take_frame();
+        assert_eq!(frame.count(Counter::Faces), 6);
+        assert!(frame.time(Timing::Transform) >= Duration::from_millis(2));
+        assert_eq!(frame.time(Timing::Projection), Duration::ZERO);
+        assert_eq!(take_frame(), FrameStats::default());
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::rendering::crt::CrtSettings;
+use crate::rendering::depth_cue::DepthCue;
+
+/// Where the player's settings are saved, relative to the working directory.
+pub const SETTINGS_FILE: &str = "settings.json";
+
+/// # Settings
+/// Player settings other than the input bindings, changed from the options screen.
+///
+/// Every field has a default so settings files saved by older versions still load.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub crt: CrtSettings,
+    pub depth_cue: DepthCue,
+}
+
+impl Settings {
+    /// Loads the settings from the given file, falling back to the defaults if there isn't one yet
+    /// or it can't be read.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// # test_missing_fields_get_defaults
+    fn test_missing_fields_get_defaults() {
+        let settings: Settings = serde_json::from_str("{\"crt\": {\"glow\": false}}").unwrap();
+
+        assert!(!settings.crt.glow);
+        assert_eq!(settings.crt.phosphor, CrtSettings::default().phosphor);
+    }
+}